@@ -6,9 +6,15 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
-    cargo_emit::rerun_if_changed!("extern/icmp_dgram.c", "extern/icmp_dgram.h",);
+    cargo_emit::rerun_if_changed!(
+        "extern/icmp_dgram.c",
+        "extern/icmp_dgram.h",
+        "extern/udp_dgram.c",
+        "extern/udp_dgram.h",
+    );
 
     cc::Build::new().file("extern/icmp_dgram.c").compile("libicmp_dgram.a");
+    cc::Build::new().file("extern/udp_dgram.c").compile("libudp_dgram.a");
 
     let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
     bindgen::Builder::default()
@@ -17,4 +23,10 @@ fn main() {
         .expect("Unable to generate bindings.") // -> bindings
         .write_to_file(out_path.join("bindings.rs"))
         .expect("Could not write bindings.");
+    bindgen::Builder::default()
+        .header("extern/udp_dgram.h")
+        .generate()
+        .expect("Unable to generate bindings.") // -> bindings
+        .write_to_file(out_path.join("udp_bindings.rs"))
+        .expect("Could not write bindings.");
 }
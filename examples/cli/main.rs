@@ -1,6 +1,9 @@
-use ping_fox::{PingFoxConfig, PingReceive, PingReceiveData, SocketType};
+#[cfg(feature = "serde")]
+use ping_fox::output::{CsvWriter, NdjsonWriter};
+use ping_fox::{PingFoxConfig, PingReceive, PingReceiveData, ProbeKind, SocketType};
+#[cfg(feature = "serde")]
+use std::io::Stdout;
 use std::net::Ipv4Addr;
-use std::sync::{Arc, Condvar, Mutex};
 use std::time::Duration;
 
 type GenericError = Box<dyn std::error::Error + Send + Sync + 'static>;
@@ -26,35 +29,41 @@ impl std::error::Error for Error {
     }
 }
 
-#[derive(Clone)]
-struct StopCondition {
-    condition: Arc<(Mutex<bool>, Condvar)>,
+enum Output {
+    Text,
+    #[cfg(feature = "serde")]
+    Ndjson(NdjsonWriter<Stdout>),
+    #[cfg(feature = "serde")]
+    Csv(CsvWriter<Stdout>),
 }
 
-impl StopCondition {
-    pub(crate) fn new() -> Self {
-        Self { condition: Arc::new((Mutex::new(false), Condvar::new())) }
-    }
-
-    pub(crate) fn set_should_stop(&self) {
-        let (lock, cvar) = &*self.condition;
-        let mut should_stop = lock.lock().unwrap();
-        *should_stop = true;
-        cvar.notify_all();
-    }
-
-    #[allow(dead_code)]
-    pub(crate) fn get_should_stop(&self) -> bool {
-        let (lock, _) = &*self.condition;
-        let should_stop = lock.lock().unwrap();
-        *should_stop
-    }
-
-    pub(crate) fn wait_timeout(&self, timeout: Duration) -> bool {
-        let (lock, cvar) = &*self.condition;
-        let guard = lock.lock().unwrap();
-        let (should_stop, _) = cvar.wait_timeout(guard, timeout).unwrap();
-        *should_stop
+impl Output {
+    fn write(&mut self, ping_receive: &PingReceive) -> std::io::Result<()> {
+        match self {
+            Output::Text => {
+                match ping_receive {
+                    PingReceive::Data(PingReceiveData {
+                        package_size,
+                        ip_addr,
+                        ttl,
+                        sequence_number,
+                        ping_duration,
+                        ..
+                    }) => {
+                        println!("{package_size} bytes from {ip_addr}: icmp_seq={sequence_number} ttl={ttl} time={ping_duration:?}",);
+                    }
+                    PingReceive::Timeout => {
+                        println!("receive timed out");
+                    }
+                    PingReceive::Closed => {}
+                }
+                Ok(())
+            }
+            #[cfg(feature = "serde")]
+            Output::Ndjson(writer) => writer.write(ping_receive),
+            #[cfg(feature = "serde")]
+            Output::Csv(writer) => writer.write(ping_receive),
+        }
     }
 }
 
@@ -65,6 +74,22 @@ struct Args {
     /// stop after <count> sent ping messages
     count: u16,
 
+    #[argh(option)]
+    /// probe <tcp-port> with TCP connection attempts instead of ICMP echo requests
+    tcp_port: Option<u16>,
+
+    #[argh(option)]
+    /// probe with UDP datagrams to <udp-port> and up instead of ICMP echo requests
+    udp_port: Option<u16>,
+
+    #[argh(switch)]
+    /// print results as newline-delimited JSON (needs the serde feature)
+    json: bool,
+
+    #[argh(switch)]
+    /// print results as CSV (needs the serde feature)
+    csv: bool,
+
     #[argh(positional)]
     /// IP address
     first_address: String,
@@ -87,29 +112,55 @@ fn main() -> Result<(), GenericError> {
         addresses.push(address.parse::<Ipv4Addr>()?);
     }
 
-    let config = PingFoxConfig { timeout: Duration::from_secs(1), channel_size: 8, socket_type: SocketType::DGRAM };
-
-    let (mut ping_sender, mut ping_receiver) = ping_fox::create(&config)?;
+    let probe_kind = match (args.tcp_port, args.udp_port) {
+        (Some(_), Some(_)) => {
+            return Err(Error { message: "--tcp-port and --udp-port are mutually exclusive".to_owned() }.into())
+        }
+        (Some(port), None) => ProbeKind::Tcp { port },
+        (None, Some(port)) => ProbeKind::Udp { port },
+        (None, None) => ProbeKind::Icmp,
+    };
+    let mut output = match (args.json, args.csv) {
+        (true, true) => return Err(Error { message: "--json and --csv are mutually exclusive".to_owned() }.into()),
+        #[cfg(feature = "serde")]
+        (true, false) => Output::Ndjson(NdjsonWriter::new(std::io::stdout())),
+        #[cfg(feature = "serde")]
+        (false, true) => Output::Csv(CsvWriter::new(std::io::stdout())),
+        #[cfg(not(feature = "serde"))]
+        (true, false) | (false, true) => {
+            return Err(Error { message: "--json and --csv need the serde feature".to_owned() }.into())
+        }
+        (false, false) => Output::Text,
+    };
+    let config = PingFoxConfig {
+        timeout: Duration::from_secs(1),
+        channel_size: 8,
+        socket_type: SocketType::DGRAM,
+        probe_kind,
+        ..PingFoxConfig::default()
+    };
+
+    let (ping_sender, mut ping_receiver) = ping_fox::create(&config)?;
     let (tx, rx) = std::sync::mpsc::sync_channel(8);
-    let stop_condition_1 = StopCondition::new();
-    let stop_condition_2 = stop_condition_1.clone();
+    let shutdown_handle = ping_sender.shutdown_handle();
+    let sender_shutdown_handle = shutdown_handle.clone();
 
     let thrd2 = std::thread::spawn(move || 'outer: loop {
         for address in &addresses {
             match ping_sender.send_to(*address) {
                 Ok(token) => {
-                    if let Err(e) = tx.send(token) {
-                        println!("ERROR: {:?}", e);
+                    if tx.send(token).is_err() {
+                        break 'outer;
                     }
                 }
+                Err(_) if sender_shutdown_handle.is_shut_down() => break 'outer,
                 Err(e) => {
                     println!("ERROR: {:?}", e);
                     break 'outer;
                 }
             }
         }
-        let should_stop: bool = stop_condition_1.wait_timeout(Duration::from_secs(1));
-        if should_stop {
+        if sender_shutdown_handle.wait_timeout(Duration::from_secs(1)) {
             break 'outer;
         }
     });
@@ -125,12 +176,12 @@ fn main() -> Result<(), GenericError> {
         };
         let ping_output = ping_receiver.receive(token);
         match ping_output {
-            Ok(PingReceive::Data(PingReceiveData { package_size, ip_addr, ttl, sequence_number, ping_duration })) => {
-                println!("{package_size} bytes from {ip_addr}: icmp_seq={sequence_number} ttl={ttl} time={ping_duration:?}",);
-                i += 1;
-            }
-            Ok(PingReceive::Timeout) => {
-                println!("receive timed out");
+            Ok(PingReceive::Closed) => break 'outer,
+            Ok(ping_receive) => {
+                if matches!(ping_receive, PingReceive::Data(_)) {
+                    i += 1;
+                }
+                output.write(&ping_receive)?;
             }
             Err(e) => {
                 println!("ERROR: {:?}", e);
@@ -140,7 +191,9 @@ fn main() -> Result<(), GenericError> {
             break 'outer;
         }
     }
-    stop_condition_2.set_should_stop();
+    // Wakes up the sender, which also stops once it cannot hand over its tokens anymore.
+    shutdown_handle.shutdown();
+    drop(rx);
     let join_result = thrd2.join();
     if let Err(e) = join_result {
         println!("ERROR: {:?}", e);
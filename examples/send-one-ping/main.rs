@@ -1,4 +1,4 @@
-use ping_fox::{PingFoxConfig, PingReceive, PingReceiveData, SocketType};
+use ping_fox::{PingFoxConfig, PingReceive, PingReceiveData, ProbeKind, SocketType};
 use std::net::Ipv4Addr;
 use std::time::Duration;
 
@@ -44,12 +44,19 @@ fn main() -> Result<(), GenericError> {
     let address = args.address.parse::<Ipv4Addr>()?;
     let timeout = Duration::from_secs(1);
 
-    let config = PingFoxConfig { timeout, channel_size: 1, socket_type: SocketType::DGRAM };
+    let config = PingFoxConfig {
+        timeout,
+        channel_size: 1,
+        socket_type: SocketType::DGRAM,
+        probe_kind: ProbeKind::Icmp,
+        ..PingFoxConfig::default()
+    };
 
-    let (mut ping_sender, mut ping_receiver) = ping_fox::create(&config)?;
+    let (ping_sender, mut ping_receiver) = ping_fox::create(&config)?;
     let token = ping_sender.send_to(address)?;
     let ping_response = ping_receiver.receive(token);
-    if let PingReceive::Data(PingReceiveData { package_size, ip_addr, ttl, sequence_number, ping_duration }) = ping_response?
+    if let PingReceive::Data(PingReceiveData { package_size, ip_addr, ttl, sequence_number, ping_duration, .. }) =
+        ping_response?
     {
         println!("{package_size} bytes from {ip_addr}: icmp_seq={sequence_number} ttl={ttl} time={ping_duration:?}",);
     }
@@ -0,0 +1,99 @@
+#include "udp_dgram.h"
+
+#include <arpa/inet.h>
+#include <errno.h>
+#include <linux/errqueue.h>
+#include <string.h>
+#include <sys/socket.h>
+
+int udp_enable_recverr(int socket) {
+  int yes = 1;
+  if (0 != setsockopt(socket, IPPROTO_IP, IP_RECVERR, &yes, sizeof(yes))) {
+    return -1;
+  }
+  if (0 != setsockopt(socket, IPPROTO_IP, IP_RECVTTL, &yes, sizeof(yes))) {
+    return -1;
+  }
+  return 0;
+}
+
+static int recv_msg(int socket, UdpData *data, int flags) {
+  struct iovec iov[1] = {{data->data_buffer, data->data_buffer_size}};
+  struct sockaddr_in address;
+  // Large enough for a TTL and an extended error with its offender.
+  uint8_t ctrl_data_buffer[CMSG_SPACE(sizeof(int)) +
+                           CMSG_SPACE(sizeof(struct sock_extended_err) +
+                                      sizeof(struct sockaddr_in))];
+
+  struct msghdr header = {
+      .msg_name = &address,
+      .msg_namelen = sizeof(address),
+      .msg_iov = iov,
+      .msg_iovlen = 1,
+      .msg_control = ctrl_data_buffer,
+      .msg_controllen = sizeof(ctrl_data_buffer),
+  };
+
+  ssize_t n_bytes_received = recvmsg(socket, &header, flags);
+  if (n_bytes_received < 0) {
+    return -1;
+  }
+  data->n_data_bytes_received = n_bytes_received;
+
+  if (header.msg_flags & MSG_CTRUNC) {
+    // error: control data truncated
+    return -3;
+  }
+
+  data->is_icmp_error = false;
+  data->ttl = 0;
+  struct cmsghdr *cmsg = CMSG_FIRSTHDR(&header);
+  for (; cmsg; cmsg = CMSG_NXTHDR(&header, cmsg)) {
+    if (cmsg->cmsg_level != IPPROTO_IP) {
+      continue;
+    }
+    if (cmsg->cmsg_type == IP_TTL) {
+      data->ttl = *(uint8_t *)CMSG_DATA(cmsg);
+    } else if (cmsg->cmsg_type == IP_RECVERR) {
+      struct sock_extended_err err;
+      memcpy(&err, CMSG_DATA(cmsg), sizeof(err));
+      if (err.ee_origin != SO_EE_ORIGIN_ICMP) {
+        // error: not caused by an ICMP message
+        return -5;
+      }
+      data->is_icmp_error = true;
+      data->icmp_type = err.ee_type;
+      data->icmp_code = err.ee_code;
+    }
+  }
+
+  // On the error queue the kernel fills in the destination address and port
+  // of the IP and UDP headers quoted in the ICMP error.
+  data->port = ntohs(address.sin_port);
+  if (!inet_ntop(AF_INET, &address.sin_addr, (char *)data->addr_str,
+                 INET6_ADDRSTRLEN)) {
+    return -6;
+  }
+
+  return 0;
+}
+
+int udp_recv_from(int socket, UdpData *data) {
+  for (;;) {
+    // Queued errors are not reported by an ordinary recvmsg, so look at the
+    // error queue first and only then block for a datagram.
+    int result = recv_msg(socket, data, MSG_ERRQUEUE | MSG_DONTWAIT);
+    if (result != -1 || errno != EAGAIN) {
+      return result;
+    }
+    result = recv_msg(socket, data, 0);
+    if (result == -1 && errno != EAGAIN && errno != EWOULDBLOCK &&
+        errno != EINTR) {
+      // A pending socket error (e.g. ECONNREFUSED) wakes up the blocking
+      // recvmsg and is cleared by it. The ICMP error which caused it is on
+      // the error queue.
+      continue;
+    }
+    return result;
+  }
+}
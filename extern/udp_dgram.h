@@ -0,0 +1,36 @@
+#include <netinet/in.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct UdpData {
+  uint8_t *data_buffer;
+  uint64_t data_buffer_size;
+  size_t n_data_bytes_received;
+  uint8_t ttl;
+  bool is_icmp_error;
+  uint8_t icmp_type;
+  uint8_t icmp_code;
+  uint16_t port;
+  uint8_t addr_str[INET6_ADDRSTRLEN];
+} UdpData;
+
+/**
+ * @brief Enable the reception of ICMP errors and of the TTL on a UDP socket.
+ * @param socket The socket file descriptor.
+ * @return 0 on success. Otherwise, -1 is returned and errno is set.
+ */
+int udp_enable_recverr(int socket);
+
+/**
+ * @brief Receive a datagram or a queued ICMP error from a UDP socket.
+ * @param socket The socket file descriptor.
+ * @param data Points to an UdpData structure acting as the buffer to store the
+ * incoming message and its metadata. For a datagram, port and addr_str
+ * describe its source. For an ICMP error, port and addr_str are taken from
+ * the UDP and IP headers quoted in the error, i.e., they describe the
+ * destination of the datagram which caused the error.
+ * @return 0 on success. If the underlying recvmsg fails, -1 is returned and
+ * errno is set. Otherwise, another negative number will be returned.
+ */
+int udp_recv_from(int socket, UdpData *data);
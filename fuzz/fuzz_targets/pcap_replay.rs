@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ping_fox::testing::{PcapReplaySocket, ReplayTiming};
+use ping_fox::TSocket;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(socket) = PcapReplaySocket::from_reader(data, ReplayTiming::AsFastAsPossible) {
+        let mut buf = [0u8; 128];
+        while socket.recv_from(&mut buf).is_ok() {}
+    }
+});
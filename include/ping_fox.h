@@ -0,0 +1,273 @@
+#ifndef PING_FOX_H
+#define PING_FOX_H
+
+/* Generated by cbindgen from src/capi.rs, see scripts/test_capi.sh. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of a function of the C ABI.
+ */
+typedef enum PingFoxError {
+  /**
+   * Success.
+   */
+  PING_FOX_ERROR_OK = 0,
+  /**
+   * A pointer was null or an argument was invalid, e.g. an address which is not IPv4.
+   */
+  PING_FOX_ERROR_INVALID_ARGUMENT,
+  /**
+   * Sending would exceed the maximum number of pings in flight, see [`WouldExceedInFlight`].
+   */
+  PING_FOX_ERROR_WOULD_EXCEED_IN_FLIGHT,
+  /**
+   * A received message could not be parsed, see [`ParseError`].
+   */
+  PING_FOX_ERROR_PARSE,
+  /**
+   * The socket could not be created for lack of privileges.
+   */
+  PING_FOX_ERROR_PERMISSION_DENIED,
+  /**
+   * Any other error of the operating system.
+   */
+  PING_FOX_ERROR_IO,
+  /**
+   * The sender and the receiver have been shut down.
+   */
+  PING_FOX_ERROR_SHUT_DOWN,
+  /**
+   * Any other error.
+   */
+  PING_FOX_ERROR_OTHER,
+  /**
+   * A panic was caught. This is a bug in ping-fox.
+   */
+  PING_FOX_ERROR_PANIC,
+} PingFoxError;
+
+/**
+ * Outcome of [`ping_fox_receive`], see [`PingReceive`].
+ */
+typedef enum PingFoxOutcome {
+  /**
+   * An echo reply was received.
+   */
+  PING_FOX_OUTCOME_DATA,
+  /**
+   * Nothing was received within the timeout.
+   */
+  PING_FOX_OUTCOME_TIMEOUT,
+  /**
+   * The sender and the receiver have been shut down.
+   */
+  PING_FOX_OUTCOME_CLOSED,
+} PingFoxOutcome;
+
+/**
+ * Socket type of a [`PingFoxConfig`], see [`SocketType`].
+ */
+typedef enum PingFoxSocketType {
+  /**
+   * Datagram socket, usable without elevated privileges.
+   */
+  PING_FOX_SOCKET_TYPE_DGRAM,
+  /**
+   * Raw socket, which needs elevated privileges.
+   */
+  PING_FOX_SOCKET_TYPE_RAW,
+} PingFoxSocketType;
+
+/**
+ * The ping-fox configuration structure.
+ */
+typedef struct PingFoxConfig PingFoxConfig;
+
+/**
+ * Opaque handle of a [`PingReceiver`].
+ */
+typedef struct PingFoxReceiver PingFoxReceiver;
+
+/**
+ * Opaque handle of a [`PingSender`].
+ */
+typedef struct PingFoxSender PingFoxSender;
+
+/**
+ * Result of [`ping_fox_receive`]. Only `outcome` is set unless it is `Data`.
+ */
+typedef struct PingFoxReply {
+  /**
+   * What was received.
+   */
+  enum PingFoxOutcome outcome;
+  /**
+   * The IPv4 address of the host which sent the reply, in network byte order.
+   */
+  uint8_t address[4];
+  /**
+   * The sequence number of the reply.
+   */
+  uint16_t sequence_number;
+  /**
+   * The TTL of the reply, or 0 if it cannot be observed.
+   */
+  uint8_t ttl;
+  /**
+   * The size of the payload of the reply.
+   */
+  size_t package_size;
+  /**
+   * The round-trip time in microseconds.
+   */
+  uint64_t rtt_us;
+} PingFoxReply;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Returns the message of the last error on the calling thread, or null if there was none. The
+ * string is valid until the next call of a function of ping-fox on the same thread.
+ */
+const char *ping_fox_last_error_message(void);
+
+/**
+ * Returns a new configuration with the defaults of [`PingFoxConfig::default`]. It is freed with
+ * [`ping_fox_config_free`].
+ */
+struct PingFoxConfig *ping_fox_config_new(void);
+
+/**
+ * Frees a configuration. Null is ignored.
+ *
+ * # Safety
+ *
+ * `config` must be null or a configuration returned by [`ping_fox_config_new`] which has not
+ * been freed.
+ */
+void ping_fox_config_free(struct PingFoxConfig *config);
+
+/**
+ * Sets the socket type, see [`PingFoxConfig::socket_type`].
+ *
+ * # Safety
+ *
+ * `config` must be a configuration returned by [`ping_fox_config_new`] or null.
+ */
+enum PingFoxError ping_fox_config_set_socket_type(struct PingFoxConfig *config,
+                                                  enum PingFoxSocketType socket_type);
+
+/**
+ * Sets the timeout in milliseconds, see [`PingFoxConfig::timeout`].
+ *
+ * # Safety
+ *
+ * `config` must be a configuration returned by [`ping_fox_config_new`] or null.
+ */
+enum PingFoxError ping_fox_config_set_timeout_ms(struct PingFoxConfig *config, uint64_t timeout_ms);
+
+/**
+ * Sets the maximum number of pings in flight, see [`PingFoxConfig::channel_size`].
+ *
+ * # Safety
+ *
+ * `config` must be a configuration returned by [`ping_fox_config_new`] or null.
+ */
+enum PingFoxError ping_fox_config_set_max_in_flight(struct PingFoxConfig *config,
+                                                    size_t max_in_flight);
+
+/**
+ * Sets the TTL of echo requests, see [`PingFoxConfig::ttl`]. 0 restores the system default.
+ *
+ * # Safety
+ *
+ * `config` must be a configuration returned by [`ping_fox_config_new`] or null.
+ */
+enum PingFoxError ping_fox_config_set_ttl(struct PingFoxConfig *config, uint8_t ttl);
+
+/**
+ * Sets the number of payload bytes, see [`PingFoxConfig::payload_size`].
+ *
+ * # Safety
+ *
+ * `config` must be a configuration returned by [`ping_fox_config_new`] or null.
+ */
+enum PingFoxError ping_fox_config_set_payload_size(struct PingFoxConfig *config,
+                                                   size_t payload_size);
+
+/**
+ * Creates a sender and a receiver as configured, see [`crate::create`]. On success, they are
+ * stored in `sender` and `receiver` and are freed with [`ping_fox_sender_free`] and
+ * [`ping_fox_receiver_free`].
+ *
+ * # Safety
+ *
+ * `config` must be a configuration returned by [`ping_fox_config_new`] or null. `sender` and
+ * `receiver` must be valid for writes or null.
+ */
+enum PingFoxError ping_fox_create(const struct PingFoxConfig *config,
+                                  struct PingFoxSender **sender,
+                                  struct PingFoxReceiver **receiver);
+
+/**
+ * Sends a ping to `address`, an IPv4 address in dotted decimal notation like "127.0.0.1". See
+ * [`PingSender::send_to`]. The sender may be used from several threads at once.
+ *
+ * # Safety
+ *
+ * `sender` must be a sender returned by [`ping_fox_create`] or null. `address` must be a null
+ * terminated string or null.
+ */
+enum PingFoxError ping_fox_send_to(const struct PingFoxSender *sender, const char *address);
+
+/**
+ * Waits for an echo reply and stores it in `reply`, see [`PingReceiver::receive`].
+ *
+ * # Safety
+ *
+ * `receiver` must be a receiver returned by [`ping_fox_create`] or null. It must not be used by
+ * several threads at once. `reply` must be valid for writes or null.
+ */
+enum PingFoxError ping_fox_receive(struct PingFoxReceiver *receiver, struct PingFoxReply *reply);
+
+/**
+ * Shuts down a sender and its receiver, see [`crate::ShutdownHandle::shutdown`]. A receiver
+ * blocked in [`ping_fox_receive`] on another thread returns `Closed` at once.
+ *
+ * # Safety
+ *
+ * `sender` must be a sender returned by [`ping_fox_create`] or null.
+ */
+enum PingFoxError ping_fox_shutdown(const struct PingFoxSender *sender);
+
+/**
+ * Frees a sender. Null is ignored.
+ *
+ * # Safety
+ *
+ * `sender` must be null or a sender returned by [`ping_fox_create`] which has not been freed.
+ */
+void ping_fox_sender_free(struct PingFoxSender *sender);
+
+/**
+ * Frees a receiver. Null is ignored.
+ *
+ * # Safety
+ *
+ * `receiver` must be null or a receiver returned by [`ping_fox_create`] which has not been
+ * freed.
+ */
+void ping_fox_receiver_free(struct PingFoxReceiver *receiver);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PING_FOX_H */
@@ -0,0 +1,37 @@
+#!/bin/sh
+# Checks that include/ping_fox.h matches src/capi.rs, builds ping-fox as a shared and as a static
+# library with the C ABI and runs the C test program linked against each. Needs the cbindgen CLI (`cargo install cbindgen`).
+# With --update-header, the header is rewritten instead of checked.
+set -eu
+
+cd "$(dirname "$0")/.."
+target_dir="${CARGO_TARGET_DIR:-target}"
+
+mkdir -p "$target_dir"
+cbindgen --quiet --config cbindgen.toml --output "$target_dir/ping_fox.h"
+if [ "${1:-}" = "--update-header" ]; then
+    cp "$target_dir/ping_fox.h" include/ping_fox.h
+elif ! diff -u include/ping_fox.h "$target_dir/ping_fox.h"; then
+    echo "include/ping_fox.h is out of date, run $0 --update-header" >&2
+    exit 1
+fi
+
+release_dir="$(realpath "$target_dir/release")"
+cflags="-std=c99 -Wall -Wextra -Werror -Iinclude"
+
+cargo rustc --lib --release --features capi --crate-type cdylib
+cc $cflags tests/capi/test_capi.c -L"$release_dir" -lping_fox -Wl,-rpath,"$release_dir" \
+    -o "$release_dir/test_capi"
+"$release_dir/test_capi"
+
+# The static library needs the system libraries which rustc prints for it.
+native_static_libs="$(cargo rustc --lib --release --features capi --crate-type staticlib --message-format short \
+    -- --print native-static-libs 2>&1 | sed -n 's/^.*native-static-libs: //p' | tail -n 1)"
+if [ -z "$native_static_libs" ]; then
+    echo "rustc did not print the native libraries of the static library" >&2
+    exit 1
+fi
+# shellcheck disable=SC2086
+cc $cflags tests/capi/test_capi.c "$release_dir/libping_fox.a" $native_static_libs \
+    -o "$release_dir/test_capi_static"
+"$release_dir/test_capi_static"
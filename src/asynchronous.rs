@@ -0,0 +1,132 @@
+//! Async sending and receiving, independent of the executor.
+//!
+//! An [`AsyncPingReceiver`] waits for replies with the reactor of `async-io`, so it works under
+//! smol, async-std, `futures::executor::block_on` and any other executor. It waits for the file
+//! descriptor of a [`PingReceiver`], see [`PingReceiver::try_receive`], and therefore supports
+//! RAW and DGRAM sockets, TCP and UDP probes and custom [`TSocket`]s with a file descriptor.
+//! Creating an [`AsyncPingReceiver`] for a custom socket without a file descriptor fails.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! use ping_fox::{PingFoxConfig, PingReceive};
+//!
+//! futures_lite::future::block_on(async {
+//!     let (ping_sender, mut ping_receiver) = ping_fox::asynchronous::create(&PingFoxConfig::default())?;
+//!     let token = ping_sender.send_to("127.0.0.1".parse()?).await?;
+//!     if let PingReceive::Data(data) = ping_receiver.receive(token).await? {
+//!         println!("reply from {} in {:?}", data.ip_addr, data.ping_duration);
+//!     }
+//!     Ok(())
+//! })
+//! # }
+//! ```
+use crate::details::PingResult;
+use crate::{Clock, PingFoxConfig, PingReceive, PingReceiver, PingSender, PingSentToken, ShutdownHandle, TSocket};
+use async_io::{Async, Timer};
+use futures_lite::future;
+use std::io;
+use std::net::Ipv4Addr;
+use std::os::fd::OwnedFd;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Async counterpart of [`PingSender`].
+#[derive(Clone)]
+pub struct AsyncPingSender(PingSender);
+
+impl AsyncPingSender {
+    /// Wraps `ping_sender`.
+    #[must_use]
+    pub fn new(ping_sender: PingSender) -> Self {
+        AsyncPingSender(ping_sender)
+    }
+
+    /// Sends a ping echo message to `ip` like [`PingSender::send_to`]. Sending never waits for
+    /// the receiver, so this completes at once.
+    #[allow(clippy::unused_async)]
+    pub async fn send_to(&self, ip: Ipv4Addr) -> PingResult<PingSentToken> {
+        self.0.send_to(ip)
+    }
+
+    /// Returns a [`ShutdownHandle`] for this sender, its clones and its receiver.
+    #[must_use]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.0.shutdown_handle()
+    }
+}
+
+/// Async counterpart of [`PingReceiver`].
+pub struct AsyncPingReceiver {
+    ping_receiver: PingReceiver,
+    // A duplicate of the file descriptor of `ping_receiver`, registered with the reactor.
+    readiness: Async<OwnedFd>,
+    timeout: Duration,
+}
+
+impl AsyncPingReceiver {
+    /// Wraps `ping_receiver`. Fails if it has no socket with a file descriptor, e.g. for a custom
+    /// [`TSocket`] without one, or if its file descriptor cannot be registered with the reactor.
+    pub fn new(ping_receiver: PingReceiver) -> PingResult<Self> {
+        if !ping_receiver.has_socket_fd() {
+            return Err(unsupported("receivers without a socket file descriptor").into());
+        }
+        let readiness = Async::new(ping_receiver.readiness_fd()?.try_clone_to_owned()?)?;
+        let timeout = ping_receiver.timeout();
+        Ok(AsyncPingReceiver { ping_receiver, readiness, timeout })
+    }
+
+    /// Waits for an echo reply message like [`PingReceiver::receive`], without blocking the
+    /// executor. Returns [`PingReceive::Timeout`] after the timeout without a reply, and
+    /// [`PingReceive::Closed`] after a shutdown.
+    pub async fn receive(&mut self, token: PingSentToken) -> PingResult<PingReceive> {
+        let _ = token;
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(outcome) = self.ping_receiver.try_receive()? {
+                return Ok(outcome);
+            }
+            let readable = async { self.readiness.readable().await.map(|()| true) };
+            let timed_out = async {
+                Timer::at(deadline).await;
+                Ok(false)
+            };
+            if !future::or(readable, timed_out).await? {
+                return Ok(PingReceive::Timeout);
+            }
+        }
+    }
+
+    /// Returns a [`ShutdownHandle`] for this receiver and its senders.
+    #[must_use]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.ping_receiver.shutdown_handle()
+    }
+}
+
+/// Creates an [`AsyncPingSender`] and an [`AsyncPingReceiver`], see [`crate::create`].
+pub fn create(config: &PingFoxConfig) -> PingResult<(AsyncPingSender, AsyncPingReceiver)> {
+    let (ping_sender, ping_receiver) = crate::create(config)?;
+    Ok((AsyncPingSender::new(ping_sender), AsyncPingReceiver::new(ping_receiver)?))
+}
+
+/// Creates an [`AsyncPingSender`] and an [`AsyncPingReceiver`] which send ICMP echo requests
+/// through a custom `socket`, see [`crate::create_with_socket`]. The socket needs a file
+/// descriptor, see [`TSocket::raw_fd`].
+pub fn create_with_socket<S>(
+    socket: S,
+    clock: Arc<dyn Clock>,
+    config: &PingFoxConfig,
+) -> PingResult<(AsyncPingSender, AsyncPingReceiver)>
+where
+    S: TSocket + 'static,
+{
+    let (ping_sender, ping_receiver) = crate::create_with_socket(socket, clock, config);
+    Ok((AsyncPingSender::new(ping_sender), AsyncPingReceiver::new(ping_receiver)?))
+}
+
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{what} are not supported by async receivers"),
+    )
+}
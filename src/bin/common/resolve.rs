@@ -0,0 +1,14 @@
+//! Name resolution shared by the binaries, which ping IPv4 addresses only.
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
+
+/// Resolves `name`, an IPv4 address or a host name, to its first IPv4 address.
+pub fn resolve(name: &str) -> io::Result<Ipv4Addr> {
+    (name, 0)
+        .to_socket_addrs()?
+        .find_map(|addr| match addr {
+            SocketAddr::V4(addr) => Some(*addr.ip()),
+            SocketAddr::V6(_) => None,
+        })
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{name}: no IPv4 address")))
+}
@@ -0,0 +1,411 @@
+//! `fping-fox`: an fping(8) work-alike which pings many hosts at once without root or setuid.
+use ping_fox::{PingFoxConfig, PingReceive, PingReceiveData, PingSentToken};
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::net::{IpAddr, Ipv4Addr};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use resolve::resolve;
+
+#[path = "common/resolve.rs"]
+mod resolve;
+
+type GenericError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+// Exit codes of fping(8).
+const EXIT_ALL_ALIVE: u8 = 0;
+const EXIT_SOME_UNREACHABLE: u8 = 1;
+const EXIT_ADDRESS_NOT_FOUND: u8 = 2;
+const EXIT_INVALID_ARGUMENTS: u8 = 3;
+const EXIT_SYSTEM_FAILURE: u8 = 4;
+
+// Ranges generated with `-g` are limited like in fping(8).
+const MAX_GENERATED_TARGETS: u64 = 131_072;
+
+#[derive(argh::FromArgs)]
+/// fping-fox - send ICMP ECHO_REQUEST to many network hosts in parallel
+struct Args {
+    #[argh(switch, short = 'a')]
+    /// show targets that are alive
+    alive: bool,
+
+    #[argh(switch, short = 'u')]
+    /// show targets that are unreachable
+    unreachable: bool,
+
+    #[argh(switch, short = 'g')]
+    /// generate the targets from a range given as "start end" or as CIDR network, e.g. 192.0.2.0/24
+    generate: bool,
+
+    #[argh(option, short = 'C')]
+    /// send <count> pings to each target and print every RTT, with "-" for no reply
+    count: Option<u32>,
+
+    #[argh(option, short = 'r', default = "3")]
+    /// number of retries for unreachable targets
+    retries: u32,
+
+    #[argh(option, short = 'B', default = "1.5")]
+    /// factor by which the timeout grows with each retry
+    backoff: f64,
+
+    #[argh(option, short = 't', default = "500")]
+    /// initial timeout in milliseconds
+    timeout: u64,
+
+    #[argh(option, short = 'i', default = "10")]
+    /// interval between sending pings to different targets in milliseconds
+    interval: u64,
+
+    #[argh(option, short = 'p', default = "1000")]
+    /// period between two pings to the same target with -C, in milliseconds
+    period: u64,
+
+    #[argh(option, short = 'f')]
+    /// read targets from <file>, one per line
+    file: Option<String>,
+
+    #[argh(positional)]
+    /// host names or IPv4 addresses; read from stdin if there are none and no file is given
+    targets: Vec<String>,
+}
+
+struct Target {
+    name: String,
+    ip: Ipv4Addr,
+}
+
+// Outcome of sending one ping to each of a set of addresses.
+type RoundResult = HashMap<Ipv4Addr, Duration>;
+
+fn parse_target_lines(reader: impl BufRead) -> Result<Vec<String>, GenericError> {
+    let mut names = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let name = line.split('#').next().unwrap_or_default().trim();
+        if !name.is_empty() {
+            names.push(name.to_owned());
+        }
+    }
+    Ok(names)
+}
+
+fn generate_range(range: &[String]) -> Result<Vec<Ipv4Addr>, String> {
+    let (start, end) = match range {
+        [cidr] => {
+            let (network, prefix_len) = cidr.split_once('/').ok_or_else(|| format!("invalid range: {cidr}"))?;
+            let network = network.parse::<Ipv4Addr>().map_err(|e| format!("{network}: {e}"))?;
+            let prefix_len = prefix_len
+                .parse::<u32>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= 32)
+                .ok_or_else(|| format!("invalid prefix length: {prefix_len}"))?;
+            let mask = u32::MAX.checked_shl(32 - prefix_len).unwrap_or(0);
+            let first = u32::from(network) & mask;
+            let last = first | !mask;
+            // Network and broadcast addresses are skipped unless the network is a /31 or /32.
+            if prefix_len < 31 {
+                (first + 1, last - 1)
+            } else {
+                (first, last)
+            }
+        }
+        [start, end] => (
+            u32::from(start.parse::<Ipv4Addr>().map_err(|e| format!("{start}: {e}"))?),
+            u32::from(end.parse::<Ipv4Addr>().map_err(|e| format!("{end}: {e}"))?),
+        ),
+        _ => return Err("-g needs either a CIDR network or a start and an end address".to_owned()),
+    };
+    if start > end {
+        return Err("start of range is after its end".to_owned());
+    }
+    if u64::from(end - start) >= MAX_GENERATED_TARGETS {
+        return Err(format!("range is larger than {MAX_GENERATED_TARGETS} addresses"));
+    }
+    Ok((start..=end).map(Ipv4Addr::from).collect())
+}
+
+// Sends one ping to every address and collects the replies which arrive within `timeout` of the last ping.
+fn ping_round(ips: &[Ipv4Addr], timeout: Duration, interval: Duration) -> Result<RoundResult, GenericError> {
+    let config = PingFoxConfig {
+        timeout,
+        // Large enough not to block the sender while replies are outstanding.
+        channel_size: ips.len().max(1),
+        ..PingFoxConfig::default()
+    };
+    let (ping_sender, mut ping_receiver) = ping_fox::create(&config)?;
+    let (token_tx, token_rx) = mpsc::channel::<PingSentToken>();
+    let is_sending_done = Arc::new(AtomicBool::new(false));
+
+    let sender_ips = ips.to_vec();
+    let sender_is_sending_done = is_sending_done.clone();
+    let sender = std::thread::spawn(move || {
+        for (i, ip) in sender_ips.iter().enumerate() {
+            if i > 0 {
+                std::thread::sleep(interval);
+            }
+            match ping_sender.send_to(*ip) {
+                Ok(token) => {
+                    if token_tx.send(token).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => eprintln!("fping-fox: {ip}: {e}"),
+            }
+        }
+        sender_is_sending_done.store(true, Ordering::SeqCst);
+    });
+
+    let pending: HashSet<Ipv4Addr> = ips.iter().copied().collect();
+    let mut round_result = RoundResult::new();
+    // Every token allows one receive. A timeout only ends the round once all pings have been sent.
+    while let Ok(token) = token_rx.recv() {
+        match ping_receiver.receive(token) {
+            Ok(PingReceive::Data(PingReceiveData { ip_addr: IpAddr::V4(ip), ping_duration, .. }))
+                if pending.contains(&ip) =>
+            {
+                round_result.entry(ip).or_insert(ping_duration);
+            }
+            Ok(PingReceive::Data(_)) => {}
+            Ok(PingReceive::Timeout) if is_sending_done.load(Ordering::SeqCst) => break,
+            Ok(PingReceive::Timeout) => {}
+            Ok(PingReceive::Closed) => break,
+            Err(e) => eprintln!("fping-fox: {e}"),
+        }
+        if round_result.len() == pending.len() && is_sending_done.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    drop(token_rx);
+    if sender.join().is_err() {
+        return Err("sender thread panicked".into());
+    }
+    Ok(round_result)
+}
+
+fn unique_ips(targets: &[&Target]) -> Vec<Ipv4Addr> {
+    let mut seen = HashSet::new();
+    targets.iter().map(|target| target.ip).filter(|ip| seen.insert(*ip)).collect()
+}
+
+fn milliseconds(duration: Duration) -> String {
+    format!("{:.2}", duration.as_secs_f64() * 1000.0)
+}
+
+fn rtt_list_line(name: &str, name_width: usize, rtts: &[Option<Duration>]) -> String {
+    let rtts: Vec<String> = rtts
+        .iter()
+        .map(|rtt| rtt.map_or_else(|| "-".to_owned(), milliseconds))
+        .collect();
+    format!("{name:<name_width$} : {}", rtts.join(" "))
+}
+
+// Default mode: retries unreachable targets with a growing timeout; returns the alive addresses.
+fn run_alive(args: &Args, targets: &[Target]) -> Result<HashSet<Ipv4Addr>, GenericError> {
+    let interval = Duration::from_millis(args.interval);
+    let mut timeout = Duration::from_millis(args.timeout);
+    let mut alive = HashSet::new();
+    for _ in 0..=args.retries {
+        let pending: Vec<&Target> = targets.iter().filter(|target| !alive.contains(&target.ip)).collect();
+        if pending.is_empty() {
+            break;
+        }
+        alive.extend(ping_round(&unique_ips(&pending), timeout, interval)?.into_keys());
+        timeout = timeout.mul_f64(args.backoff);
+    }
+
+    // Like fping(8), -a and -u print bare names, otherwise each target is printed with its state.
+    for target in targets {
+        let is_alive = alive.contains(&target.ip);
+        match (args.alive, args.unreachable) {
+            (false, false) => println!("{} is {}", target.name, if is_alive { "alive" } else { "unreachable" }),
+            (show_alive, show_unreachable) if (is_alive && show_alive) || (!is_alive && show_unreachable) => {
+                println!("{}", target.name);
+            }
+            _ => {}
+        }
+    }
+    Ok(alive)
+}
+
+// `-C` mode: pings each target `count` times and prints the RTTs; returns the addresses with at least one reply.
+fn run_count(args: &Args, targets: &[Target], count: u32) -> Result<HashSet<Ipv4Addr>, GenericError> {
+    let interval = Duration::from_millis(args.interval);
+    let timeout = Duration::from_millis(args.timeout);
+    let period = Duration::from_millis(args.period);
+    let ips = unique_ips(&targets.iter().collect::<Vec<_>>());
+    let mut rtts: HashMap<Ipv4Addr, Vec<Option<Duration>>> = HashMap::new();
+    let name_width = targets.iter().map(|target| target.name.len()).max().unwrap_or_default();
+    for i in 0..count {
+        let round_start = Instant::now();
+        let round_result = ping_round(&ips, timeout, interval)?;
+        for ip in &ips {
+            rtts.entry(*ip).or_default().push(round_result.get(ip).copied());
+        }
+        for target in targets {
+            let target_rtts = &rtts[&target.ip];
+            if let Some(rtt) = target_rtts.last().copied().flatten() {
+                let replies: Vec<Duration> = target_rtts.iter().copied().flatten().collect();
+                let avg = replies.iter().sum::<Duration>() / u32::try_from(replies.len())?;
+                let loss = 100 - replies.len() * 100 / target_rtts.len();
+                println!(
+                    "{:<name_width$} : [{i}], {} ms ({} avg, {loss}% loss)",
+                    target.name,
+                    milliseconds(rtt),
+                    milliseconds(avg)
+                );
+            }
+        }
+        if i + 1 < count {
+            std::thread::sleep(period.saturating_sub(round_start.elapsed()));
+        }
+    }
+
+    eprintln!();
+    for target in targets {
+        eprintln!("{}", rtt_list_line(&target.name, name_width, &rtts[&target.ip]));
+    }
+    Ok(rtts
+        .into_iter()
+        .filter(|(_, rtts)| rtts.iter().any(Option::is_some))
+        .map(|(ip, _)| ip)
+        .collect())
+}
+
+fn read_target_names(args: &Args) -> Result<Vec<String>, GenericError> {
+    let mut names = args.targets.clone();
+    if let Some(file) = &args.file {
+        let file = std::fs::File::open(file).map_err(|e| format!("{file}: {e}"))?;
+        names.extend(parse_target_lines(std::io::BufReader::new(file))?);
+    }
+    if args.targets.is_empty() && args.file.is_none() {
+        names.extend(parse_target_lines(std::io::stdin().lock())?);
+    }
+    Ok(names)
+}
+
+fn run(args: &Args) -> Result<u8, GenericError> {
+    if args.backoff < 1.0 || args.timeout == 0 || args.count == Some(0) {
+        eprintln!("fping-fox: backoff must be at least 1, timeout and count must be positive");
+        return Ok(EXIT_INVALID_ARGUMENTS);
+    }
+    let names = read_target_names(args)?;
+    if names.is_empty() {
+        eprintln!("fping-fox: no targets");
+        return Ok(EXIT_INVALID_ARGUMENTS);
+    }
+    let mut targets = Vec::new();
+    let mut is_address_not_found = false;
+    if args.generate {
+        match generate_range(&names) {
+            Ok(ips) => targets.extend(ips.into_iter().map(|ip| Target { name: ip.to_string(), ip })),
+            Err(e) => {
+                eprintln!("fping-fox: {e}");
+                return Ok(EXIT_INVALID_ARGUMENTS);
+            }
+        }
+    } else {
+        for name in names {
+            match resolve(&name) {
+                Ok(ip) => targets.push(Target { name, ip }),
+                Err(_) => {
+                    eprintln!("{name}: Name or service not known");
+                    is_address_not_found = true;
+                }
+            }
+        }
+    }
+
+    let alive = match args.count {
+        Some(count) => run_count(args, &targets, count)?,
+        None => run_alive(args, &targets)?,
+    };
+    Ok(if is_address_not_found {
+        EXIT_ADDRESS_NOT_FOUND
+    } else if targets.iter().all(|target| alive.contains(&target.ip)) {
+        EXIT_ALL_ALIVE
+    } else {
+        EXIT_SOME_UNREACHABLE
+    })
+}
+
+fn main() -> ExitCode {
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(tracing::Level::WARN)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let args: Args = argh::from_env();
+    match run(&args) {
+        Ok(exit_code) => ExitCode::from(exit_code),
+        Err(e) => {
+            eprintln!("fping-fox: {e}");
+            ExitCode::from(EXIT_SYSTEM_FAILURE)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| (*value).to_owned()).collect()
+    }
+
+    #[test]
+    fn generate_range_from_cidr_skips_network_and_broadcast() {
+        let ips = generate_range(&strings(&["192.0.2.5/30"])).unwrap();
+
+        assert_eq!(vec![Ipv4Addr::new(192, 0, 2, 5), Ipv4Addr::new(192, 0, 2, 6)], ips);
+        assert_eq!(
+            vec![Ipv4Addr::new(192, 0, 2, 5)],
+            generate_range(&strings(&["192.0.2.5/32"])).unwrap()
+        );
+        assert_eq!(254, generate_range(&strings(&["192.0.2.0/24"])).unwrap().len());
+    }
+
+    #[test]
+    fn generate_range_from_start_and_end() {
+        let ips = generate_range(&strings(&["192.0.2.254", "192.0.3.1"])).unwrap();
+
+        assert_eq!(
+            vec![
+                Ipv4Addr::new(192, 0, 2, 254),
+                Ipv4Addr::new(192, 0, 2, 255),
+                Ipv4Addr::new(192, 0, 3, 0),
+                Ipv4Addr::new(192, 0, 3, 1)
+            ],
+            ips
+        );
+    }
+
+    #[test]
+    fn generate_range_rejects_invalid_ranges() {
+        assert!(generate_range(&strings(&["192.0.2.2", "192.0.2.1"])).is_err());
+        assert!(generate_range(&strings(&["192.0.2.0/33"])).is_err());
+        assert!(generate_range(&strings(&["192.0.2.0"])).is_err());
+        assert!(generate_range(&strings(&["10.0.0.0/8"])).is_err());
+        assert!(generate_range(&strings(&[])).is_err());
+    }
+
+    #[test]
+    fn parse_target_lines_skips_comments_and_empty_lines() {
+        let input = "192.0.2.1\n\n  # a comment\nlocalhost  # trailing comment\n";
+
+        let names = parse_target_lines(input.as_bytes()).unwrap();
+
+        assert_eq!(strings(&["192.0.2.1", "localhost"]), names);
+    }
+
+    #[test]
+    fn rtt_list_line_like_fping() {
+        let rtts = [Some(Duration::from_micros(120)), None, Some(Duration::from_micros(1500))];
+
+        assert_eq!("localhost : 0.12 - 1.50", rtt_list_line("localhost", 9, &rtts));
+        assert_eq!("a   : -", rtt_list_line("a", 3, &[None]));
+    }
+}
@@ -0,0 +1,232 @@
+use ping_fox::SocketType;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::resolve::resolve;
+use crate::GenericError;
+
+/// The configuration file of the daemon.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Socket type of the groups without their own.
+    #[serde(default)]
+    pub socket_type: SocketTypeName,
+    /// Time between two summaries of the statistics of every target.
+    #[serde(default = "default_summary_interval_ms")]
+    pub summary_interval_ms: u64,
+    /// Sinks the results and summaries are written to. NDJSON is written to stdout if there are none.
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+    /// The groups of targets by name.
+    #[serde(default)]
+    pub groups: BTreeMap<String, GroupConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GroupConfig {
+    /// Host names or IPv4 addresses.
+    pub targets: Vec<String>,
+    /// Time between two pings to the same target.
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    pub socket_type: Option<SocketTypeName>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SinkConfig {
+    pub format: SinkFormat,
+    /// File the records are appended to. Records are written to stdout without a path.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SocketTypeName {
+    #[default]
+    Dgram,
+    Raw,
+}
+
+impl From<SocketTypeName> for SocketType {
+    fn from(name: SocketTypeName) -> Self {
+        match name {
+            SocketTypeName::Dgram => SocketType::DGRAM,
+            SocketTypeName::Raw => SocketType::RAW,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SinkFormat {
+    /// Results and summaries as JSON objects, one per line.
+    Ndjson,
+    /// Results as CSV records.
+    Csv,
+}
+
+fn default_summary_interval_ms() -> u64 {
+    60_000
+}
+
+fn default_interval_ms() -> u64 {
+    1000
+}
+
+fn default_timeout_ms() -> u64 {
+    1000
+}
+
+/// What a group needs to run, apart from its targets. A running group is restarted when these
+/// change, while its targets can be changed live.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct GroupSettings {
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub socket_type: SocketTypeName,
+}
+
+/// A target of a group with its resolved address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Target {
+    pub name: String,
+    pub ip: Ipv4Addr,
+}
+
+impl Config {
+    /// Reads and checks the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<Config, GenericError> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Config::parse(&text).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    pub fn parse(text: &str) -> Result<Config, GenericError> {
+        let config: Config = toml::from_str(text)?;
+        if config.summary_interval_ms == 0 {
+            return Err("summary_interval_ms must be positive".into());
+        }
+        for (name, group) in &config.groups {
+            if group.interval_ms == 0 || group.timeout_ms == 0 {
+                return Err(format!("group {name}: interval_ms and timeout_ms must be positive").into());
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn summary_interval(&self) -> Duration {
+        Duration::from_millis(self.summary_interval_ms)
+    }
+
+    pub fn group_settings(&self, group: &GroupConfig) -> GroupSettings {
+        GroupSettings {
+            interval: Duration::from_millis(group.interval_ms),
+            timeout: Duration::from_millis(group.timeout_ms),
+            socket_type: group.socket_type.unwrap_or(self.socket_type),
+        }
+    }
+}
+
+impl GroupConfig {
+    /// Resolves the targets. Targets which cannot be resolved, or which resolve to the address
+    /// of an earlier target, are skipped with a warning, so that one bad entry does not stop the
+    /// others from being monitored.
+    pub fn resolve_targets(&self, group_name: &str) -> Vec<Target> {
+        let mut ips = HashSet::new();
+        let mut targets = Vec::new();
+        for name in &self.targets {
+            let Ok(ip) = resolve(name) else {
+                tracing::warn!("group {}: skipping {}: Name or service not known", group_name, name);
+                continue;
+            };
+            if !ips.insert(ip) {
+                tracing::warn!("group {}: skipping {}: {} is already a target", group_name, name, ip);
+                continue;
+            }
+            targets.push(Target { name: name.clone(), ip });
+        }
+        targets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_applies_defaults_and_overrides() {
+        let config = Config::parse(
+            r#"
+            socket_type = "raw"
+
+            [[sinks]]
+            format = "csv"
+            path = "/var/log/ping-fox.csv"
+
+            [groups.core]
+            targets = ["192.0.2.1"]
+
+            [groups.edge]
+            targets = ["192.0.2.2", "192.0.2.3"]
+            interval_ms = 5000
+            timeout_ms = 2000
+            socket_type = "dgram"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(Duration::from_secs(60), config.summary_interval());
+        assert_eq!(1, config.sinks.len());
+        assert_eq!(SinkFormat::Csv, config.sinks[0].format);
+        assert_eq!(
+            GroupSettings {
+                interval: Duration::from_secs(1),
+                timeout: Duration::from_secs(1),
+                socket_type: SocketTypeName::Raw
+            },
+            config.group_settings(&config.groups["core"])
+        );
+        assert_eq!(
+            GroupSettings {
+                interval: Duration::from_secs(5),
+                timeout: Duration::from_secs(2),
+                socket_type: SocketTypeName::Dgram
+            },
+            config.group_settings(&config.groups["edge"])
+        );
+    }
+
+    #[test]
+    fn parse_rejects_invalid_configurations() {
+        assert!(Config::parse("[groups.core]\ntargets = [\"192.0.2.1\"]\ninterval = 5\n").is_err());
+        assert!(Config::parse("[groups.core]\ntargets = [\"192.0.2.1\"]\ninterval_ms = 0\n").is_err());
+        assert!(Config::parse("socket_type = \"stream\"\n").is_err());
+        assert!(Config::parse("summary_interval_ms = 0\n").is_err());
+        assert!(Config::parse("[[sinks]]\nformat = \"xml\"\n").is_err());
+    }
+
+    #[test]
+    fn resolve_targets_skips_duplicate_addresses() {
+        let group = GroupConfig {
+            targets: vec!["192.0.2.1".to_owned(), "127.0.0.1".to_owned(), "192.0.2.1".to_owned()],
+            interval_ms: default_interval_ms(),
+            timeout_ms: default_timeout_ms(),
+            socket_type: None,
+        };
+
+        assert_eq!(
+            vec![
+                Target { name: "192.0.2.1".to_owned(), ip: Ipv4Addr::new(192, 0, 2, 1) },
+                Target { name: "127.0.0.1".to_owned(), ip: Ipv4Addr::LOCALHOST }
+            ],
+            group.resolve_targets("core")
+        );
+    }
+}
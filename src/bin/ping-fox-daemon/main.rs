@@ -0,0 +1,86 @@
+//! `ping-fox-daemon`: pings groups of targets continuously and writes the results and statistics
+//! to NDJSON and CSV sinks. SIGHUP reloads the configuration file, SIGINT and SIGTERM stop it.
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use config::Config;
+use monitor::Monitor;
+
+mod config;
+mod monitor;
+#[path = "../common/resolve.rs"]
+mod resolve;
+mod sink;
+
+type GenericError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+// Longest delay before a signal is acted upon.
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(argh::FromArgs)]
+/// ping-fox-daemon - monitor groups of network hosts with ICMP ECHO_REQUEST
+struct Args {
+    #[argh(positional)]
+    /// path of the TOML configuration file, which is read again on SIGHUP
+    config: PathBuf,
+}
+
+fn reload(args: &Args, monitor: &mut Monitor) -> Result<Duration, GenericError> {
+    let config = Config::load(&args.config)?;
+    monitor.reload(&config)?;
+    Ok(config.summary_interval())
+}
+
+fn run(args: &Args) -> Result<(), GenericError> {
+    let config = Config::load(&args.config)?;
+
+    let reload_requested = Arc::new(AtomicBool::new(false));
+    let terminate_requested = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, reload_requested.clone())?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, terminate_requested.clone())?;
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, terminate_requested.clone())?;
+
+    let mut monitor = Monitor::start(&config)?;
+    let mut summary_interval = config.summary_interval();
+    let mut next_summary_time = Instant::now() + summary_interval;
+    while !terminate_requested.load(Ordering::SeqCst) {
+        if reload_requested.swap(false, Ordering::SeqCst) {
+            // A broken file leaves everything as it is, so it can be fixed and reloaded again.
+            match reload(args, &mut monitor) {
+                Ok(interval) => {
+                    tracing::info!("reloaded {}", args.config.display());
+                    summary_interval = interval;
+                    next_summary_time = next_summary_time.min(Instant::now() + summary_interval);
+                }
+                Err(e) => tracing::error!("keeping the running configuration: {}", e),
+            }
+        }
+        if Instant::now() >= next_summary_time {
+            monitor.write_summaries();
+            next_summary_time = Instant::now() + summary_interval;
+        }
+        std::thread::sleep(SIGNAL_POLL_INTERVAL.min(next_summary_time.saturating_duration_since(Instant::now())));
+    }
+    monitor.write_summaries();
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(tracing::Level::INFO)
+        .with_writer(std::io::stderr)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let args: Args = argh::from_env();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("ping-fox-daemon: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
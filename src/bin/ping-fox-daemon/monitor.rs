@@ -0,0 +1,331 @@
+use ping_fox::metrics::{Histogram, Jitter};
+use ping_fox::{PingEvent, PingFoxConfig, PingReceive, PingSession, ShutdownHandle};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::config::{Config, GroupSettings, Target};
+use crate::sink::{Outcome, ProbeResult, Sinks, Summary};
+use crate::GenericError;
+
+// Sequence numbers are 16 bits, so no more pings can be told apart anyway.
+const MAX_IN_FLIGHT: usize = u16::MAX as usize;
+
+// Statistics are kept per group and target name, so that they survive reloads which keep both.
+type StatisticsKey = (String, String);
+type Statistics = BTreeMap<StatisticsKey, TargetStatistics>;
+
+struct TargetStatistics {
+    address: Ipv4Addr,
+    sent: u64,
+    received: u64,
+    histogram: Histogram,
+    jitter: Jitter,
+}
+
+impl TargetStatistics {
+    fn new(address: Ipv4Addr) -> Self {
+        TargetStatistics { address, sent: 0, received: 0, histogram: Histogram::new(), jitter: Jitter::new() }
+    }
+
+    fn summary<'a>(&self, group: &'a str, target: &'a str) -> Summary<'a> {
+        let loss = if self.sent == 0 {
+            0.0
+        } else {
+            #[allow(clippy::cast_precision_loss)]
+            let loss = (self.sent - self.received) as f64 / self.sent as f64;
+            loss
+        };
+        Summary {
+            group,
+            target,
+            address: self.address,
+            sent: self.sent,
+            received: self.received,
+            loss,
+            rtt_min_us: self.histogram.min().map(|rtt| rtt.as_micros()),
+            rtt_avg_us: self.histogram.mean().map(|rtt| rtt.as_micros()),
+            rtt_max_us: self.histogram.max().map(|rtt| rtt.as_micros()),
+            rtt_p99_us: self.histogram.value_at_quantile(0.99).map(|rtt| rtt.as_micros()),
+            jitter_us: self.jitter.value().as_micros(),
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Pings the targets of every group of a configuration and writes the results to its sinks.
+pub(crate) struct Monitor {
+    groups: BTreeMap<String, Group>,
+    statistics: Arc<Mutex<Statistics>>,
+    sinks: Arc<Mutex<Sinks>>,
+}
+
+impl Monitor {
+    /// Opens the sinks of `config` and starts its groups. Groups which cannot be started, e.g. for
+    /// lack of privileges for a RAW socket, are logged and left out.
+    pub fn start(config: &Config) -> Result<Monitor, GenericError> {
+        let sinks = Sinks::open(&config.sinks)?;
+        let mut monitor = Monitor {
+            groups: BTreeMap::new(),
+            statistics: Arc::new(Mutex::new(Statistics::new())),
+            sinks: Arc::new(Mutex::new(sinks)),
+        };
+        monitor.apply_groups(config);
+        Ok(monitor)
+    }
+
+    /// Switches to `config` while running. The sinks are reopened, and targets are added and
+    /// removed live. A group is only restarted if its interval, timeout or socket type changes.
+    /// The statistics of the targets which are kept are kept as well.
+    ///
+    /// Nothing changes if the sinks cannot be opened.
+    pub fn reload(&mut self, config: &Config) -> Result<(), GenericError> {
+        let sinks = Sinks::open(&config.sinks)?;
+        *lock(&self.sinks) = sinks;
+        self.apply_groups(config);
+        Ok(())
+    }
+
+    /// Writes the statistics of every target to the sinks.
+    pub fn write_summaries(&self) {
+        let statistics = lock(&self.statistics);
+        let mut sinks = lock(&self.sinks);
+        for ((group, target), target_statistics) in statistics.iter() {
+            sinks.write_summary(&target_statistics.summary(group, target));
+        }
+    }
+
+    fn apply_groups(&mut self, config: &Config) {
+        let resolved: BTreeMap<&str, (GroupSettings, Vec<Target>)> = config
+            .groups
+            .iter()
+            .map(|(name, group)| (name.as_str(), (config.group_settings(group), group.resolve_targets(name))))
+            .collect();
+
+        {
+            let mut statistics = lock(&self.statistics);
+            statistics.retain(|(group, target), _| {
+                resolved
+                    .get(group.as_str())
+                    .is_some_and(|(_, targets)| targets.iter().any(|t| t.name == *target))
+            });
+            for (group, (_, targets)) in &resolved {
+                for target in targets {
+                    statistics
+                        .entry(((*group).to_owned(), target.name.clone()))
+                        .or_insert_with(|| TargetStatistics::new(target.ip))
+                        .address = target.ip;
+                }
+            }
+        }
+
+        self.groups.retain(|name, _| resolved.contains_key(name.as_str()));
+        for (name, (settings, targets)) in resolved {
+            if let Some(group) = self.groups.get(name) {
+                if group.settings == settings {
+                    *lock(&group.targets) = targets;
+                    continue;
+                }
+            }
+            // The old group is stopped before the new one starts.
+            self.groups.remove(name);
+            match Group::start(name, settings, targets, self.statistics.clone(), self.sinks.clone()) {
+                Ok(group) => {
+                    self.groups.insert(name.to_owned(), group);
+                }
+                Err(e) => tracing::error!("group {}: could not start: {}", name, e),
+            }
+        }
+
+        let n_targets: usize = self.groups.values().map(|group| lock(&group.targets).len()).sum();
+        tracing::info!("monitoring {} targets in {} groups", n_targets, self.groups.len());
+    }
+}
+
+/// A group of targets pinged with a [`PingSession`] of its own.
+///
+/// Dropping the group stops it.
+struct Group {
+    settings: GroupSettings,
+    targets: Arc<Mutex<Vec<Target>>>,
+    shutdown_handle: ShutdownHandle,
+    scheduler: Option<JoinHandle<()>>,
+}
+
+impl Group {
+    fn start(
+        name: &str,
+        settings: GroupSettings,
+        targets: Vec<Target>,
+        statistics: Arc<Mutex<Statistics>>,
+        sinks: Arc<Mutex<Sinks>>,
+    ) -> Result<Group, GenericError> {
+        let targets = Arc::new(Mutex::new(targets));
+        let config = PingFoxConfig {
+            socket_type: settings.socket_type.into(),
+            timeout: settings.timeout,
+            channel_size: MAX_IN_FLIGHT,
+            ..PingFoxConfig::default()
+        };
+        let on_event = EventHandler { group: name.to_owned(), targets: targets.clone(), statistics, sinks };
+        let session = PingSession::new(&config, move |event| on_event.on_event(&event))?;
+        let shutdown_handle = session.shutdown_handle();
+
+        let scheduler_name = name.to_owned();
+        let scheduler_targets = targets.clone();
+        let scheduler_shutdown_handle = shutdown_handle.clone();
+        // The session is dropped, and its receive thread joined, when the scheduler ends.
+        let scheduler = std::thread::Builder::new()
+            .name(format!("group-{name}"))
+            .spawn(move || loop {
+                let round = lock(&scheduler_targets).clone();
+                for target in &round {
+                    match session.send_to(target.ip) {
+                        Ok(_) => {}
+                        Err(_) if scheduler_shutdown_handle.is_shut_down() => return,
+                        Err(e) => {
+                            tracing::warn!("group {}: error sending ping to {}: {}", scheduler_name, target.name, e);
+                        }
+                    }
+                }
+                if scheduler_shutdown_handle.wait_timeout(settings.interval) {
+                    return;
+                }
+            })?;
+
+        Ok(Group { settings, targets, shutdown_handle, scheduler: Some(scheduler) })
+    }
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        self.shutdown_handle.shutdown();
+        if let Some(scheduler) = self.scheduler.take() {
+            if scheduler.join().is_err() {
+                tracing::error!("group scheduler thread panicked");
+            }
+        }
+    }
+}
+
+// Records the events of the session of a group and writes them to the sinks.
+struct EventHandler {
+    group: String,
+    targets: Arc<Mutex<Vec<Target>>>,
+    statistics: Arc<Mutex<Statistics>>,
+    sinks: Arc<Mutex<Sinks>>,
+}
+
+impl EventHandler {
+    fn on_event(&self, event: &PingEvent) {
+        let IpAddr::V4(address) = event.target else {
+            return;
+        };
+        // The target may have been removed while the ping was in flight.
+        let Some(name) = lock(&self.targets)
+            .iter()
+            .find(|target| target.ip == address)
+            .map(|target| target.name.clone())
+        else {
+            return;
+        };
+        let (outcome, ttl, rtt) = {
+            let mut statistics = lock(&self.statistics);
+            let Some(target_statistics) = statistics.get_mut(&(self.group.clone(), name.clone())) else {
+                return;
+            };
+            match &event.outcome {
+                PingReceive::Data(data) => {
+                    target_statistics.sent += 1;
+                    target_statistics.received += 1;
+                    target_statistics.histogram.record(data.ping_duration);
+                    target_statistics.jitter.update(data.ping_duration);
+                    (Outcome::Reply, Some(data.ttl), Some(data.ping_duration))
+                }
+                PingReceive::Timeout => {
+                    target_statistics.sent += 1;
+                    (Outcome::Timeout, None, None)
+                }
+                PingReceive::Closed => return,
+            }
+        };
+        lock(&self.sinks).write_result(&ProbeResult {
+            group: &self.group,
+            target: &name,
+            address,
+            sequence_number: event.sequence_number,
+            outcome,
+            ttl,
+            rtt_us: rtt.map(|rtt: Duration| rtt.as_micros()),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn config(sink_path: &std::path::Path, targets: &str, interval_ms: u64) -> Config {
+        Config::parse(&format!(
+            "[[sinks]]\nformat = \"ndjson\"\npath = \"{}\"\n\n[groups.local]\ntargets = [{targets}]\ninterval_ms = {interval_ms}\n",
+            sink_path.display()
+        ))
+        .unwrap()
+    }
+
+    fn counts(monitor: &Monitor, target: &str) -> Option<(u64, u64)> {
+        lock(&monitor.statistics)
+            .get(&("local".to_owned(), target.to_owned()))
+            .map(|statistics| (statistics.sent, statistics.received))
+    }
+
+    fn wait_for(mut condition: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "condition not met in time");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn reload_applies_targets_and_keeps_statistics() {
+        let sink_path = std::env::temp_dir().join(format!("ping-fox-daemon-test-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&sink_path);
+        let mut monitor = Monitor::start(&config(&sink_path, "\"127.0.0.1\"", 10)).unwrap();
+        wait_for(|| counts(&monitor, "127.0.0.1").is_some_and(|(_, received)| received >= 2));
+
+        // Adding a target keeps the group running and the statistics of the other target.
+        let (_, received_before) = counts(&monitor, "127.0.0.1").unwrap();
+        monitor
+            .reload(&config(&sink_path, "\"127.0.0.1\", \"127.0.0.2\"", 10))
+            .unwrap();
+        assert!(counts(&monitor, "127.0.0.1").unwrap().1 >= received_before);
+        wait_for(|| counts(&monitor, "127.0.0.2").is_some_and(|(_, received)| received >= 1));
+
+        // A new interval restarts the group, still with the statistics.
+        let (_, received_before) = counts(&monitor, "127.0.0.2").unwrap();
+        monitor.reload(&config(&sink_path, "\"127.0.0.2\"", 20)).unwrap();
+        assert_eq!(None, counts(&monitor, "127.0.0.1"));
+        assert!(counts(&monitor, "127.0.0.2").unwrap().1 >= received_before);
+
+        monitor.write_summaries();
+        drop(monitor);
+
+        let output = std::fs::read_to_string(&sink_path).unwrap();
+        let _ = std::fs::remove_file(&sink_path);
+        let records: Vec<serde_json::Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert!(records
+            .iter()
+            .any(|record| record["record"] == "result" && record["target"] == "127.0.0.2"));
+        let summaries: Vec<&serde_json::Value> = records.iter().filter(|record| record["record"] == "summary").collect();
+        assert_eq!(1, summaries.len());
+        assert_eq!("127.0.0.2", summaries[0]["target"]);
+        assert!(summaries[0]["received"].as_u64().unwrap() >= received_before);
+    }
+}
@@ -0,0 +1,264 @@
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::net::Ipv4Addr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{SinkConfig, SinkFormat};
+use crate::GenericError;
+
+const CSV_HEADER: &str = "time,group,target,address,sequence_number,outcome,ttl,rtt_us";
+
+/// The outcome of one ping.
+#[derive(Serialize)]
+pub(crate) struct ProbeResult<'a> {
+    pub group: &'a str,
+    pub target: &'a str,
+    pub address: Ipv4Addr,
+    pub sequence_number: u16,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtt_us: Option<u128>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Outcome {
+    Reply,
+    Timeout,
+}
+
+/// The statistics of one target since it was added. Round-trip times are `None` without replies.
+#[derive(Serialize)]
+pub(crate) struct Summary<'a> {
+    pub group: &'a str,
+    pub target: &'a str,
+    pub address: Ipv4Addr,
+    pub sent: u64,
+    pub received: u64,
+    pub loss: f64,
+    pub rtt_min_us: Option<u128>,
+    pub rtt_avg_us: Option<u128>,
+    pub rtt_max_us: Option<u128>,
+    pub rtt_p99_us: Option<u128>,
+    pub jitter_us: u128,
+}
+
+// A line of NDJSON output: the record type, the time in seconds since the Unix epoch, and the record.
+#[derive(Serialize)]
+struct Line<'a, T> {
+    record: &'a str,
+    time: f64,
+    #[serde(flatten)]
+    fields: &'a T,
+}
+
+struct Sink {
+    format: SinkFormat,
+    writer: Box<dyn Write + Send>,
+    is_header_written: bool,
+}
+
+/// The sinks of a configuration, which results and summaries are written to.
+///
+/// Write errors are logged, so that one full disk does not stop the others from being written.
+pub(crate) struct Sinks {
+    sinks: Vec<Sink>,
+}
+
+impl Sinks {
+    /// Opens the sinks in `configs`, or NDJSON on stdout if there are none. Files are appended to,
+    /// so reopening them after a reload or a log rotation keeps earlier records.
+    pub fn open(configs: &[SinkConfig]) -> Result<Sinks, GenericError> {
+        if configs.is_empty() {
+            return Ok(Sinks { sinks: vec![Sink::new(SinkFormat::Ndjson, Box::new(io::stdout()))] });
+        }
+        let mut sinks = Vec::new();
+        for config in configs {
+            let writer: Box<dyn Write + Send> = match &config.path {
+                Some(path) => Box::new(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)
+                        .map_err(|e| format!("{}: {e}", path.display()))?,
+                ),
+                None => Box::new(io::stdout()),
+            };
+            sinks.push(Sink::new(config.format, writer));
+        }
+        Ok(Sinks { sinks })
+    }
+
+    pub fn write_result(&mut self, result: &ProbeResult<'_>) {
+        let time = now();
+        for sink in &mut self.sinks {
+            let written = match sink.format {
+                SinkFormat::Ndjson => sink.write_json("result", time, result),
+                SinkFormat::Csv => sink.write_csv(time, result),
+            };
+            if let Err(e) = written {
+                tracing::warn!("could not write result: {}", e);
+            }
+        }
+    }
+
+    /// Writes `summary` to the NDJSON sinks. CSV sinks only hold results.
+    pub fn write_summary(&mut self, summary: &Summary<'_>) {
+        let time = now();
+        for sink in self.sinks.iter_mut().filter(|sink| sink.format == SinkFormat::Ndjson) {
+            if let Err(e) = sink.write_json("summary", time, summary) {
+                tracing::warn!("could not write summary: {}", e);
+            }
+        }
+    }
+}
+
+impl Sink {
+    fn new(format: SinkFormat, writer: Box<dyn Write + Send>) -> Self {
+        Sink { format, writer, is_header_written: false }
+    }
+
+    fn write_json<T: Serialize>(&mut self, record: &str, time: f64, fields: &T) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, &Line { record, time, fields })?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+
+    fn write_csv(&mut self, time: f64, result: &ProbeResult<'_>) -> io::Result<()> {
+        if !self.is_header_written {
+            writeln!(self.writer, "{CSV_HEADER}")?;
+            self.is_header_written = true;
+        }
+        let outcome = match result.outcome {
+            Outcome::Reply => "reply",
+            Outcome::Timeout => "timeout",
+        };
+        writeln!(
+            self.writer,
+            "{time:.3},{},{},{},{},{outcome},{},{}",
+            csv_field(result.group),
+            csv_field(result.target),
+            result.address,
+            result.sequence_number,
+            result.ttl.map(|ttl| ttl.to_string()).unwrap_or_default(),
+            result.rtt_us.map(|rtt_us| rtt_us.to_string()).unwrap_or_default()
+        )?;
+        self.writer.flush()
+    }
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs_f64()
+}
+
+// Quotes a field which contains a separator, a quote or a line break, as in RFC 4180.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // A writer whose output stays readable after it has been moved into a sink.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(ToOwned::to_owned)
+                .collect()
+        }
+    }
+
+    fn reply() -> ProbeResult<'static> {
+        ProbeResult {
+            group: "core, east",
+            target: "localhost",
+            address: Ipv4Addr::LOCALHOST,
+            sequence_number: 7,
+            outcome: Outcome::Reply,
+            ttl: Some(64),
+            rtt_us: Some(120),
+        }
+    }
+
+    #[test]
+    fn ndjson_sink_writes_results_and_summaries() {
+        let buffer = SharedBuffer::default();
+        let mut sinks = Sinks { sinks: vec![Sink::new(SinkFormat::Ndjson, Box::new(buffer.clone()))] };
+
+        sinks.write_result(&reply());
+        sinks.write_summary(&Summary {
+            group: "core",
+            target: "localhost",
+            address: Ipv4Addr::LOCALHOST,
+            sent: 2,
+            received: 0,
+            loss: 1.0,
+            rtt_min_us: None,
+            rtt_avg_us: None,
+            rtt_max_us: None,
+            rtt_p99_us: None,
+            jitter_us: 0,
+        });
+
+        let lines = buffer.lines();
+        assert_eq!(2, lines.len());
+        let result: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!("result", result["record"]);
+        assert_eq!("reply", result["outcome"]);
+        assert_eq!(120, result["rtt_us"]);
+        assert!(result["time"].as_f64().unwrap() > 0.0);
+        let summary: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!("summary", summary["record"]);
+        assert_eq!(1.0, summary["loss"]);
+        assert!(summary["rtt_avg_us"].is_null());
+    }
+
+    #[test]
+    fn csv_sink_writes_header_and_quoted_results() {
+        let buffer = SharedBuffer::default();
+        let mut sinks = Sinks { sinks: vec![Sink::new(SinkFormat::Csv, Box::new(buffer.clone()))] };
+
+        sinks.write_result(&reply());
+        sinks.write_result(&ProbeResult { outcome: Outcome::Timeout, ttl: None, rtt_us: None, ..reply() });
+
+        let lines = buffer.lines();
+        assert_eq!(CSV_HEADER, lines[0]);
+        assert!(
+            lines[1].ends_with(",\"core, east\",localhost,127.0.0.1,7,reply,64,120"),
+            "{}",
+            lines[1]
+        );
+        assert!(
+            lines[2].ends_with(",\"core, east\",localhost,127.0.0.1,7,timeout,,"),
+            "{}",
+            lines[2]
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! `ping-fox`: a ping(8) work-alike which needs neither root nor setuid.
+use ping_fox::{PingEvent, PingFoxConfig, PingReceive, PingReceiveData, PingSession};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use resolve::resolve;
+
+#[path = "common/resolve.rs"]
+mod resolve;
+
+type GenericError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+// Size of the IPv4 header and the ICMP header, as printed by ping(8) in its first line.
+const IPV4_HEADER_SIZE: usize = 20;
+const ICMP_HEADER_SIZE: usize = 8;
+// ping(8) accepts at most 16 pattern bytes.
+const MAX_PATTERN_SIZE: usize = 16;
+// Granularity in which sleeping and waiting threads check for SIGINT and the deadline.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// Sequence numbers repeat after 2^16 pings, so more of them cannot be told apart in flight.
+const MAX_IN_FLIGHT: usize = 1 << 16;
+
+#[derive(argh::FromArgs)]
+/// ping-fox - send ICMP ECHO_REQUEST to a network host
+struct Args {
+    #[argh(option, short = 'c')]
+    /// stop after sending <count> ECHO_REQUEST packets
+    count: Option<u64>,
+
+    #[argh(option, short = 'i', default = "1.0")]
+    /// wait <interval> seconds between sending each packet
+    interval: f64,
+
+    #[argh(option, short = 'W', default = "1.0")]
+    /// time to wait for a response, in seconds
+    timeout: f64,
+
+    #[argh(option, short = 'w')]
+    /// stop after <deadline> seconds, regardless of how many packets have been sent or received
+    deadline: Option<f64>,
+
+    #[argh(option, short = 's', default = "56")]
+    /// number of data bytes to be sent
+    size: usize,
+
+    #[argh(option, short = 't')]
+    /// set the IP time to live
+    ttl: Option<u8>,
+
+    #[argh(switch, short = 'q')]
+    /// quiet output, only the summary is printed
+    quiet: bool,
+
+    #[argh(switch, short = 'D')]
+    /// print the timestamp (unix time + microseconds) before each line
+    timestamps: bool,
+
+    #[argh(option, short = 'I')]
+    /// interface name or source IPv4 address
+    interface: Option<String>,
+
+    #[argh(option, short = 'p')]
+    /// up to 16 hex bytes to fill the packet with, e.g. "ff00"
+    pattern: Option<String>,
+
+    #[argh(positional)]
+    /// host name or IPv4 address
+    destination: String,
+}
+
+#[derive(Default)]
+struct Statistics {
+    transmitted: u64,
+    received: u64,
+    rtts: Vec<Duration>,
+}
+
+impl Statistics {
+    fn on_reply(&mut self, rtt: Duration) {
+        self.received += 1;
+        self.rtts.push(rtt);
+    }
+
+    fn summary(&self, destination: &str, elapsed: Duration) -> String {
+        #[allow(clippy::cast_precision_loss)]
+        let loss = if self.transmitted == 0 {
+            0.0
+        } else {
+            (self.transmitted.saturating_sub(self.received)) as f64 * 100.0 / self.transmitted as f64
+        };
+        let mut summary = format!(
+            "--- {destination} ping statistics ---\n\
+             {} packets transmitted, {} received, {}% packet loss, time {}ms\n",
+            self.transmitted,
+            self.received,
+            format_general(loss),
+            elapsed.as_millis()
+        );
+        if !self.rtts.is_empty() {
+            let millis: Vec<f64> = self.rtts.iter().map(|rtt| rtt.as_secs_f64() * 1000.0).collect();
+            #[allow(clippy::cast_precision_loss)]
+            let n = millis.len() as f64;
+            let min = millis.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = millis.iter().copied().fold(0.0, f64::max);
+            let avg = millis.iter().sum::<f64>() / n;
+            let mdev = (millis.iter().map(|ms| ms * ms).sum::<f64>() / n - avg * avg).max(0.0).sqrt();
+            summary += &format!("rtt min/avg/max/mdev = {min:.3}/{avg:.3}/{max:.3}/{mdev:.3} ms\n");
+        }
+        summary
+    }
+}
+
+// Formats like `%g` in C for the values we print: at most four decimals, without trailing zeros.
+fn format_general(value: f64) -> String {
+    let formatted = format!("{value:.4}");
+    formatted.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
+
+fn parse_pattern(pattern: &str) -> Result<Vec<u8>, String> {
+    if pattern.is_empty() || pattern.len() % 2 == 1 || pattern.len() / 2 > MAX_PATTERN_SIZE {
+        return Err(format!("pattern must be 1 to {MAX_PATTERN_SIZE} hex bytes: {pattern}"));
+    }
+    (0..pattern.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&pattern[i..i + 2], 16).map_err(|_| format!("invalid hex in pattern: {pattern}")))
+        .collect()
+}
+
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("[{}.{:06}] ", now.as_secs(), now.subsec_micros())
+}
+
+fn seconds(value: f64, name: &str) -> Result<Duration, GenericError> {
+    Duration::try_from_secs_f64(value).map_err(|_| format!("invalid {name}: {value}").into())
+}
+
+// Returns how many pings can be in flight: one per interval within the timeout, and the one being
+// sent.
+fn max_in_flight(interval: Duration, timeout: Duration) -> usize {
+    let n = timeout.as_nanos().div_ceil(interval.as_nanos().max(1)) + 1;
+    usize::try_from(n).unwrap_or(usize::MAX).min(MAX_IN_FLIGHT)
+}
+
+fn print_reply(args: &Args, event: PingEvent, statistics: &mut Statistics) {
+    let PingReceive::Data(PingReceiveData { package_size, ip_addr, ttl, sequence_number, ping_duration, .. }) =
+        event.outcome
+    else {
+        return;
+    };
+    statistics.on_reply(ping_duration);
+    if !args.quiet {
+        let time = ping_duration.as_secs_f64() * 1000.0;
+        let prefix = if args.timestamps { timestamp() } else { String::new() };
+        println!("{prefix}{package_size} bytes from {ip_addr}: icmp_seq={sequence_number} ttl={ttl} time={time:.3} ms");
+    }
+}
+
+fn run(args: &Args) -> Result<ExitCode, GenericError> {
+    let ip = resolve(&args.destination)?;
+    let interval = seconds(args.interval, "interval")?;
+    let timeout = seconds(args.timeout, "timeout")?;
+    let deadline = args.deadline.map(|deadline| seconds(deadline, "deadline")).transpose()?;
+    let payload_pattern = args.pattern.as_deref().map(parse_pattern).transpose()?.unwrap_or_default();
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGINT, interrupted.clone())?;
+
+    let config = PingFoxConfig {
+        timeout,
+        channel_size: max_in_flight(interval, timeout),
+        payload_size: args.size,
+        payload_pattern,
+        ttl: args.ttl,
+        interface: args.interface.clone(),
+        ..PingFoxConfig::default()
+    };
+    // Every ping has one event: its reply, or a timeout after `timeout`.
+    let (session, events) = PingSession::with_channel(&config)?;
+
+    println!(
+        "PING {} ({ip}) {}({}) bytes of data.",
+        args.destination,
+        args.size,
+        args.size + ICMP_HEADER_SIZE + IPV4_HEADER_SIZE
+    );
+
+    let start_time = Instant::now();
+    let is_over = || interrupted.load(Ordering::SeqCst) || deadline.is_some_and(|deadline| start_time.elapsed() >= deadline);
+
+    let transmitted = AtomicU64::new(0);
+    let is_sending_done = AtomicBool::new(false);
+    let mut statistics = Statistics::default();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while args.count.is_none_or(|count| transmitted.load(Ordering::SeqCst) < count) && !is_over() {
+                match session.send_to(ip) {
+                    Ok(_) => {
+                        transmitted.fetch_add(1, Ordering::SeqCst);
+                    }
+                    Err(e) => eprintln!("ping-fox: sendmsg: {e}"),
+                }
+                if args.count.is_some_and(|count| transmitted.load(Ordering::SeqCst) >= count) {
+                    break;
+                }
+                let wake_up_time = Instant::now() + interval;
+                while Instant::now() < wake_up_time && !is_over() {
+                    std::thread::sleep(POLL_INTERVAL.min(wake_up_time.saturating_duration_since(Instant::now())));
+                }
+            }
+            is_sending_done.store(true, Ordering::SeqCst);
+        });
+
+        // Waits until every ping sent has its event, or until the deadline or SIGINT.
+        let mut n_events: u64 = 0;
+        while !is_over() {
+            if is_sending_done.load(Ordering::SeqCst) && n_events >= transmitted.load(Ordering::SeqCst) {
+                break;
+            }
+            match events.recv_timeout(POLL_INTERVAL) {
+                Ok(event) => {
+                    n_events += 1;
+                    print_reply(args, event, &mut statistics);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+    statistics.transmitted = transmitted.load(Ordering::SeqCst);
+
+    if interrupted.load(Ordering::SeqCst) {
+        println!();
+    }
+    print!("{}", statistics.summary(&args.destination, start_time.elapsed()));
+    Ok(if statistics.received > 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+fn main() -> ExitCode {
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(tracing::Level::WARN)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let args: Args = argh::from_env();
+    match run(&args) {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            eprintln!("ping-fox: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pattern_accepts_hex_bytes() {
+        assert_eq!(Ok(vec![0xFF, 0x00, 0x1a]), parse_pattern("ff001a"));
+        assert!(parse_pattern("").is_err());
+        assert!(parse_pattern("f").is_err());
+        assert!(parse_pattern("zz").is_err());
+        assert!(parse_pattern(&"00".repeat(MAX_PATTERN_SIZE + 1)).is_err());
+    }
+
+    #[test]
+    fn summary_like_iputils() {
+        let mut statistics = Statistics { transmitted: 3, ..Statistics::default() };
+        statistics.on_reply(Duration::from_micros(1000));
+        statistics.on_reply(Duration::from_micros(3000));
+
+        assert_eq!(
+            "--- localhost ping statistics ---\n\
+             3 packets transmitted, 2 received, 33.3333% packet loss, time 2002ms\n\
+             rtt min/avg/max/mdev = 1.000/2.000/3.000/1.000 ms\n",
+            statistics.summary("localhost", Duration::from_millis(2002))
+        );
+    }
+
+    #[test]
+    fn summary_without_replies() {
+        let statistics = Statistics { transmitted: 2, ..Statistics::default() };
+
+        assert_eq!(
+            "--- 192.0.2.1 ping statistics ---\n\
+             2 packets transmitted, 0 received, 100% packet loss, time 1001ms\n",
+            statistics.summary("192.0.2.1", Duration::from_millis(1001))
+        );
+    }
+
+    #[test]
+    fn max_in_flight_covers_timeout() {
+        assert_eq!(2, max_in_flight(Duration::from_secs(1), Duration::from_secs(1)));
+        assert_eq!(6, max_in_flight(Duration::from_millis(200), Duration::from_millis(999)));
+        assert_eq!(1, max_in_flight(Duration::from_secs(1), Duration::ZERO));
+        assert_eq!(MAX_IN_FLIGHT, max_in_flight(Duration::ZERO, Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,420 @@
+//! C ABI of ping-fox, enabled by the `capi` feature.
+//!
+//! The header `include/ping_fox.h` is generated from this module by cbindgen. Configurations,
+//! senders and receivers are opaque handles which are created and freed by the functions here.
+//! Every function which can fail returns a [`PingFoxError`]; the message of the last error on the
+//! calling thread is returned by [`ping_fox_last_error_message`].
+
+use crate::details::PingResult;
+use crate::{
+    ParseError, PingFoxConfig, PingReceive, PingReceiveData, PingReceiver, PingSender, PingSentToken, SocketType,
+    WouldExceedInFlight,
+};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::net::{IpAddr, Ipv4Addr};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+/// Result of a function of the C ABI.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PingFoxError {
+    /// Success.
+    Ok = 0,
+    /// A pointer was null or an argument was invalid, e.g. an address which is not IPv4.
+    InvalidArgument,
+    /// Sending would exceed the maximum number of pings in flight, see [`WouldExceedInFlight`].
+    WouldExceedInFlight,
+    /// A received message could not be parsed, see [`ParseError`].
+    Parse,
+    /// The socket could not be created for lack of privileges.
+    PermissionDenied,
+    /// Any other error of the operating system.
+    Io,
+    /// The sender and the receiver have been shut down.
+    ShutDown,
+    /// Any other error.
+    Other,
+    /// A panic was caught. This is a bug in ping-fox.
+    Panic,
+}
+
+/// Socket type of a [`PingFoxConfig`], see [`SocketType`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PingFoxSocketType {
+    /// Datagram socket, usable without elevated privileges.
+    Dgram,
+    /// Raw socket, which needs elevated privileges.
+    Raw,
+}
+
+/// Outcome of [`ping_fox_receive`], see [`PingReceive`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PingFoxOutcome {
+    /// An echo reply was received.
+    Data,
+    /// Nothing was received within the timeout.
+    Timeout,
+    /// The sender and the receiver have been shut down.
+    Closed,
+}
+
+/// Result of [`ping_fox_receive`]. Only `outcome` is set unless it is `Data`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PingFoxReply {
+    /// What was received.
+    pub outcome: PingFoxOutcome,
+    /// The IPv4 address of the host which sent the reply, in network byte order.
+    pub address: [u8; 4],
+    /// The sequence number of the reply.
+    pub sequence_number: u16,
+    /// The TTL of the reply, or 0 if it cannot be observed.
+    pub ttl: u8,
+    /// The size of the payload of the reply.
+    pub package_size: usize,
+    /// The round-trip time in microseconds.
+    pub rtt_us: u64,
+}
+
+impl PingFoxReply {
+    fn new(outcome: PingFoxOutcome) -> Self {
+        PingFoxReply { outcome, address: [0; 4], sequence_number: 0, ttl: 0, package_size: 0, rtt_us: 0 }
+    }
+
+    fn from_data(data: &PingReceiveData) -> Self {
+        let address = match data.ip_addr {
+            IpAddr::V4(ip) => ip.octets(),
+            IpAddr::V6(_) => [0; 4],
+        };
+        PingFoxReply {
+            outcome: PingFoxOutcome::Data,
+            address,
+            sequence_number: data.sequence_number,
+            ttl: data.ttl,
+            package_size: data.package_size,
+            rtt_us: u64::try_from(data.ping_duration.as_micros()).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+/// Opaque handle of a [`PingSender`].
+pub struct PingFoxSender(PingSender);
+
+/// Opaque handle of a [`PingReceiver`].
+pub struct PingFoxReceiver(PingReceiver);
+
+thread_local! {
+    static LAST_ERROR_MESSAGE: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error_message(message: &str) {
+    // Interior null bytes cannot occur in a C string, so they are dropped.
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR_MESSAGE.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn error_code(error: &(dyn std::error::Error + 'static)) -> PingFoxError {
+    if error.is::<WouldExceedInFlight>() {
+        PingFoxError::WouldExceedInFlight
+    } else if error.is::<ParseError>() {
+        PingFoxError::Parse
+    } else if let Some(error) = error.downcast_ref::<std::io::Error>() {
+        match error.kind() {
+            std::io::ErrorKind::PermissionDenied => PingFoxError::PermissionDenied,
+            _ => PingFoxError::Io,
+        }
+    } else {
+        PingFoxError::Other
+    }
+}
+
+// Runs `f`, maps its error to a `PingFoxError` and keeps its message.
+fn call<F>(f: F) -> PingFoxError
+where
+    F: FnOnce() -> Result<(), (PingFoxError, String)>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => PingFoxError::Ok,
+        Ok(Err((code, message))) => {
+            set_last_error_message(&message);
+            code
+        }
+        Err(_) => {
+            set_last_error_message("panic in ping-fox");
+            PingFoxError::Panic
+        }
+    }
+}
+
+fn map_error<T>(result: PingResult<T>) -> Result<T, (PingFoxError, String)> {
+    result.map_err(|error| (error_code(error.as_ref()), error.to_string()))
+}
+
+fn invalid_argument(message: &str) -> (PingFoxError, String) {
+    (PingFoxError::InvalidArgument, message.to_owned())
+}
+
+/// Returns the message of the last error on the calling thread, or null if there was none. The
+/// string is valid until the next call of a function of ping-fox on the same thread.
+#[no_mangle]
+pub extern "C" fn ping_fox_last_error_message() -> *const c_char {
+    LAST_ERROR_MESSAGE.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr()))
+}
+
+/// Returns a new configuration with the defaults of [`PingFoxConfig::default`]. It is freed with
+/// [`ping_fox_config_free`].
+#[no_mangle]
+pub extern "C" fn ping_fox_config_new() -> *mut PingFoxConfig {
+    Box::into_raw(Box::new(PingFoxConfig::default()))
+}
+
+/// Frees a configuration. Null is ignored.
+///
+/// # Safety
+///
+/// `config` must be null or a configuration returned by [`ping_fox_config_new`] which has not
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_config_free(config: *mut PingFoxConfig) {
+    if !config.is_null() {
+        drop(unsafe { Box::from_raw(config) });
+    }
+}
+
+// Applies `f` to the configuration behind `config`.
+unsafe fn with_config<F>(config: *mut PingFoxConfig, f: F) -> PingFoxError
+where
+    F: FnOnce(&mut PingFoxConfig),
+{
+    call(|| {
+        let config = unsafe { config.as_mut() }.ok_or_else(|| invalid_argument("config is null"))?;
+        f(config);
+        Ok(())
+    })
+}
+
+/// Sets the socket type, see [`PingFoxConfig::socket_type`].
+///
+/// # Safety
+///
+/// `config` must be a configuration returned by [`ping_fox_config_new`] or null.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_config_set_socket_type(
+    config: *mut PingFoxConfig,
+    socket_type: PingFoxSocketType,
+) -> PingFoxError {
+    unsafe {
+        with_config(config, |config| {
+            config.socket_type = match socket_type {
+                PingFoxSocketType::Dgram => SocketType::DGRAM,
+                PingFoxSocketType::Raw => SocketType::RAW,
+            };
+        })
+    }
+}
+
+/// Sets the timeout in milliseconds, see [`PingFoxConfig::timeout`].
+///
+/// # Safety
+///
+/// `config` must be a configuration returned by [`ping_fox_config_new`] or null.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_config_set_timeout_ms(config: *mut PingFoxConfig, timeout_ms: u64) -> PingFoxError {
+    unsafe { with_config(config, |config| config.timeout = Duration::from_millis(timeout_ms)) }
+}
+
+/// Sets the maximum number of pings in flight, see [`PingFoxConfig::channel_size`].
+///
+/// # Safety
+///
+/// `config` must be a configuration returned by [`ping_fox_config_new`] or null.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_config_set_max_in_flight(
+    config: *mut PingFoxConfig,
+    max_in_flight: usize,
+) -> PingFoxError {
+    unsafe { with_config(config, |config| config.channel_size = max_in_flight) }
+}
+
+/// Sets the TTL of echo requests, see [`PingFoxConfig::ttl`]. 0 restores the system default.
+///
+/// # Safety
+///
+/// `config` must be a configuration returned by [`ping_fox_config_new`] or null.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_config_set_ttl(config: *mut PingFoxConfig, ttl: u8) -> PingFoxError {
+    unsafe { with_config(config, |config| config.ttl = (ttl != 0).then_some(ttl)) }
+}
+
+/// Sets the number of payload bytes, see [`PingFoxConfig::payload_size`].
+///
+/// # Safety
+///
+/// `config` must be a configuration returned by [`ping_fox_config_new`] or null.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_config_set_payload_size(config: *mut PingFoxConfig, payload_size: usize) -> PingFoxError {
+    unsafe { with_config(config, |config| config.payload_size = payload_size) }
+}
+
+/// Creates a sender and a receiver as configured, see [`crate::create`]. On success, they are
+/// stored in `sender` and `receiver` and are freed with [`ping_fox_sender_free`] and
+/// [`ping_fox_receiver_free`].
+///
+/// # Safety
+///
+/// `config` must be a configuration returned by [`ping_fox_config_new`] or null. `sender` and
+/// `receiver` must be valid for writes or null.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_create(
+    config: *const PingFoxConfig,
+    sender: *mut *mut PingFoxSender,
+    receiver: *mut *mut PingFoxReceiver,
+) -> PingFoxError {
+    call(|| {
+        let config = unsafe { config.as_ref() }.ok_or_else(|| invalid_argument("config is null"))?;
+        if sender.is_null() || receiver.is_null() {
+            return Err(invalid_argument("sender or receiver is null"));
+        }
+        let (ping_sender, ping_receiver) = map_error(crate::create(config))?;
+        unsafe {
+            *sender = Box::into_raw(Box::new(PingFoxSender(ping_sender)));
+            *receiver = Box::into_raw(Box::new(PingFoxReceiver(ping_receiver)));
+        }
+        Ok(())
+    })
+}
+
+/// Sends a ping to `address`, an IPv4 address in dotted decimal notation like "127.0.0.1". See
+/// [`PingSender::send_to`]. The sender may be used from several threads at once.
+///
+/// # Safety
+///
+/// `sender` must be a sender returned by [`ping_fox_create`] or null. `address` must be a null
+/// terminated string or null.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_send_to(sender: *const PingFoxSender, address: *const c_char) -> PingFoxError {
+    call(|| {
+        let sender = unsafe { sender.as_ref() }.ok_or_else(|| invalid_argument("sender is null"))?;
+        if address.is_null() {
+            return Err(invalid_argument("address is null"));
+        }
+        let address = unsafe { CStr::from_ptr(address) };
+        let ip: Ipv4Addr = address
+            .to_str()
+            .ok()
+            .and_then(|address| address.parse().ok())
+            .ok_or_else(|| invalid_argument(&format!("not an IPv4 address: {}", address.to_string_lossy())))?;
+        match sender.0.send_to(ip) {
+            Ok(_token) => Ok(()),
+            Err(error) if sender.0.shutdown_handle().is_shut_down() => Err((PingFoxError::ShutDown, error.to_string())),
+            Err(error) => map_error(Err(error)),
+        }
+    })
+}
+
+/// Waits for an echo reply and stores it in `reply`, see [`PingReceiver::receive`].
+///
+/// # Safety
+///
+/// `receiver` must be a receiver returned by [`ping_fox_create`] or null. It must not be used by
+/// several threads at once. `reply` must be valid for writes or null.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_receive(receiver: *mut PingFoxReceiver, reply: *mut PingFoxReply) -> PingFoxError {
+    call(|| {
+        let receiver = unsafe { receiver.as_mut() }.ok_or_else(|| invalid_argument("receiver is null"))?;
+        let reply = unsafe { reply.as_mut() }.ok_or_else(|| invalid_argument("reply is null"))?;
+        *reply = match map_error(receiver.0.receive(PingSentToken {}))? {
+            PingReceive::Data(data) => PingFoxReply::from_data(&data),
+            PingReceive::Timeout => PingFoxReply::new(PingFoxOutcome::Timeout),
+            PingReceive::Closed => PingFoxReply::new(PingFoxOutcome::Closed),
+        };
+        Ok(())
+    })
+}
+
+/// Shuts down a sender and its receiver, see [`crate::ShutdownHandle::shutdown`]. A receiver
+/// blocked in [`ping_fox_receive`] on another thread returns `Closed` at once.
+///
+/// # Safety
+///
+/// `sender` must be a sender returned by [`ping_fox_create`] or null.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_shutdown(sender: *const PingFoxSender) -> PingFoxError {
+    call(|| {
+        let sender = unsafe { sender.as_ref() }.ok_or_else(|| invalid_argument("sender is null"))?;
+        sender.0.shutdown_handle().shutdown();
+        Ok(())
+    })
+}
+
+/// Frees a sender. Null is ignored.
+///
+/// # Safety
+///
+/// `sender` must be null or a sender returned by [`ping_fox_create`] which has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_sender_free(sender: *mut PingFoxSender) {
+    if !sender.is_null() {
+        drop(unsafe { Box::from_raw(sender) });
+    }
+}
+
+/// Frees a receiver. Null is ignored.
+///
+/// # Safety
+///
+/// `receiver` must be null or a receiver returned by [`ping_fox_create`] which has not been
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn ping_fox_receiver_free(receiver: *mut PingFoxReceiver) {
+    if !receiver.is_null() {
+        drop(unsafe { Box::from_raw(receiver) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    fn last_error_message() -> String {
+        unsafe { CStr::from_ptr(ping_fox_last_error_message()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn errors_are_mapped_to_codes() {
+        assert_eq!(
+            PingFoxError::WouldExceedInFlight,
+            error_code(&WouldExceedInFlight { max_in_flight: 1 })
+        );
+        assert_eq!(
+            PingFoxError::PermissionDenied,
+            error_code(&std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+        );
+        assert_eq!(
+            PingFoxError::Io,
+            error_code(&std::io::Error::from(std::io::ErrorKind::NotFound))
+        );
+    }
+
+    #[test]
+    fn null_pointers_are_invalid_arguments() {
+        let mut sender = ptr::null_mut();
+        let mut receiver = ptr::null_mut();
+
+        let error = unsafe { ping_fox_create(ptr::null(), ptr::addr_of_mut!(sender), ptr::addr_of_mut!(receiver)) };
+
+        assert_eq!(PingFoxError::InvalidArgument, error);
+        assert_eq!("config is null", last_error_message());
+        assert!(sender.is_null() && receiver.is_null());
+        assert_eq!(PingFoxError::InvalidArgument, unsafe {
+            ping_fox_send_to(ptr::null(), ptr::null())
+        });
+    }
+}
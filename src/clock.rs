@@ -0,0 +1,83 @@
+use crate::details::sync::lock;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Source of the time stamps from which ping durations are computed.
+///
+/// ping-fox reads the time through a `Clock` instead of calling [`Instant::now`] directly, so that
+/// tests can use a [`ManualClock`] and assert exact ping durations.
+pub trait Clock: Send + Sync {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+}
+
+/// A [`Clock`] reading the monotonic system clock, i.e. [`Instant::now`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A [`Clock`] which stands still until it is advanced explicitly.
+///
+/// ```
+/// use ping_fox::{Clock, ManualClock};
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::new();
+/// let start = clock.now();
+/// clock.advance(Duration::from_millis(5));
+/// assert_eq!(Duration::from_millis(5), clock.now() - start);
+/// ```
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// Creates a `ManualClock` standing at an arbitrary instant.
+    #[must_use]
+    pub fn new() -> Self {
+        ManualClock { start: Instant::now(), elapsed: Mutex::new(Duration::ZERO) }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let mut elapsed = lock(&self.elapsed);
+        *elapsed += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *lock(&self.elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_stands_still_until_advanced() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(start, clock.now());
+
+        clock.advance(Duration::from_millis(3));
+        clock.advance(Duration::from_micros(250));
+        assert_eq!(Duration::from_micros(3250), clock.now() - start);
+    }
+}
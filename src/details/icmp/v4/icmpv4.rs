@@ -1,7 +1,12 @@
 use super::TSocket;
-use crate::details::icmp::v4::SequenceNumber;
+use crate::details::icmp::v4::parse::{parse_icmp_message, IcmpMessage};
+use crate::details::icmp::v4::{SequenceNumber, Ttl};
+use crate::details::pcap::PcapWriter;
 use crate::details::records::PingReceiveRecordData;
+use crate::details::sync::lock;
 use crate::details::PingError;
+use crate::details::TProbe;
+use crate::{Clock, ProbeKind};
 use pnet_packet::icmp::{
     echo_reply::EchoReplyPacket,
     echo_request::{EchoRequestPacket as EchoRequestPacketV4, MutableEchoRequestPacket as MutableEchoRequestPacketV4},
@@ -11,63 +16,140 @@ use pnet_packet::Packet;
 use rand::Rng;
 use std::io;
 use std::net::{IpAddr, Ipv4Addr};
+use std::os::fd::RawFd;
 use std::result::Result;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-const PAYLOAD_SIZE: usize = 56;
+pub(crate) const PAYLOAD_SIZE: usize = 56;
+
+// Receive buffers are never smaller than this, so that replies to other pings fit as well.
+const MIN_RECEIVE_BUFFER_SIZE: usize = 128;
 
 pub(crate) struct IcmpV4<S> {
-    payload: [u8; PAYLOAD_SIZE],
+    payload: Vec<u8>,
+    // Allocated once for all receives, which only ever happen on one thread at a time.
+    receive_buffer: Mutex<Vec<u8>>,
     socket: S,
+    clock: Arc<dyn Clock>,
+    pcap_writer: Option<PcapWriter>,
 }
 
 impl<S> IcmpV4<S>
 where
     S: TSocket + 'static,
 {
+    #[cfg(test)]
     pub(crate) fn new(socket: S) -> IcmpV4<S> {
-        let mut payload = [0u8; PAYLOAD_SIZE];
+        Self::with_payload(socket, new_payload(PAYLOAD_SIZE, &[]), Arc::new(crate::SystemClock))
+    }
+
+    pub(crate) fn with_payload(socket: S, payload: Vec<u8>, clock: Arc<dyn Clock>) -> IcmpV4<S> {
+        let receive_buffer_size = (EchoReplyPacket::minimum_packet_size() + payload.len()).max(MIN_RECEIVE_BUFFER_SIZE);
+        IcmpV4 { payload, receive_buffer: Mutex::new(vec![0u8; receive_buffer_size]), socket, clock, pcap_writer: None }
+    }
+
+    // Writes every sent and received ICMP message to `pcap_writer`.
+    pub(crate) fn with_pcap_writer(self, pcap_writer: PcapWriter) -> IcmpV4<S> {
+        IcmpV4 { pcap_writer: Some(pcap_writer), ..self }
+    }
+
+    // A failure to capture a message must not fail the ping.
+    fn capture(&self, write: impl FnOnce(&PcapWriter) -> io::Result<()>) {
+        if let Some(pcap_writer) = &self.pcap_writer {
+            if let Err(e) = write(pcap_writer) {
+                tracing::warn!("error writing pcap file: {}", e);
+            }
+        }
+    }
+
+    // Receives through `recv_from`, a receive function of the socket. Messages other than echo
+    // replies are skipped. A malformed message fails with a `ParseError`.
+    fn receive_with<F>(&self, recv_from: F) -> std::result::Result<Option<PingReceiveRecordData>, io::Error>
+    where
+        F: Fn(&S, &mut [u8]) -> io::Result<(usize, IpAddr, u8)>,
+    {
+        let mut buf1 = lock(&self.receive_buffer);
+        loop {
+            let (package_size, ip_addr, ttl) = match recv_from(&self.socket, &mut buf1) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e),
+                Ok(received) => received,
+            };
+            let receive_time: Instant = self.clock.now();
+            // A custom socket may report more bytes than fit into the buffer.
+            let message = &buf1[..package_size.min(buf1.len())];
+            if let IpAddr::V4(source) = ip_addr {
+                self.capture(|pcap_writer| pcap_writer.write_incoming(source, ttl, message));
+            }
+            match parse_icmp_message(message)? {
+                IcmpMessage::EchoReply { sequence_number } => {
+                    return Ok(Some(PingReceiveRecordData {
+                        package_size,
+                        ip_addr,
+                        ttl: Ttl(ttl),
+                        sequence_number,
+                        receive_time,
+                        probe_kind: ProbeKind::Icmp,
+                    }));
+                }
+                IcmpMessage::Other { icmp_type, icmp_code } => {
+                    tracing::trace!("ignoring ICMP message type {icmp_type} code {icmp_code} from {ip_addr}");
+                    // Another receive could block until the socket times out. The receiver waits
+                    // for a socket with a file descriptor before it calls `try_receive` again.
+                    if self.socket.raw_fd().is_some() {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Creates a payload of `size` bytes. Like the `-p` option of ping(8), a non-empty `pattern` is
+// repeated to fill the payload. Otherwise, the payload is random.
+pub(crate) fn new_payload(size: usize, pattern: &[u8]) -> Vec<u8> {
+    if pattern.is_empty() {
+        let mut payload = vec![0u8; size];
         rand::thread_rng().fill(&mut payload[..]);
-        IcmpV4 { payload, socket }
+        payload
+    } else {
+        pattern.iter().copied().cycle().take(size).collect()
     }
+}
 
-    pub(crate) fn send_to(
+impl<S> TProbe for IcmpV4<S>
+where
+    S: TSocket + 'static,
+{
+    fn send_to(
         &self,
         ipv4: Ipv4Addr,
         sequence_number: SequenceNumber,
     ) -> Result<(usize, IpAddr, SequenceNumber, Instant), PingError> {
         let ip_addr = IpAddr::V4(ipv4);
-        let addr = std::net::SocketAddr::new(ip_addr, 0);
 
         let package = new_icmpv4_package(sequence_number, &self.payload)
             .ok_or(PingError { message: "could not create ICMP package".to_owned() })?;
 
         let packet = pnet_packet::Packet::packet(&package);
-        let addr2: socket2::SockAddr = addr.into();
-        let start_time: Instant = Instant::now();
-        self.socket.send_to(packet, &addr2)?;
-
-        Ok((PAYLOAD_SIZE, ip_addr, sequence_number, start_time))
-    }
-
-    pub(crate) fn try_receive(&self) -> std::result::Result<Option<PingReceiveRecordData>, io::Error> {
-        let mut buf1 = [0u8; 128];
-        match self.socket.recv_from(&mut buf1) {
-            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
-            Err(e) => Err(e),
-            Ok((package_size, ip_addr, ttl)) => {
-                let receive_time: Instant = Instant::now();
-                let echo_reply_package = EchoReplyPacket::new(&buf1).expect("could not initialize echo reply package");
-                let sequence_number: SequenceNumber = echo_reply_package.get_sequence_number().into();
-                Ok(Some(PingReceiveRecordData {
-                    package_size,
-                    ip_addr,
-                    ttl,
-                    sequence_number,
-                    receive_time,
-                }))
-            }
-        }
+        let start_time: Instant = self.clock.now();
+        self.socket.send_to(packet, ip_addr)?;
+        self.capture(|pcap_writer| pcap_writer.write_outgoing(ipv4, packet));
+
+        Ok((self.payload.len(), ip_addr, sequence_number, start_time))
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.socket.raw_fd()
+    }
+
+    fn try_receive(&self) -> std::result::Result<Option<PingReceiveRecordData>, io::Error> {
+        self.receive_with(S::recv_from)
+    }
+
+    fn try_receive_now(&self) -> std::result::Result<Option<PingReceiveRecordData>, io::Error> {
+        self.receive_with(S::try_recv_from)
     }
 }
 
@@ -94,6 +176,7 @@ mod tests {
     use crate::details::icmp::v4::tests::OnReceive;
     use crate::details::icmp::v4::tests::OnSend;
     use crate::details::icmp::v4::tests::SocketMock;
+    use crate::details::icmp::v4::ParseError;
 
     #[test]
     fn test_send_one_ping() {
@@ -121,10 +204,79 @@ mod tests {
 
         assert!(result.is_ok());
         assert!(result.as_ref().unwrap().is_some());
-        let PingReceiveRecordData { package_size, ip_addr, ttl: _, sequence_number: _, receive_time: _ } =
+        let PingReceiveRecordData { package_size, ip_addr, ttl: _, sequence_number: _, receive_time: _, probe_kind } =
             result.unwrap().unwrap();
         assert!(package_size >= EchoReplyPacket::minimum_packet_size());
         assert!(ip_addr == Ipv4Addr::new(127, 0, 0, 1));
+        assert!(probe_kind == ProbeKind::Icmp);
         socket_mock.should_receive_number_of_messages(1);
     }
+
+    #[test]
+    fn new_payload_repeats_pattern() {
+        assert_eq!(vec![0xAB, 0xCD, 0xAB, 0xCD, 0xAB], new_payload(5, &[0xAB, 0xCD]));
+        assert_eq!(56, new_payload(56, &[]).len());
+        assert!(new_payload(0, &[0xFF]).is_empty());
+    }
+
+    #[test]
+    fn test_send_one_ping_with_payload() {
+        let socket_mock = SocketMock::new(OnSend::ReturnDefault, OnReceive::ReturnWouldBlock);
+        let icmpv4 = IcmpV4::with_payload(socket_mock.clone(), new_payload(1000, &[0x42]), Arc::new(crate::SystemClock));
+
+        let (payload_size, _, _, _) = icmpv4
+            .send_to(Ipv4Addr::new(127, 0, 0, 1), SequenceNumber::start_value())
+            .unwrap();
+
+        assert_eq!(1000, payload_size);
+        socket_mock.should_send_number_of_messages(1);
+    }
+
+    #[test]
+    fn sent_and_received_messages_are_written_to_pcap_file() {
+        use crate::details::pcap::tests::{temp_pcap_path, PCAP_HEADER_SIZE};
+
+        let path = temp_pcap_path("icmpv4");
+        let socket_mock = SocketMock::new(OnSend::ReturnDefault, OnReceive::ReturnDefault(1));
+        let icmpv4 = IcmpV4::new(socket_mock).with_pcap_writer(PcapWriter::create(&path, 64).unwrap());
+
+        icmpv4
+            .send_to(Ipv4Addr::new(127, 0, 0, 1), SequenceNumber::start_value())
+            .unwrap();
+        icmpv4.try_receive().unwrap().unwrap();
+
+        let content = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // Two records of a 16 byte record header, a 20 byte IPv4 header and at least an 8 byte
+        // ICMP header each.
+        assert!(content.len() >= PCAP_HEADER_SIZE + 2 * (16 + 20 + 8));
+    }
+
+    struct TruncatedMessageSocket;
+
+    impl TSocket for TruncatedMessageSocket {
+        fn send_to(&self, buf: &[u8], _addr: IpAddr) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+            buf[..3].copy_from_slice(&[0, 0, 0]);
+            Ok((3, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 64))
+        }
+    }
+
+    #[test]
+    fn when_message_is_truncated_then_try_receive_fails_with_parse_error() {
+        let icmpv4 = IcmpV4::new(TruncatedMessageSocket);
+
+        let Err(error) = icmpv4.try_receive() else {
+            panic!("expected an error");
+        };
+
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert_eq!(
+            Some(&ParseError::Truncated { expected: 8, actual: 3 }),
+            error.get_ref().and_then(|e| e.downcast_ref())
+        );
+    }
 }
@@ -1,12 +1,14 @@
 pub(crate) use icmpv4::IcmpV4;
+pub use parse::ParseError;
 pub(crate) use sequence_number::SequenceNumber;
 pub(crate) use socket::dgram_socket::DgramSocket;
 pub(crate) use socket::raw_socket::RawSocket;
 pub(crate) use socket::Socket;
-pub(crate) use socket::TSocket;
+pub use socket::TSocket;
 pub(crate) use ttl::Ttl;
 
 pub(crate) mod icmpv4;
+pub(crate) mod parse;
 mod sequence_number;
 mod socket;
 mod ttl;
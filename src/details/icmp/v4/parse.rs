@@ -0,0 +1,270 @@
+use crate::details::icmp::v4::SequenceNumber;
+use std::ffi::CStr;
+use std::net::{IpAddr, Ipv4Addr};
+use std::{error::Error, fmt, io};
+
+const MIN_IPV4_HEADER_SIZE: usize = 20;
+const ICMP_HEADER_SIZE: usize = 8;
+const ICMP_ECHO_REPLY: u8 = 0;
+
+/// Error parsing a received message.
+///
+/// A receive fails with an [`io::Error`] of kind [`io::ErrorKind::InvalidData`] which wraps a
+/// `ParseError`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// The message is shorter than its header.
+    Truncated {
+        /// Minimum number of bytes.
+        expected: usize,
+        /// Number of bytes received.
+        actual: usize,
+    },
+    /// The version field of the IP header is not 4.
+    NotIpv4 {
+        /// The version field.
+        version: u8,
+    },
+    /// The IHL field of the IPv4 header is below the minimum of 5 words or exceeds the packet.
+    InvalidHeaderLength {
+        /// The header length in bytes.
+        header_length: usize,
+    },
+    /// The checksum of the ICMP message is wrong.
+    InvalidChecksum,
+    /// The address of the sender is not a valid IP address.
+    InvalidAddress,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ParseError::Truncated { expected, actual } => {
+                write!(f, "message truncated: {actual} bytes received, at least {expected} expected")
+            }
+            ParseError::NotIpv4 { version } => write!(f, "IP version {version} instead of 4"),
+            ParseError::InvalidHeaderLength { header_length } => {
+                write!(f, "invalid IPv4 header length of {header_length} bytes")
+            }
+            ParseError::InvalidChecksum => write!(f, "invalid ICMP checksum"),
+            ParseError::InvalidAddress => write!(f, "invalid IP address"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(error: ParseError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+// The fields of an IPv4 header ping-fox needs, and the payload of the packet.
+pub(crate) struct Ipv4Packet<'a> {
+    pub(crate) source: Ipv4Addr,
+    pub(crate) ttl: u8,
+    // Only needed to skip packets other than ICMP when replaying a capture.
+    #[cfg_attr(not(feature = "testing"), allow(dead_code))]
+    pub(crate) protocol: u8,
+    pub(crate) payload: &'a [u8],
+}
+
+// Parses an IPv4 packet. Options are skipped as given by the IHL field. The payload ends where the
+// total length field says, or with the buffer if the packet was truncated.
+pub(crate) fn parse_ipv4_packet(packet: &[u8]) -> Result<Ipv4Packet<'_>, ParseError> {
+    let header = packet
+        .get(..MIN_IPV4_HEADER_SIZE)
+        .ok_or(ParseError::Truncated { expected: MIN_IPV4_HEADER_SIZE, actual: packet.len() })?;
+    let version = header[0] >> 4;
+    if version != 4 {
+        return Err(ParseError::NotIpv4 { version });
+    }
+    let header_length = usize::from(header[0] & 0x0f) * 4;
+    if header_length < MIN_IPV4_HEADER_SIZE || header_length > packet.len() {
+        return Err(ParseError::InvalidHeaderLength { header_length });
+    }
+    let total_length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+    let end = total_length.clamp(header_length, packet.len());
+    Ok(Ipv4Packet {
+        source: Ipv4Addr::new(header[12], header[13], header[14], header[15]),
+        ttl: header[8],
+        protocol: header[9],
+        payload: &packet[header_length..end],
+    })
+}
+
+pub(crate) enum IcmpMessage {
+    EchoReply { sequence_number: SequenceNumber },
+    // Messages other than echo replies, e.g. errors or, on RAW sockets, echo requests.
+    Other { icmp_type: u8, icmp_code: u8 },
+}
+
+// Parses an ICMP message and verifies its checksum.
+pub(crate) fn parse_icmp_message(message: &[u8]) -> Result<IcmpMessage, ParseError> {
+    let header = message
+        .get(..ICMP_HEADER_SIZE)
+        .ok_or(ParseError::Truncated { expected: ICMP_HEADER_SIZE, actual: message.len() })?;
+    if internet_checksum(message) != 0 {
+        return Err(ParseError::InvalidChecksum);
+    }
+    match (header[0], header[1]) {
+        (ICMP_ECHO_REPLY, _) => {
+            Ok(IcmpMessage::EchoReply { sequence_number: u16::from_be_bytes([header[6], header[7]]).into() })
+        }
+        (icmp_type, icmp_code) => Ok(IcmpMessage::Other { icmp_type, icmp_code }),
+    }
+}
+
+// Parses the null-terminated address string the C helpers return.
+pub(crate) fn parse_addr(addr_str: &[u8]) -> Result<IpAddr, ParseError> {
+    CStr::from_bytes_until_nul(addr_str)
+        .ok()
+        .and_then(|addr_str| addr_str.to_str().ok())
+        .and_then(|addr_str| addr_str.parse::<IpAddr>().ok())
+        .ok_or(ParseError::InvalidAddress)
+}
+
+// The checksum of RFC 1071. It is 0 over a message with a correct checksum.
+pub(crate) fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u64 = data
+        .chunks(2)
+        .map(|chunk| u64::from(u16::from_be_bytes([chunk[0], chunk.get(1).copied().unwrap_or(0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    // After folding, `sum` fits into 16 bits.
+    #[allow(clippy::cast_possible_truncation)]
+    let sum = sum as u16;
+    !sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::details::icmp::v4::icmpv4::new_icmpv4_package;
+    use pnet_packet::Packet;
+
+    fn echo_reply(sequence_number: u16) -> Vec<u8> {
+        let mut message = new_icmpv4_package(sequence_number.into(), &[0xAB; 8])
+            .unwrap()
+            .packet()
+            .to_vec();
+        message[0] = ICMP_ECHO_REPLY;
+        message[2..4].fill(0);
+        let checksum = internet_checksum(&message);
+        message[2..4].copy_from_slice(&checksum.to_be_bytes());
+        message
+    }
+
+    fn ipv4_packet(header_length: usize, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; header_length];
+        packet[0] = 0x40 | u8::try_from(header_length / 4).unwrap();
+        packet[2..4].copy_from_slice(&u16::try_from(header_length + payload.len()).unwrap().to_be_bytes());
+        packet[8] = 57;
+        packet[12..16].copy_from_slice(&[192, 0, 2, 1]);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn parse_ipv4_packet_honours_options() {
+        let packet = ipv4_packet(24, &[1, 2, 3]);
+
+        let Ipv4Packet { source, ttl, payload, .. } = parse_ipv4_packet(&packet).unwrap();
+
+        assert_eq!(Ipv4Addr::new(192, 0, 2, 1), source);
+        assert_eq!(57, ttl);
+        assert_eq!([1, 2, 3], payload);
+    }
+
+    #[test]
+    fn parse_ipv4_packet_ignores_trailing_bytes_and_accepts_truncated_payload() {
+        let mut packet = ipv4_packet(20, &[1, 2, 3]);
+        packet.push(4);
+        assert_eq!([1, 2, 3], parse_ipv4_packet(&packet).unwrap().payload);
+
+        let packet = ipv4_packet(20, &[1, 2, 3]);
+        assert_eq!([1, 2], parse_ipv4_packet(&packet[..22]).unwrap().payload);
+    }
+
+    #[test]
+    fn parse_ipv4_packet_rejects_malformed_headers() {
+        let mut packet = ipv4_packet(20, &[]);
+        assert_eq!(
+            Err(ParseError::Truncated { expected: 20, actual: 19 }),
+            parse_ipv4_packet(&packet[..19]).map(|_| ())
+        );
+        packet[0] = 0x65;
+        assert_eq!(
+            Err(ParseError::NotIpv4 { version: 6 }),
+            parse_ipv4_packet(&packet).map(|_| ())
+        );
+        packet[0] = 0x44;
+        assert_eq!(
+            Err(ParseError::InvalidHeaderLength { header_length: 16 }),
+            parse_ipv4_packet(&packet).map(|_| ())
+        );
+        packet[0] = 0x46;
+        assert_eq!(
+            Err(ParseError::InvalidHeaderLength { header_length: 24 }),
+            parse_ipv4_packet(&packet).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn parse_icmp_message_returns_sequence_number_of_echo_reply() {
+        match parse_icmp_message(&echo_reply(7)).unwrap() {
+            IcmpMessage::EchoReply { sequence_number } => assert_eq!(7, u16::from(sequence_number)),
+            IcmpMessage::Other { .. } => panic!("expected an echo reply"),
+        }
+    }
+
+    #[test]
+    fn parse_icmp_message_rejects_truncated_and_corrupted_messages() {
+        let mut message = echo_reply(7);
+        assert_eq!(
+            Err(ParseError::Truncated { expected: 8, actual: 7 }),
+            parse_icmp_message(&message[..7]).map(|_| ())
+        );
+        message[10] ^= 0xff;
+        assert_eq!(Err(ParseError::InvalidChecksum), parse_icmp_message(&message).map(|_| ()));
+    }
+
+    #[test]
+    fn parse_icmp_message_returns_type_and_code_of_other_messages() {
+        let request = new_icmpv4_package(1.into(), &[]).unwrap().packet().to_vec();
+
+        assert!(matches!(
+            parse_icmp_message(&request),
+            Ok(IcmpMessage::Other { icmp_type: 8, icmp_code: 0 })
+        ));
+    }
+
+    #[test]
+    fn parse_addr_rejects_invalid_strings() {
+        assert_eq!(Ok(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))), parse_addr(b"127.0.0.1\0\0\0"));
+        assert_eq!(Err(ParseError::InvalidAddress), parse_addr(b"127.0.0.1"));
+        assert_eq!(Err(ParseError::InvalidAddress), parse_addr(b"\xff\0"));
+        assert_eq!(Err(ParseError::InvalidAddress), parse_addr(b"localhost\0"));
+    }
+
+    #[test]
+    fn internet_checksum_of_odd_number_of_bytes() {
+        assert_eq!(!0x0102u16, internet_checksum(&[0x01, 0x02]));
+        assert_eq!(!0x0302u16, internet_checksum(&[0x01, 0x02, 0x02]));
+    }
+
+    #[test]
+    fn parse_error_is_wrapped_in_invalid_data() {
+        let error = io::Error::from(ParseError::InvalidChecksum);
+
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert_eq!(
+            Some(&ParseError::InvalidChecksum),
+            error.get_ref().and_then(|e| e.downcast_ref())
+        );
+    }
+}
@@ -1,5 +1,5 @@
-use super::TSocket;
-use crate::details::icmp::v4::Ttl;
+use super::super::parse::parse_addr;
+use super::{set_socket_options, TSocket};
 use socket2::{Domain, Protocol, Type};
 use std::{io, os::unix::prelude::AsRawFd, time::Duration};
 
@@ -13,24 +13,25 @@ mod c_icmp_dgram {
 }
 
 pub(crate) struct DgramSocket {
-    socket: socket2::Socket,
+    pub(super) socket: socket2::Socket,
 }
 
 impl DgramSocket {
-    pub(crate) fn new(timeout: Duration) -> Result<Self, io::Error> {
+    pub(crate) fn new(timeout: Duration, ttl: Option<u8>, interface: Option<&str>) -> Result<Self, io::Error> {
         tracing::trace!("creating DgramSocket");
         let socket = socket2::Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4))?;
-        socket.set_read_timeout(Some(timeout)).expect("could not set socket timeout");
+        socket.set_read_timeout(Some(timeout))?;
+        set_socket_options(&socket, ttl, interface)?;
         Ok(DgramSocket { socket })
     }
 }
 
 impl TSocket for DgramSocket {
-    fn send_to(&self, buf: &[u8], addr: &socket2::SockAddr) -> io::Result<usize> {
-        self.socket.send_to(buf, addr)
+    fn send_to(&self, buf: &[u8], addr: std::net::IpAddr) -> io::Result<usize> {
+        self.socket.send_to(buf, &std::net::SocketAddr::new(addr, 0).into())
     }
 
-    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, std::net::IpAddr, Ttl)> {
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, std::net::IpAddr, u8)> {
         let mut icmp_data = c_icmp_dgram::IcmpData {
             data_buffer: buf.as_mut_ptr(),
             data_buffer_size: buf.len() as u64,
@@ -41,6 +42,10 @@ impl TSocket for DgramSocket {
 
         let raw_fd: std::ffi::c_int = self.socket.as_raw_fd();
         let n_bytes_received = unsafe { c_icmp_dgram::recv_from(raw_fd, std::ptr::addr_of_mut!(icmp_data)) };
+        // -2 is a failed `recvmsg`, e.g. a timeout; errno tells which.
+        if n_bytes_received == -2 {
+            return Err(io::Error::last_os_error());
+        }
         if n_bytes_received < 0 {
             return Err(io::Error::new(
                 io::ErrorKind::Other,
@@ -50,24 +55,7 @@ impl TSocket for DgramSocket {
         if n_bytes_received == 0 {
             return Err(io::Error::new(io::ErrorKind::Other, "socket closed"));
         }
-        let addr_str: String = str_from_null_terminated_utf8_safe(&icmp_data.addr_str).to_string();
-        Ok((
-            icmp_data.n_data_bytes_received,
-            addr_str.parse::<std::net::IpAddr>().expect("error reading IP address"),
-            icmp_data.ttl.try_into().expect("error decoding TTL"),
-        ))
-    }
-}
-
-fn str_from_null_terminated_utf8_safe(s: &[u8]) -> &str {
-    if s.iter().any(|&x| x == 0u8) {
-        unsafe { str_from_null_terminated_utf8(s) }
-    } else {
-        std::str::from_utf8(s).unwrap()
+        let ip_addr = parse_addr(&icmp_data.addr_str)?;
+        Ok((icmp_data.n_data_bytes_received, ip_addr, icmp_data.ttl))
     }
 }
-
-// unsafe: s must contain a null byte
-unsafe fn str_from_null_terminated_utf8(s: &[u8]) -> &str {
-    std::ffi::CStr::from_ptr(s.as_ptr().cast()).to_str().unwrap()
-}
@@ -1,4 +1,6 @@
-use crate::{details::icmp::v4::Ttl, SocketType};
+use crate::SocketType;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::os::fd::{AsRawFd, RawFd};
 use std::{io, time::Duration};
 
 use super::{DgramSocket, RawSocket};
@@ -6,37 +8,114 @@ use super::{DgramSocket, RawSocket};
 pub(crate) mod dgram_socket;
 pub(crate) mod raw_socket;
 
-pub(crate) trait TSocket: Send + Sync {
-    fn send_to(&self, buf: &[u8], addr: &socket2::SockAddr) -> io::Result<usize>;
-    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, std::net::IpAddr, Ttl)>;
+/// A transport for ICMP messages.
+///
+/// ping-fox creates its own sockets, see [`crate::SocketType`]. Other transports, e.g. a socket
+/// opened in another network namespace, a file descriptor passed in by systemd, a TUN device or a
+/// userspace network stack, implement `TSocket` and are used with [`crate::create_with_socket`].
+pub trait TSocket: Send + Sync {
+    /// Sends `buf`, a complete ICMP echo request including its checksum, to `addr`. Returns the
+    /// number of bytes sent.
+    ///
+    /// The identifier of the echo request is 0. A transport may replace it like the kernel does
+    /// for DGRAM sockets, as long as `recv_from` only returns replies to its own requests.
+    fn send_to(&self, buf: &[u8], addr: IpAddr) -> io::Result<usize>;
+
+    /// Blocks until an ICMP message arrives and copies it, without the IP header, into `buf`.
+    /// Returns the number of bytes copied, the address of the sender and the time to live (TTL)
+    /// of the IP packet.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::WouldBlock`] when no message arrives within the
+    /// timeout of the transport.
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)>;
+
+    /// Like `recv_from`, but returns an error of kind [`io::ErrorKind::WouldBlock`] at once if no
+    /// message has arrived. [`crate::PingReceiver::try_receive`] calls it for a transport without
+    /// a file descriptor.
+    ///
+    /// The default calls `recv_from`, which waits up to the timeout of the transport.
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+        self.recv_from(buf)
+    }
+
+    /// Returns a file descriptor which is readable when `recv_from` does not block, if the
+    /// transport has one.
+    ///
+    /// A [`crate::ShutdownHandle`] wakes up a receiver waiting on this file descriptor at once.
+    /// Without it, the receiver notices a shutdown only when `recv_from` returns.
+    fn raw_fd(&self) -> Option<RawFd> {
+        None
+    }
 }
 
 pub(crate) enum Socket {
     Raw(RawSocket),
     Dgram(DgramSocket),
+    Custom(Box<dyn TSocket>),
 }
 
 impl Socket {
-    pub(crate) fn new(socket_type: SocketType, timeout: Duration) -> Result<Self, io::Error> {
+    pub(crate) fn new(
+        socket_type: SocketType,
+        timeout: Duration,
+        ttl: Option<u8>,
+        interface: Option<&str>,
+    ) -> Result<Self, io::Error> {
         match socket_type {
-            SocketType::DGRAM => Ok(Socket::Dgram(DgramSocket::new(timeout)?)),
-            SocketType::RAW => Ok(Socket::Raw(RawSocket::new(timeout)?)),
+            SocketType::DGRAM => Ok(Socket::Dgram(DgramSocket::new(timeout, ttl, interface)?)),
+            SocketType::RAW => Ok(Socket::Raw(RawSocket::new(timeout, ttl, interface)?)),
         }
     }
 }
 
+// Applies the options shared by all socket types. An `interface` is either a local IPv4 address
+// to send from or the name of a network interface.
+pub(crate) fn set_socket_options(
+    socket: &socket2::Socket,
+    ttl: Option<u8>,
+    interface: Option<&str>,
+) -> Result<(), io::Error> {
+    if let Some(ttl) = ttl {
+        socket.set_ttl(u32::from(ttl))?;
+    }
+    match interface.map(|interface| (interface, interface.parse::<Ipv4Addr>())) {
+        None => {}
+        Some((_, Ok(source_addr))) => socket.bind(&SocketAddr::from((source_addr, 0)).into())?,
+        Some((interface, Err(_))) => socket.bind_device(Some(interface.as_bytes()))?,
+    }
+    Ok(())
+}
+
 impl TSocket for Socket {
-    fn send_to(&self, buf: &[u8], addr: &socket2::SockAddr) -> io::Result<usize> {
+    fn send_to(&self, buf: &[u8], addr: IpAddr) -> io::Result<usize> {
         match self {
             Socket::Dgram(socket) => socket.send_to(buf, addr),
             Socket::Raw(socket) => socket.send_to(buf, addr),
+            Socket::Custom(socket) => socket.send_to(buf, addr),
         }
     }
 
-    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, std::net::IpAddr, Ttl)> {
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
         match self {
             Socket::Dgram(socket) => socket.recv_from(buf),
             Socket::Raw(socket) => socket.recv_from(buf),
+            Socket::Custom(socket) => socket.recv_from(buf),
+        }
+    }
+
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+        match self {
+            Socket::Dgram(socket) => socket.try_recv_from(buf),
+            Socket::Raw(socket) => socket.try_recv_from(buf),
+            Socket::Custom(socket) => socket.try_recv_from(buf),
+        }
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        match self {
+            Socket::Dgram(socket) => Some(socket.socket.as_raw_fd()),
+            Socket::Raw(socket) => Some(socket.socket.as_raw_fd()),
+            Socket::Custom(socket) => socket.raw_fd(),
         }
     }
 }
@@ -121,20 +200,15 @@ pub(crate) mod tests {
     }
 
     impl TSocket for SocketMock {
-        fn send_to(&self, buf: &[u8], addr: &socket2::SockAddr) -> io::Result<usize> {
+        fn send_to(&self, buf: &[u8], addr: IpAddr) -> io::Result<usize> {
             if self.on_send == OnSend::ReturnErr {
                 return Err(io::Error::new(io::ErrorKind::Other, "simulating error in mock"));
             }
-            self.sent.lock().unwrap().push((
-                buf.to_vec(),
-                addr.as_socket()
-                    .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "error in extracting IP address from SockAddr"))?
-                    .ip(),
-            ));
+            self.sent.lock().unwrap().push((buf.to_vec(), addr));
             Ok(buf.len())
         }
 
-        fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, Ttl)> {
+        fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
             let on_receive: OnReceive = *self.on_receive.lock().unwrap();
             match on_receive {
                 OnReceive::ReturnWouldBlock => {
@@ -169,7 +243,7 @@ pub(crate) mod tests {
             }
             buf[..package_bytes.len()].copy_from_slice(package_bytes);
 
-            Ok((package.packet_size(), "127.0.0.1".parse::<IpAddr>().unwrap(), Ttl(128)))
+            Ok((package.packet_size(), "127.0.0.1".parse::<IpAddr>().unwrap(), 128))
         }
     }
 }
@@ -1,29 +1,32 @@
-use super::TSocket;
-use crate::details::icmp::v4::Ttl;
-use pnet_packet::{ipv4::Ipv4Packet, Packet};
+use super::super::parse::parse_ipv4_packet;
+use super::{set_socket_options, TSocket};
 use socket2::{Domain, Protocol, Type};
 use std::{io, time::Duration};
 
+// The IHL field of an IPv4 header allows for at most 15 32-bit words.
+const MAX_IPV4_HEADER_SIZE: usize = 60;
+
 pub(crate) struct RawSocket {
-    socket: socket2::Socket,
+    pub(super) socket: socket2::Socket,
 }
 
 impl RawSocket {
-    pub(crate) fn new(timeout: Duration) -> Result<Self, io::Error> {
+    pub(crate) fn new(timeout: Duration, ttl: Option<u8>, interface: Option<&str>) -> Result<Self, io::Error> {
         tracing::trace!("creating RawSocket");
         let socket = socket2::Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?;
-        socket.set_read_timeout(Some(timeout)).expect("could not set socket timeout");
+        socket.set_read_timeout(Some(timeout))?;
+        set_socket_options(&socket, ttl, interface)?;
         Ok(RawSocket { socket })
     }
 }
 
 impl TSocket for RawSocket {
-    fn send_to(&self, buf: &[u8], addr: &socket2::SockAddr) -> io::Result<usize> {
-        self.socket.send_to(buf, addr)
+    fn send_to(&self, buf: &[u8], addr: std::net::IpAddr) -> io::Result<usize> {
+        self.socket.send_to(buf, &std::net::SocketAddr::new(addr, 0).into())
     }
 
-    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, std::net::IpAddr, Ttl)> {
-        let mut recv_buf = [0u8; 256];
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, std::net::IpAddr, u8)> {
+        let mut recv_buf = vec![0u8; MAX_IPV4_HEADER_SIZE + buf.len()];
 
         // Socket2 gives a safety guaranty which allows us to do an unsafe cast from `&mut [u8]`
         // to `&mut [std::mem::MaybeUninit<u8>]`.
@@ -31,17 +34,16 @@ impl TSocket for RawSocket {
         //
         // In fact, even if we would use MaybeUninit here we would have
         // to use unsafe somewhere to copy the data out of MaybeUninit.
-        let (_, socket_addr) = socket2::Socket::recv_from(&self.socket, unsafe {
-            &mut *(std::ptr::addr_of_mut!(recv_buf) as *mut [u8] as *mut [std::mem::MaybeUninit<u8>])
+        let (n_bytes_received, _) = socket2::Socket::recv_from(&self.socket, unsafe {
+            &mut *(std::ptr::addr_of_mut!(recv_buf[..]) as *mut [std::mem::MaybeUninit<u8>])
         })?;
         // On a RAW socket we get an IP packet.
-        let ipv4_packet = Ipv4Packet::new(&recv_buf).expect("could not initialize IPv4 package");
-        let ip_payload: &[u8] = ipv4_packet.payload();
+        let ipv4_packet = parse_ipv4_packet(&recv_buf[..n_bytes_received])?;
+        let ip_payload: &[u8] = ipv4_packet.payload;
         if ip_payload.len() > buf.len() {
             return Err(io::Error::new(io::ErrorKind::Other, "recveive buffer too small"));
         }
         buf[..ip_payload.len()].copy_from_slice(ip_payload);
-        let ip = *socket_addr.as_socket_ipv4().expect("logic error").ip();
-        Ok((ip_payload.len(), std::net::IpAddr::V4(ip), ipv4_packet.get_ttl().into()))
+        Ok((ip_payload.len(), std::net::IpAddr::V4(ipv4_packet.source), ipv4_packet.ttl))
     }
 }
@@ -0,0 +1,228 @@
+use crate::details::icmp::v4::SequenceNumber;
+use crate::details::records::PingSendRecord;
+use crate::details::sync;
+use crate::Clock;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use std::{error::Error, fmt};
+
+/// Error returned by [`PingSender::send_to`](crate::PingSender::send_to) instead of sending a ping
+/// while [`PingFoxConfig::channel_size`](crate::PingFoxConfig::channel_size) pings are in flight.
+///
+/// A ping is in flight from sending until a [`PingReceiver`](crate::PingReceiver) matches its
+/// reply, or until it is older than the timeout without a reply. Receiving makes room for further
+/// pings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WouldExceedInFlight {
+    /// The maximum number of pings in flight.
+    pub max_in_flight: usize,
+}
+
+impl fmt::Display for WouldExceedInFlight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "sending would exceed the maximum of {} pings in flight",
+            self.max_in_flight
+        )
+    }
+}
+
+impl Error for WouldExceedInFlight {}
+
+struct InFlightRecord {
+    payload_size: usize,
+    send_time: Instant,
+}
+
+#[derive(Default)]
+struct InFlightState {
+    records: HashMap<(SequenceNumber, IpAddr), InFlightRecord>,
+    // Pings which are being sent and have no record yet.
+    n_reserved: usize,
+}
+
+// The pings in flight, shared by a `PingSender` and a `PingReceiver`.
+//
+// The sender reserves room for a ping before sending it and inserts the send record afterwards.
+// It never blocks: if there is no room, the reservation fails. The receiver removes the record of
+// each reply it matches. Records of pings without a reply are evicted once they are older than
+// `expiry` on `clock`, the clock of the send times, and room is needed.
+pub(crate) struct InFlightTable {
+    state: Mutex<InFlightState>,
+    record_inserted: Condvar,
+    max_in_flight: usize,
+    expiry: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl InFlightTable {
+    pub(crate) fn new(max_in_flight: usize, expiry: Duration, clock: Arc<dyn Clock>) -> Self {
+        InFlightTable {
+            state: Mutex::new(InFlightState::default()),
+            record_inserted: Condvar::new(),
+            max_in_flight: max_in_flight.max(1),
+            expiry,
+            clock,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, InFlightState> {
+        sync::lock(&self.state)
+    }
+
+    pub(crate) fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
+        let state = self.lock();
+        state.records.len() + state.n_reserved
+    }
+
+    pub(crate) fn try_reserve(&self) -> Result<Reservation<'_>, WouldExceedInFlight> {
+        let mut state = self.lock();
+        if state.records.len() + state.n_reserved >= self.max_in_flight {
+            let now = self.clock.now();
+            state
+                .records
+                .retain(|_, record| now.saturating_duration_since(record.send_time) < self.expiry);
+        }
+        if state.records.len() + state.n_reserved >= self.max_in_flight {
+            return Err(WouldExceedInFlight { max_in_flight: self.max_in_flight });
+        }
+        state.n_reserved += 1;
+        Ok(Reservation { table: self })
+    }
+
+    // Removes the record of the ping with `sequence_number` to `ip_addr` and returns its payload
+    // size and send time. A reply can arrive before the sender has inserted the record of its
+    // request, so this waits up to `wait` while pings are being sent.
+    pub(crate) fn take(&self, sequence_number: SequenceNumber, ip_addr: IpAddr, wait: Duration) -> Option<(usize, Instant)> {
+        let deadline = Instant::now() + wait;
+        let mut state = self.lock();
+        loop {
+            if let Some(record) = state.records.remove(&(sequence_number, ip_addr)) {
+                return Some((record.payload_size, record.send_time));
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if state.n_reserved == 0 || timeout.is_zero() {
+                return None;
+            }
+            state = self
+                .record_inserted
+                .wait_timeout(state, timeout)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+}
+
+// Room for one ping in an `InFlightTable`. It is given back when dropped without a record.
+pub(crate) struct Reservation<'a> {
+    table: &'a InFlightTable,
+}
+
+impl Reservation<'_> {
+    pub(crate) fn insert(self, send_record: &PingSendRecord) {
+        let PingSendRecord { payload_size, ip_addr, sequence_number, send_time } = *send_record;
+        let mut state = self.table.lock();
+        state
+            .records
+            .insert((sequence_number, ip_addr), InFlightRecord { payload_size, send_time });
+        state.n_reserved -= 1;
+        drop(state);
+        self.table.record_inserted.notify_all();
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.table.lock().n_reserved -= 1;
+        self.table.record_inserted.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ManualClock, SystemClock};
+    use std::net::Ipv4Addr;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn send_record(sequence_number: u16) -> PingSendRecord {
+        send_record_at(sequence_number, Instant::now())
+    }
+
+    fn send_record_at(sequence_number: u16, send_time: Instant) -> PingSendRecord {
+        PingSendRecord { payload_size: 56, ip_addr: LOCALHOST, sequence_number: sequence_number.into(), send_time }
+    }
+
+    fn table(max_in_flight: usize) -> InFlightTable {
+        InFlightTable::new(max_in_flight, Duration::from_secs(1), Arc::new(SystemClock))
+    }
+
+    #[test]
+    fn try_reserve_fails_when_table_is_full() {
+        let table = table(2);
+
+        table.try_reserve().unwrap().insert(&send_record(1));
+        let reservation = table.try_reserve().unwrap();
+        assert_eq!(2, table.in_flight());
+        assert_eq!(Some(WouldExceedInFlight { max_in_flight: 2 }), table.try_reserve().err());
+
+        drop(reservation);
+        assert_eq!(1, table.in_flight());
+        assert!(table.try_reserve().is_ok());
+    }
+
+    #[test]
+    fn take_makes_room() {
+        let table = table(1);
+        table.try_reserve().unwrap().insert(&send_record(1));
+
+        assert_eq!(
+            Some(56),
+            table.take(1.into(), LOCALHOST, Duration::ZERO).map(|(size, _)| size)
+        );
+        assert_eq!(None, table.take(1.into(), LOCALHOST, Duration::ZERO));
+        assert_eq!(0, table.in_flight());
+    }
+
+    #[test]
+    fn expired_records_are_evicted_when_room_is_needed() {
+        let clock = Arc::new(ManualClock::new());
+        let table = InFlightTable::new(1, Duration::from_millis(10), clock.clone());
+        table.try_reserve().unwrap().insert(&send_record_at(1, clock.now()));
+
+        clock.advance(Duration::from_millis(9));
+        assert!(table.try_reserve().is_err());
+        clock.advance(Duration::from_millis(1));
+
+        assert!(table.try_reserve().is_ok());
+        assert_eq!(None, table.take(1.into(), LOCALHOST, Duration::ZERO));
+    }
+
+    #[test]
+    fn take_waits_for_record_of_ping_being_sent() {
+        let table = Arc::new(table(1));
+
+        let sender_table = table.clone();
+        let (reserved_tx, reserved_rx) = std::sync::mpsc::channel();
+        let sender = std::thread::spawn(move || {
+            let reservation = sender_table.try_reserve().unwrap();
+            reserved_tx.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+            reservation.insert(&send_record(1));
+        });
+        reserved_rx.recv().unwrap();
+
+        assert!(table.take(1.into(), LOCALHOST, Duration::from_secs(1)).is_some());
+        sender.join().unwrap();
+    }
+}
@@ -1,15 +1,28 @@
-use generic_error::GenericError;
+pub(crate) use generic_error::GenericError;
 pub(crate) use ping_data_buffer::PingDataBuffer;
 use ping_error::PingError;
 pub(crate) use ping_receiver::PingReceiver;
 pub(crate) use ping_result::PingResult;
 pub(crate) use ping_sender::PingSender;
+pub(crate) use probe::Probe;
+pub(crate) use probe::TProbe;
+pub(crate) use shutdown::SharedProbe;
 
 mod generic_error;
 pub(crate) mod icmp;
+pub(crate) mod in_flight;
+pub(crate) mod netns;
+pub(crate) mod pcap;
 mod ping_data_buffer;
 mod ping_error;
 mod ping_receiver;
 mod ping_result;
 mod ping_sender;
+mod probe;
 pub(crate) mod records;
+#[cfg(feature = "serde")]
+pub(crate) mod serde_with;
+pub(crate) mod shutdown;
+pub(crate) mod sync;
+pub(crate) mod tcp;
+pub(crate) mod udp;
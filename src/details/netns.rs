@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io;
+use std::os::unix::prelude::AsRawFd;
+use std::path::Path;
+
+// A Linux network namespace, e.g. `/var/run/netns/blue` or `/proc/<pid>/ns/net`.
+//
+// A socket stays in the network namespace it was created in. `setns` only changes the namespace
+// of the calling thread, so sockets are created on short-lived helper threads and the threads of
+// the caller never leave their namespace.
+pub(crate) struct NetworkNamespace {
+    file: File,
+}
+
+impl NetworkNamespace {
+    pub(crate) fn open(path: &Path) -> Result<Self, io::Error> {
+        tracing::trace!("opening network namespace {}", path.display());
+        let file = File::open(path)?;
+        Ok(NetworkNamespace { file })
+    }
+
+    // Moves the calling thread into the network namespace. Needs CAP_SYS_ADMIN.
+    pub(crate) fn enter(&self) -> Result<(), io::Error> {
+        if unsafe { libc::setns(self.file.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // Runs `f` on a helper thread inside the network namespace and returns its result.
+    pub(crate) fn run<T, F>(&self, f: F) -> Result<T, io::Error>
+    where
+        T: Send,
+        F: FnOnce() -> Result<T, io::Error> + Send,
+    {
+        std::thread::scope(|scope| {
+            std::thread::Builder::new()
+                .name("ping-fox-netns".to_owned())
+                .spawn_scoped(scope, || {
+                    self.enter()?;
+                    f()
+                })?
+                .join()
+                .map_err(|_| io::Error::other("network namespace helper thread panicked"))?
+        })
+    }
+}
+
+// Runs `f` inside `network_namespace`, or on the calling thread if there is none.
+pub(crate) fn run_in<T, F>(network_namespace: Option<&NetworkNamespace>, f: F) -> Result<T, io::Error>
+where
+    T: Send,
+    F: FnOnce() -> Result<T, io::Error> + Send,
+{
+    match network_namespace {
+        Some(network_namespace) => network_namespace.run(f),
+        None => f(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn when_path_does_not_exist_then_open_fails() {
+        let result = NetworkNamespace::open(Path::new("/var/run/netns/ping-fox-does-not-exist"));
+
+        assert_eq!(io::ErrorKind::NotFound, result.err().unwrap().kind());
+    }
+
+    #[test]
+    fn when_path_is_not_a_network_namespace_then_run_fails() {
+        let network_namespace = NetworkNamespace::open(Path::new("/dev/null")).unwrap();
+
+        let result = network_namespace.run(|| Ok(()));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn when_there_is_no_network_namespace_then_run_in_runs_on_calling_thread() {
+        let thread_id = std::thread::current().id();
+
+        let result = run_in(None, || Ok(std::thread::current().id()));
+
+        assert_eq!(thread_id, result.unwrap());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::details::icmp::v4::parse::internet_checksum;
+use crate::details::sync::lock;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(crate) const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+const PCAP_SNAPLEN: u32 = 65535;
+// Packets start with an IPv4 or IPv6 header, without a link-layer header.
+pub(crate) const LINKTYPE_RAW: u32 = 101;
+
+pub(crate) const IPV4_HEADER_SIZE: usize = 20;
+pub(crate) const IP_PROTOCOL_ICMP: u8 = 1;
+
+// Writes ICMP messages to a file in the pcap format, which Wireshark and tcpdump can read.
+//
+// Sockets return ICMP messages without their IP header (see `TSocket`), so an IPv4 header is
+// synthesized for each message. The local address is unknown and written as 0.0.0.0.
+pub(crate) struct PcapWriter {
+    writer: Mutex<BufWriter<File>>,
+    outgoing_ttl: u8,
+}
+
+impl PcapWriter {
+    // Creates or truncates the file at `path` and writes the pcap header. `outgoing_ttl` is the
+    // TTL written into the IP headers of outgoing messages.
+    pub(crate) fn create(path: &Path, outgoing_ttl: u8) -> Result<Self, io::Error> {
+        tracing::trace!("creating pcap file {}", path.display());
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&PCAP_MAGIC.to_ne_bytes())?;
+        writer.write_all(&PCAP_VERSION_MAJOR.to_ne_bytes())?;
+        writer.write_all(&PCAP_VERSION_MINOR.to_ne_bytes())?;
+        // Time zone offset and accuracy of time stamps.
+        writer.write_all(&0i32.to_ne_bytes())?;
+        writer.write_all(&0u32.to_ne_bytes())?;
+        writer.write_all(&PCAP_SNAPLEN.to_ne_bytes())?;
+        writer.write_all(&LINKTYPE_RAW.to_ne_bytes())?;
+        writer.flush()?;
+        Ok(PcapWriter { writer: Mutex::new(writer), outgoing_ttl })
+    }
+
+    pub(crate) fn write_outgoing(&self, destination: Ipv4Addr, icmp_message: &[u8]) -> Result<(), io::Error> {
+        self.write_packet(Ipv4Addr::UNSPECIFIED, destination, self.outgoing_ttl, icmp_message)
+    }
+
+    pub(crate) fn write_incoming(&self, source: Ipv4Addr, ttl: u8, icmp_message: &[u8]) -> Result<(), io::Error> {
+        self.write_packet(source, Ipv4Addr::UNSPECIFIED, ttl, icmp_message)
+    }
+
+    // Every packet is flushed, so that the file is complete even if the process is killed.
+    fn write_packet(&self, source: Ipv4Addr, destination: Ipv4Addr, ttl: u8, icmp_message: &[u8]) -> Result<(), io::Error> {
+        let packet = ipv4_packet(source, destination, ttl, icmp_message)?;
+        let packet_size =
+            u32::try_from(packet.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "packet too large"))?;
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        // The seconds field of a pcap record is 32 bits wide and overflows in 2106.
+        #[allow(clippy::cast_possible_truncation)]
+        let seconds = since_epoch.as_secs() as u32;
+
+        let mut writer = lock(&self.writer);
+        writer.write_all(&seconds.to_ne_bytes())?;
+        writer.write_all(&since_epoch.subsec_micros().to_ne_bytes())?;
+        writer.write_all(&packet_size.to_ne_bytes())?;
+        writer.write_all(&packet_size.to_ne_bytes())?;
+        writer.write_all(&packet)?;
+        writer.flush()
+    }
+}
+
+// Prepends an IPv4 header to `icmp_message`.
+fn ipv4_packet(source: Ipv4Addr, destination: Ipv4Addr, ttl: u8, icmp_message: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let total_length = u16::try_from(IPV4_HEADER_SIZE + icmp_message.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "ICMP message too large for an IPv4 packet"))?;
+    let mut packet = Vec::with_capacity(usize::from(total_length));
+    // Version 4, header length of five 32-bit words, no type of service.
+    packet.extend_from_slice(&[0x45, 0x00]);
+    packet.extend_from_slice(&total_length.to_be_bytes());
+    // Identification, flags and fragment offset.
+    packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+    packet.extend_from_slice(&[ttl, IP_PROTOCOL_ICMP]);
+    // The header checksum is filled in below.
+    packet.extend_from_slice(&[0x00, 0x00]);
+    packet.extend_from_slice(&source.octets());
+    packet.extend_from_slice(&destination.octets());
+    let checksum = internet_checksum(&packet);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+    packet.extend_from_slice(icmp_message);
+    Ok(packet)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    pub(crate) const PCAP_HEADER_SIZE: usize = 24;
+    pub(crate) const PCAP_RECORD_HEADER_SIZE: usize = 16;
+
+    pub(crate) fn temp_pcap_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ping-fox-{}-{name}.pcap", std::process::id()))
+    }
+
+    #[test]
+    fn create_writes_pcap_header() {
+        let path = temp_pcap_path("header");
+
+        PcapWriter::create(&path, 64).unwrap();
+
+        let content = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(PCAP_HEADER_SIZE, content.len());
+        assert_eq!(PCAP_MAGIC.to_ne_bytes(), content[0..4]);
+        assert_eq!(LINKTYPE_RAW.to_ne_bytes(), content[20..24]);
+    }
+
+    #[test]
+    fn write_outgoing_synthesizes_ipv4_header() {
+        let path = temp_pcap_path("outgoing");
+        let pcap_writer = PcapWriter::create(&path, 42).unwrap();
+        let icmp_message = [8u8, 0, 0xf7, 0xfe, 0, 0, 0, 1];
+
+        pcap_writer
+            .write_outgoing(Ipv4Addr::new(192, 0, 2, 1), &icmp_message)
+            .unwrap();
+
+        let content = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let record = &content[PCAP_HEADER_SIZE..];
+        let packet_size = IPV4_HEADER_SIZE + icmp_message.len();
+        assert_eq!(PCAP_RECORD_HEADER_SIZE + packet_size, record.len());
+        assert_eq!(u32::try_from(packet_size).unwrap().to_ne_bytes(), record[8..12]);
+        let packet = &record[PCAP_RECORD_HEADER_SIZE..];
+        assert_eq!(0x45, packet[0]);
+        assert_eq!(42, packet[8]);
+        assert_eq!(IP_PROTOCOL_ICMP, packet[9]);
+        assert_eq!([0, 0, 0, 0], packet[12..16]);
+        assert_eq!([192, 0, 2, 1], packet[16..20]);
+        assert_eq!(0, internet_checksum(&packet[..IPV4_HEADER_SIZE]));
+        assert_eq!(icmp_message, packet[IPV4_HEADER_SIZE..]);
+    }
+}
@@ -1,49 +1,94 @@
-use crate::details::icmp::v4::SequenceNumber;
+use crate::details::in_flight::InFlightTable;
 use crate::details::ping_error::PingError;
 use crate::details::records::PingReceiveRecordData;
-use crate::details::records::PingSendRecord;
-use crate::details::records::PingSendRecordReceiver;
 use crate::details::PingResult;
 use crate::PingReceiveData;
-use std::collections::HashMap;
-use std::net::IpAddr;
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::Duration;
+
+// A reply can arrive before the sender has inserted the send record of its request. This is how
+// long the receiver waits for such a send record.
+const SEND_RECORD_WAIT: Duration = Duration::from_millis(100);
 
 pub(crate) struct PingDataBuffer {
-    ping_send_record_rx: PingSendRecordReceiver,
-    send_records: HashMap<(SequenceNumber, IpAddr), (usize, Instant)>,
+    in_flight: Arc<InFlightTable>,
 }
 
 impl PingDataBuffer {
-    pub(crate) fn new(ping_send_record_rx: PingSendRecordReceiver) -> Self {
-        Self { ping_send_record_rx, send_records: HashMap::new() }
+    pub(crate) fn new(in_flight: Arc<InFlightTable>) -> Self {
+        Self { in_flight }
     }
 
-    pub(crate) fn process_send_records(&mut self) -> usize {
-        let mut n_send_records: usize = 0;
-        while let Ok(send_record) = self.ping_send_record_rx.try_recv() {
-            let PingSendRecord { payload_size, ip_addr, sequence_number, send_time } = send_record;
-            self.send_records
-                .insert((sequence_number, ip_addr), (payload_size, send_time));
-            n_send_records += 1;
+    pub(crate) fn process_receive_record(&mut self, data: &PingReceiveRecordData) -> PingResult<PingReceiveData> {
+        let PingReceiveRecordData { package_size, ip_addr, ttl, sequence_number, receive_time, probe_kind } = *data;
+        match self.in_flight.take(sequence_number, ip_addr, SEND_RECORD_WAIT) {
+            None => Err(PingError { message: "could not find matching data in send-records buffer".to_owned() }.into()),
+            Some((_payload_size, send_time)) => Ok(PingReceiveData {
+                package_size,
+                ip_addr,
+                ttl: ttl.into(),
+                sequence_number: sequence_number.into(),
+                ping_duration: receive_time - send_time,
+                probe_kind,
+            }),
         }
-        n_send_records
     }
+}
 
-    pub(crate) fn process_receive_record(&mut self, data: &PingReceiveRecordData) -> PingResult<PingReceiveData> {
-        let PingReceiveRecordData { package_size, ip_addr, ttl, sequence_number, receive_time } = *data;
-        match self.send_records.get(&(sequence_number, ip_addr)) {
-            None => Err(PingError { message: "could not find matching data in send-records buffer".to_owned() }.into()),
-            Some(&(_payload_size, send_time)) => {
-                self.send_records.remove(&(sequence_number, ip_addr));
-                Ok(PingReceiveData {
-                    package_size,
-                    ip_addr,
-                    ttl: ttl.into(),
-                    sequence_number: sequence_number.into(),
-                    ping_duration: receive_time - send_time,
-                })
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::details::icmp::v4::SequenceNumber;
+    use crate::details::icmp::v4::Ttl;
+    use crate::details::records::PingSendRecord;
+    use crate::ProbeKind;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    fn receive_record(sequence_number: SequenceNumber, ip_addr: IpAddr) -> PingReceiveRecordData {
+        PingReceiveRecordData {
+            package_size: 64,
+            ip_addr,
+            ttl: Ttl(64),
+            sequence_number,
+            receive_time: Instant::now(),
+            probe_kind: ProbeKind::Icmp,
         }
     }
+
+    #[test]
+    fn when_send_record_arrives_late_then_receive_record_is_matched() {
+        let in_flight = Arc::new(InFlightTable::new(1, Duration::from_secs(1), Arc::new(crate::SystemClock)));
+        let mut ping_data_buffer = PingDataBuffer::new(in_flight.clone());
+        let ip_addr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let sequence_number = SequenceNumber::start_value();
+        let send_time = Instant::now();
+
+        let (reserved_tx, reserved_rx) = std::sync::mpsc::channel();
+        let sender = std::thread::spawn(move || {
+            let reservation = in_flight.try_reserve().unwrap();
+            reserved_tx.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+            reservation.insert(&PingSendRecord { payload_size: 56, ip_addr, sequence_number, send_time });
+        });
+        reserved_rx.recv().unwrap();
+        let data = ping_data_buffer.process_receive_record(&receive_record(sequence_number, ip_addr));
+        sender.join().unwrap();
+
+        assert_eq!(u16::from(sequence_number), data.unwrap().sequence_number);
+    }
+
+    #[test]
+    fn when_send_record_is_missing_then_process_receive_record_fails() {
+        let mut ping_data_buffer = PingDataBuffer::new(Arc::new(InFlightTable::new(
+            1,
+            Duration::from_secs(1),
+            Arc::new(crate::SystemClock),
+        )));
+        let ip_addr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        let data = ping_data_buffer.process_receive_record(&receive_record(SequenceNumber::start_value(), ip_addr));
+
+        assert!(data.is_err());
+    }
 }
@@ -1,55 +1,141 @@
-use crate::details::icmp::v4::IcmpV4;
-use crate::details::icmp::v4::TSocket;
 use crate::details::ping_data_buffer::PingDataBuffer;
 use crate::details::records::PingReceiveRecord;
+use crate::details::shutdown::Readiness;
 use crate::details::PingResult;
+use crate::details::SharedProbe;
+use crate::details::TProbe;
+use crate::Clock;
 use crate::PingReceive;
 use crate::PingSentToken;
-use std::sync::Arc;
+use std::io;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-pub(crate) struct PingReceiver<S> {
-    icmpv4: Arc<IcmpV4<S>>,
+pub(crate) struct PingReceiver<P> {
+    shared_probe: Arc<SharedProbe<P>>,
     ping_data_buffer: PingDataBuffer,
+    timeout: Duration,
+    // The clock of the probe, on which the deadlines of receives are measured.
+    clock: Arc<dyn Clock>,
+    // Created on first use, since most receivers are never waited for in an event loop.
+    readiness: OnceLock<OwnedFd>,
 }
 
-impl<S> PingReceiver<S>
+impl<P> PingReceiver<P>
 where
-    S: TSocket + 'static,
+    P: TProbe + 'static,
 {
-    pub(crate) fn new(icmpv4: Arc<IcmpV4<S>>, ping_data_buffer: PingDataBuffer) -> Self {
-        PingReceiver { icmpv4, ping_data_buffer }
+    pub(crate) fn new(
+        shared_probe: Arc<SharedProbe<P>>,
+        ping_data_buffer: PingDataBuffer,
+        timeout: Duration,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        PingReceiver { shared_probe, ping_data_buffer, timeout, clock, readiness: OnceLock::new() }
+    }
+
+    pub(crate) fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub(crate) fn shared_probe(&self) -> &Arc<SharedProbe<P>> {
+        &self.shared_probe
+    }
+
+    #[cfg(feature = "async-io")]
+    pub(crate) fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // Returns whether the probe has a socket with a file descriptor, which `try_receive` does not
+    // block on.
+    #[cfg(feature = "async-io")]
+    pub(crate) fn has_socket_fd(&self) -> bool {
+        self.shared_probe.get().is_some_and(|probe| probe.raw_fd().is_some())
+    }
+
+    // Returns a file descriptor which is readable while `try_receive` may return something.
+    pub(crate) fn readiness_fd(&self) -> io::Result<BorrowedFd<'_>> {
+        if let Some(fd) = self.readiness.get() {
+            return Ok(fd.as_fd());
+        }
+        let fd = self.shared_probe.new_epoll()?;
+        Ok(self.readiness.get_or_init(|| fd).as_fd())
     }
 
     #[allow(clippy::needless_pass_by_value)]
-    fn receive_aux(&self, token: PingSentToken) -> PingResult<PingReceiveRecord> {
+    fn receive_aux(&self, token: PingSentToken, timeout: Duration) -> PingResult<PingReceiveRecord> {
         let _ = token;
-        // (2) Receive on socket.
-        let recv_echo_result = self.icmpv4.try_receive();
-        match recv_echo_result {
-            Ok(None) => {
-                // Timeout: nothing received.
-                Ok(PingReceiveRecord::Timeout)
-            }
-            Err(e) => Err(e.into()),
-            Ok(Some(ping_receive_data)) => {
-                tracing::trace!("icmpv4 received");
-                // (3) Send ping-received-record.
-                Ok(PingReceiveRecord::Data(ping_receive_data))
+        let Some(probe) = self.shared_probe.get() else {
+            return Ok(PingReceiveRecord::Closed);
+        };
+        let deadline = self.clock.now() + timeout;
+        loop {
+            // (1) Wait for the socket, unless the probe has none to wait for. Only the wait itself
+            // is bounded in real time.
+            if let Some(raw_fd) = probe.raw_fd() {
+                match self
+                    .shared_probe
+                    .poll(raw_fd, deadline.saturating_duration_since(self.clock.now()))?
+                {
+                    Readiness::ShutDown => return Ok(PingReceiveRecord::Closed),
+                    Readiness::TimedOut => return Ok(PingReceiveRecord::Timeout),
+                    Readiness::Readable => {}
+                }
             }
+            // (2) Receive on socket. Without time left, a probe without a file descriptor does not
+            // wait either.
+            let recv_echo_result = if timeout.is_zero() {
+                probe.try_receive_now()
+            } else {
+                probe.try_receive()
+            };
+            return match recv_echo_result {
+                // A probe without a file descriptor is woken up by the shutdown.
+                Ok(None) | Err(_) if self.shared_probe.is_shut_down() => Ok(PingReceiveRecord::Closed),
+                // A skipped message, e.g. an ICMP error other than a reply. The socket is waited
+                // for again, so that a receive does not block on it after the deadline.
+                Ok(None) if probe.raw_fd().is_some() => continue,
+                Ok(None) => {
+                    // Timeout: nothing received.
+                    Ok(PingReceiveRecord::Timeout)
+                }
+                Err(e) => Err(e.into()),
+                Ok(Some(ping_receive_data)) => {
+                    tracing::trace!("probe received");
+                    // (3) Send ping-received-record.
+                    Ok(PingReceiveRecord::Data(ping_receive_data))
+                }
+            };
         }
     }
 
     pub(crate) fn receive(&mut self, token: PingSentToken) -> PingResult<PingReceive> {
-        match self.receive_aux(token) {
+        self.receive_within(token, self.timeout)
+    }
+
+    // Like `receive`, but waits up to `timeout` instead of the configured timeout. A probe
+    // without a file descriptor waits for its own timeout unless `timeout` is zero.
+    pub(crate) fn receive_within(&mut self, token: PingSentToken, timeout: Duration) -> PingResult<PingReceive> {
+        match self.receive_aux(token, timeout) {
             Err(e) => Err(e),
             Ok(PingReceiveRecord::Timeout) => Ok(PingReceive::Timeout),
+            Ok(PingReceiveRecord::Closed) => Ok(PingReceive::Closed),
             Ok(PingReceiveRecord::Data(data)) => {
-                let _ = self.ping_data_buffer.process_send_records();
                 let output = self.ping_data_buffer.process_receive_record(&data)?;
                 Ok(PingReceive::Data(output))
             }
         }
     }
+
+    // Returns `None` at once if there is nothing to receive.
+    pub(crate) fn try_receive(&mut self) -> PingResult<Option<PingReceive>> {
+        match self.receive_within(PingSentToken {}, Duration::ZERO)? {
+            PingReceive::Timeout => Ok(None),
+            outcome => Ok(Some(outcome)),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -58,20 +144,32 @@ mod tests {
     use crate::details::icmp::v4::tests::OnReceive;
     use crate::details::icmp::v4::tests::OnSend;
     use crate::details::icmp::v4::tests::SocketMock;
-    use crate::details::records::ping_send_record_channel;
+    use crate::details::icmp::v4::IcmpV4;
+    use crate::details::in_flight::InFlightTable;
     use crate::details::records::PingReceiveRecord;
 
+    fn ping_receiver(socket: SocketMock) -> PingReceiver<IcmpV4<SocketMock>> {
+        let ping_data_buffer = PingDataBuffer::new(Arc::new(InFlightTable::new(
+            1,
+            Duration::from_secs(1),
+            Arc::new(crate::SystemClock),
+        )));
+        PingReceiver::new(
+            Arc::new(SharedProbe::new(IcmpV4::new(socket))),
+            ping_data_buffer,
+            Duration::from_secs(1),
+            Arc::new(crate::SystemClock),
+        )
+    }
+
     #[test]
     fn receive_ping_packages_success() {
         let socket = SocketMock::new(OnSend::ReturnDefault, OnReceive::ReturnDefault(2));
-        let icmpv4 = Arc::new(IcmpV4::new(socket));
-        let (_tx, rx) = ping_send_record_channel(1);
-        let ping_data_buffer = PingDataBuffer::new(rx);
-        let ping_receiver = PingReceiver::new(icmpv4, ping_data_buffer);
+        let ping_receiver = ping_receiver(socket);
 
-        let recv_record_1 = ping_receiver.receive_aux(PingSentToken {}).unwrap();
-        let recv_record_2 = ping_receiver.receive_aux(PingSentToken {}).unwrap();
-        let recv_record_3 = ping_receiver.receive_aux(PingSentToken {}).unwrap();
+        let recv_record_1 = ping_receiver.receive_aux(PingSentToken {}, Duration::from_secs(1)).unwrap();
+        let recv_record_2 = ping_receiver.receive_aux(PingSentToken {}, Duration::from_secs(1)).unwrap();
+        let recv_record_3 = ping_receiver.receive_aux(PingSentToken {}, Duration::from_secs(1)).unwrap();
 
         assert!(matches!(recv_record_1, PingReceiveRecord::Data(_)));
         assert!(matches!(recv_record_2, PingReceiveRecord::Data(_)));
@@ -81,13 +179,20 @@ mod tests {
     #[test]
     fn when_socket_fails_then_ping_receiver_returns_timeout() {
         let socket = SocketMock::new(OnSend::ReturnDefault, OnReceive::ReturnWouldBlock);
-        let icmpv4 = Arc::new(IcmpV4::new(socket));
-        let (_tx, rx) = ping_send_record_channel(1);
-        let ping_data_buffer = PingDataBuffer::new(rx);
-        let ping_receiver = PingReceiver::new(icmpv4, ping_data_buffer);
+        let ping_receiver = ping_receiver(socket);
 
-        let recv_record = ping_receiver.receive_aux(PingSentToken {}).unwrap();
+        let recv_record = ping_receiver.receive_aux(PingSentToken {}, Duration::from_secs(1)).unwrap();
 
         assert!(matches!(recv_record, PingReceiveRecord::Timeout));
     }
+
+    #[test]
+    fn when_shut_down_then_ping_receiver_returns_closed() {
+        let socket = SocketMock::new(OnSend::ReturnDefault, OnReceive::ReturnDefault(2));
+        let mut ping_receiver = ping_receiver(socket);
+
+        ping_receiver.shared_probe().shut_down();
+
+        assert!(matches!(ping_receiver.receive(PingSentToken {}), Ok(PingReceive::Closed)));
+    }
 }
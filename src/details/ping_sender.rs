@@ -1,49 +1,120 @@
-use crate::details::icmp::v4::IcmpV4;
 use crate::details::icmp::v4::SequenceNumber;
-use crate::details::icmp::v4::TSocket;
-use crate::details::records::{PingSendRecord, PingSendRecordSender};
+use crate::details::in_flight::{InFlightTable, Reservation};
+use crate::details::records::PingSendRecord;
+use crate::details::sync::{read, write};
 use crate::details::PingResult;
+use crate::details::TProbe;
+use crate::details::{PingError, SharedProbe};
 use crate::PingSentToken;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, RwLock};
 
-pub(crate) struct PingSender<S> {
-    icmpv4: Arc<IcmpV4<S>>,
-    ping_sent_record_tx: PingSendRecordSender,
-    sequence_numbers: HashMap<IpAddr, SequenceNumber>,
+// The last sequence number sent to each destination. Senders on many threads allocate sequence
+// numbers with an atomic update; the write lock is only taken for a new destination.
+#[derive(Default)]
+struct SequenceNumbers {
+    last: RwLock<HashMap<IpAddr, AtomicU16>>,
 }
 
-impl<S> PingSender<S>
+impl SequenceNumbers {
+    fn next(&self, ip_addr: IpAddr) -> SequenceNumber {
+        if let Some(last) = read(&self.last).get(&ip_addr) {
+            return Self::increment(last);
+        }
+        match write(&self.last).entry(ip_addr) {
+            // Another sender added the destination in between.
+            Entry::Occupied(last) => Self::increment(last.get()),
+            Entry::Vacant(last) => {
+                last.insert(AtomicU16::new(SequenceNumber::start_value().into()));
+                SequenceNumber::start_value()
+            }
+        }
+    }
+
+    fn increment(last: &AtomicU16) -> SequenceNumber {
+        let next = |sequence_number: u16| SequenceNumber::from(sequence_number).next();
+        // The update always succeeds, and both variants hold the previous value.
+        let (Ok(previous) | Err(previous)) =
+            last.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |previous| Some(next(previous).into()));
+        next(previous)
+    }
+}
+
+pub(crate) struct PingSender<P> {
+    shared_probe: Arc<SharedProbe<P>>,
+    in_flight: Arc<InFlightTable>,
+    sequence_numbers: Arc<SequenceNumbers>,
+}
+
+// Clones share the probe, the in-flight table and the sequence numbers.
+impl<P> Clone for PingSender<P> {
+    fn clone(&self) -> Self {
+        PingSender {
+            shared_probe: self.shared_probe.clone(),
+            in_flight: self.in_flight.clone(),
+            sequence_numbers: self.sequence_numbers.clone(),
+        }
+    }
+}
+
+impl<P> PingSender<P>
 where
-    S: TSocket + 'static,
+    P: TProbe + 'static,
 {
-    pub(crate) fn new(icmpv4: Arc<IcmpV4<S>>, ping_sent_record_tx: PingSendRecordSender) -> Self {
-        PingSender { icmpv4, ping_sent_record_tx, sequence_numbers: HashMap::new() }
+    pub(crate) fn new(shared_probe: Arc<SharedProbe<P>>, in_flight: Arc<InFlightTable>) -> Self {
+        PingSender { shared_probe, in_flight, sequence_numbers: Arc::new(SequenceNumbers::default()) }
     }
 
-    fn send_to_details(&self, ip: Ipv4Addr, sequence_number: SequenceNumber) -> PingResult<()> {
+    // The reservation is given back if sending fails.
+    fn send_to_details(
+        &self,
+        reservation: Reservation<'_>,
+        ip: Ipv4Addr,
+        sequence_number: SequenceNumber,
+    ) -> PingResult<()> {
         // (1) Send ping.
-        let (payload_size, ip_addr, sequence_number, send_time) = self.icmpv4.send_to(ip, sequence_number)?;
-        tracing::trace!("icmpv4 sent");
+        let probe = self
+            .shared_probe
+            .get()
+            .ok_or(PingError { message: "ping-fox has been shut down".to_owned() })?;
+        let (payload_size, ip_addr, sequence_number, send_time) = probe.send_to(ip, sequence_number)?;
+        tracing::trace!("probe sent");
 
-        // (2) Dispatch data to PingDataBuffer
-        self.ping_sent_record_tx
-            .send(PingSendRecord { payload_size, ip_addr, sequence_number, send_time })?;
+        // (2) Make the send record available to the PingReceiver.
+        reservation.insert(&PingSendRecord { payload_size, ip_addr, sequence_number, send_time });
         Ok(())
     }
 
-    pub(crate) fn send_to(&mut self, ip: Ipv4Addr) -> PingResult<PingSentToken> {
-        let sequence_number = match self.sequence_numbers.get(&IpAddr::V4(ip)) {
-            Some(sequence_number) => sequence_number.next(),
-            None => SequenceNumber::start_value(),
-        };
-        self.sequence_numbers.insert(IpAddr::V4(ip), sequence_number);
+    pub(crate) fn shared_probe(&self) -> &Arc<SharedProbe<P>> {
+        &self.shared_probe
+    }
 
-        self.send_to_details(ip, sequence_number)?;
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.in_flight()
+    }
+
+    pub(crate) fn max_in_flight(&self) -> usize {
+        self.in_flight.max_in_flight()
+    }
+
+    pub(crate) fn send_to(&self, ip: Ipv4Addr) -> PingResult<PingSentToken> {
+        self.send(ip)?;
         Ok(PingSentToken {})
     }
+
+    // Like `send_to`, but returns the sequence number of the ping.
+    pub(crate) fn send(&self, ip: Ipv4Addr) -> PingResult<SequenceNumber> {
+        // Room is reserved first, so that no sequence number is used up if there is none.
+        let reservation = self.in_flight.try_reserve()?;
+        let sequence_number = self.sequence_numbers.next(IpAddr::V4(ip));
+
+        self.send_to_details(reservation, ip, sequence_number)?;
+        Ok(sequence_number)
+    }
 }
 
 #[cfg(test)]
@@ -52,45 +123,133 @@ mod tests {
     use crate::details::icmp::v4::tests::OnReceive;
     use crate::details::icmp::v4::tests::OnSend;
     use crate::details::icmp::v4::tests::SocketMock;
-    use crate::details::records::ping_send_record_channel;
-    use std::sync::mpsc;
+    use crate::details::icmp::v4::IcmpV4;
+    use crate::details::in_flight::WouldExceedInFlight;
+    use std::time::Duration;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
 
     #[test]
     fn send_ping_packages_success() {
         let socket = SocketMock::new(OnSend::ReturnDefault, OnReceive::ReturnDefault(2));
-        let icmpv4 = Arc::new(IcmpV4::new(socket));
-        let (tx, rx) = ping_send_record_channel(2);
-        let ping_sender = PingSender::new(icmpv4, tx);
+        let icmpv4 = Arc::new(SharedProbe::new(IcmpV4::new(socket)));
+        let in_flight = Arc::new(InFlightTable::new(2, Duration::from_secs(1), Arc::new(crate::SystemClock)));
+        let ping_sender = PingSender::new(icmpv4, in_flight.clone());
 
         let localhost = Ipv4Addr::new(127, 0, 0, 1);
-        ping_sender.send_to_details(localhost, SequenceNumber::from(1)).unwrap();
-        ping_sender.send_to_details(localhost, SequenceNumber::from(2)).unwrap();
-
-        let ping_sent_record_1 = rx.recv();
-        let ping_sent_record_2 = rx.recv();
+        ping_sender
+            .send_to_details(
+                ping_sender.in_flight.try_reserve().unwrap(),
+                localhost,
+                SequenceNumber::from(1),
+            )
+            .unwrap();
+        ping_sender
+            .send_to_details(
+                ping_sender.in_flight.try_reserve().unwrap(),
+                localhost,
+                SequenceNumber::from(2),
+            )
+            .unwrap();
 
-        assert!(ping_sent_record_1.is_ok());
-        let PingSendRecord { payload_size: _, ip_addr, sequence_number, send_time: _ } = ping_sent_record_1.unwrap();
-        assert!(localhost == ip_addr);
-        assert!(sequence_number == SequenceNumber::from(1));
-
-        assert!(ping_sent_record_2.is_ok());
-        let PingSendRecord { payload_size: _, ip_addr, sequence_number, send_time: _ } = ping_sent_record_2.unwrap();
-        assert!(localhost == ip_addr);
-        assert!(sequence_number == SequenceNumber::from(2));
+        assert_eq!(2, ping_sender.in_flight());
+        assert!(in_flight.take(SequenceNumber::from(1), LOCALHOST, Duration::ZERO).is_some());
+        assert!(in_flight.take(SequenceNumber::from(2), LOCALHOST, Duration::ZERO).is_some());
     }
 
     #[test]
     fn when_socket_fails_then_ping_sender_fails() {
         let socket = SocketMock::new(OnSend::ReturnErr, OnReceive::ReturnWouldBlock);
-        let icmpv4 = Arc::new(IcmpV4::new(socket));
-        let (tx, rx) = ping_send_record_channel(1);
-        let ping_sender = PingSender::new(icmpv4, tx);
+        let icmpv4 = Arc::new(SharedProbe::new(IcmpV4::new(socket)));
+        let ping_sender = PingSender::new(
+            icmpv4,
+            Arc::new(InFlightTable::new(1, Duration::from_secs(1), Arc::new(crate::SystemClock))),
+        );
 
         let localhost = Ipv4Addr::new(127, 0, 0, 1);
-        let send_result = ping_sender.send_to_details(localhost, SequenceNumber::start_value());
+        let send_result = ping_sender.send_to_details(
+            ping_sender.in_flight.try_reserve().unwrap(),
+            localhost,
+            SequenceNumber::start_value(),
+        );
 
         assert!(send_result.is_err());
-        assert!(rx.try_recv() == Err(mpsc::TryRecvError::Empty));
+        assert_eq!(0, ping_sender.in_flight());
+    }
+
+    #[test]
+    fn when_shut_down_then_ping_sender_fails_and_gives_back_room() {
+        let socket = SocketMock::new(OnSend::ReturnDefault, OnReceive::ReturnWouldBlock);
+        let ping_sender = PingSender::new(
+            Arc::new(SharedProbe::new(IcmpV4::new(socket))),
+            Arc::new(InFlightTable::new(1, Duration::from_secs(1), Arc::new(crate::SystemClock))),
+        );
+
+        ping_sender.shared_probe().shut_down();
+
+        assert!(ping_sender.send_to(Ipv4Addr::new(127, 0, 0, 1)).is_err());
+        assert_eq!(0, ping_sender.in_flight());
+    }
+
+    #[test]
+    fn when_too_many_pings_are_in_flight_then_ping_sender_fails_without_blocking() {
+        let socket = SocketMock::new(OnSend::ReturnDefault, OnReceive::ReturnWouldBlock);
+        let icmpv4 = Arc::new(SharedProbe::new(IcmpV4::new(socket)));
+        let ping_sender = PingSender::new(
+            icmpv4,
+            Arc::new(InFlightTable::new(1, Duration::from_secs(1), Arc::new(crate::SystemClock))),
+        );
+
+        let localhost = Ipv4Addr::new(127, 0, 0, 1);
+        ping_sender.send_to(localhost).unwrap();
+        let Err(error) = ping_sender.send_to(localhost) else {
+            panic!("expected an error");
+        };
+
+        assert_eq!(
+            Some(&WouldExceedInFlight { max_in_flight: 1 }),
+            error.downcast_ref::<WouldExceedInFlight>()
+        );
+        assert_eq!(
+            SequenceNumber::start_value().next(),
+            ping_sender.sequence_numbers.next(LOCALHOST)
+        );
+    }
+
+    #[test]
+    fn sequence_numbers_are_counted_per_destination_and_wrap_around() {
+        let sequence_numbers = SequenceNumbers::default();
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+        assert_eq!(SequenceNumber::from(1), sequence_numbers.next(LOCALHOST));
+        assert_eq!(SequenceNumber::from(2), sequence_numbers.next(LOCALHOST));
+        assert_eq!(SequenceNumber::from(1), sequence_numbers.next(other));
+
+        sequence_numbers.last.read().unwrap()[&other].store(u16::MAX, Ordering::Relaxed);
+        assert_eq!(SequenceNumber::start_value(), sequence_numbers.next(other));
+    }
+
+    #[test]
+    fn clones_sending_on_many_threads_use_distinct_sequence_numbers() {
+        let socket = SocketMock::new(OnSend::ReturnDefault, OnReceive::ReturnWouldBlock);
+        let in_flight = Arc::new(InFlightTable::new(400, Duration::from_secs(1), Arc::new(crate::SystemClock)));
+        let ping_sender = PingSender::new(Arc::new(SharedProbe::new(IcmpV4::new(socket))), in_flight.clone());
+        let localhost = Ipv4Addr::new(127, 0, 0, 1);
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let ping_sender = ping_sender.clone();
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        ping_sender.send_to(localhost).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(400, ping_sender.in_flight());
+        for sequence_number in 1..=400 {
+            assert!(in_flight.take(sequence_number.into(), LOCALHOST, Duration::ZERO).is_some());
+        }
     }
 }
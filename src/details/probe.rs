@@ -0,0 +1,128 @@
+use crate::details::icmp::v4::icmpv4::new_payload;
+use crate::details::icmp::v4::{IcmpV4, SequenceNumber, Socket};
+use crate::details::netns::{self, NetworkNamespace};
+use crate::details::pcap::PcapWriter;
+use crate::details::records::PingReceiveRecordData;
+use crate::details::tcp::TcpProbe;
+use crate::details::udp::UdpProbe;
+use crate::details::PingError;
+use crate::{Clock, PingFoxConfig, ProbeKind, SystemClock};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::fd::RawFd;
+use std::sync::Arc;
+use std::time::Instant;
+
+// The default TTL of Linux, written to pcap files if no TTL is configured.
+const DEFAULT_TTL: u8 = 64;
+
+pub(crate) trait TProbe: Send + Sync {
+    fn send_to(
+        &self,
+        ipv4: Ipv4Addr,
+        sequence_number: SequenceNumber,
+    ) -> Result<(usize, IpAddr, SequenceNumber, Instant), PingError>;
+    // Returns `None` on a timeout, or, for a probe with a file descriptor, after skipping a message
+    // which is not a reply.
+    fn try_receive(&self) -> io::Result<Option<PingReceiveRecordData>>;
+    // Like `try_receive`, but returns `None` at once instead of waiting, also for a probe without a
+    // file descriptor, as far as it can.
+    fn try_receive_now(&self) -> io::Result<Option<PingReceiveRecordData>> {
+        self.try_receive()
+    }
+    // A file descriptor which is readable when `try_receive` has something to receive.
+    fn raw_fd(&self) -> Option<RawFd> {
+        None
+    }
+    // Makes a `try_receive` in progress return soon, for probes without a file descriptor.
+    fn wake(&self) {}
+}
+
+pub(crate) enum Probe {
+    IcmpV4(IcmpV4<Socket>),
+    Tcp(TcpProbe),
+    Udp(UdpProbe),
+}
+
+impl Probe {
+    pub(crate) fn new(config: &PingFoxConfig) -> Result<Self, io::Error> {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let network_namespace = match &config.network_namespace {
+            Some(path) => Some(NetworkNamespace::open(path)?),
+            None => None,
+        };
+        match config.probe_kind {
+            ProbeKind::Icmp => {
+                let socket = netns::run_in(network_namespace.as_ref(), || {
+                    Socket::new(config.socket_type, config.timeout, config.ttl, config.interface.as_deref())
+                })?;
+                let payload = new_payload(config.payload_size, &config.payload_pattern);
+                let icmpv4 = IcmpV4::with_payload(socket, payload, clock);
+                match &config.pcap_file {
+                    Some(path) => {
+                        let pcap_writer = PcapWriter::create(path, config.ttl.unwrap_or(DEFAULT_TTL))?;
+                        Ok(Probe::IcmpV4(icmpv4.with_pcap_writer(pcap_writer)))
+                    }
+                    None => Ok(Probe::IcmpV4(icmpv4)),
+                }
+            }
+            // A TCP probe creates a socket per connection attempt, on a thread of its own. Entering
+            // the namespace once here reports a missing privilege before the first probe is sent.
+            ProbeKind::Tcp { port } => {
+                netns::run_in(network_namespace.as_ref(), || Ok(()))?;
+                Ok(Probe::Tcp(TcpProbe::new(port, config.timeout, clock, network_namespace)?))
+            }
+            ProbeKind::Udp { port } => {
+                let timeout = config.timeout;
+                let udp_probe = netns::run_in(network_namespace.as_ref(), || UdpProbe::new(port, timeout, clock))?;
+                Ok(Probe::Udp(udp_probe))
+            }
+        }
+    }
+}
+
+impl TProbe for Probe {
+    fn send_to(
+        &self,
+        ipv4: Ipv4Addr,
+        sequence_number: SequenceNumber,
+    ) -> Result<(usize, IpAddr, SequenceNumber, Instant), PingError> {
+        match self {
+            Probe::IcmpV4(probe) => probe.send_to(ipv4, sequence_number),
+            Probe::Tcp(probe) => probe.send_to(ipv4, sequence_number),
+            Probe::Udp(probe) => probe.send_to(ipv4, sequence_number),
+        }
+    }
+
+    fn try_receive(&self) -> io::Result<Option<PingReceiveRecordData>> {
+        match self {
+            Probe::IcmpV4(probe) => probe.try_receive(),
+            Probe::Tcp(probe) => probe.try_receive(),
+            Probe::Udp(probe) => probe.try_receive(),
+        }
+    }
+
+    fn try_receive_now(&self) -> io::Result<Option<PingReceiveRecordData>> {
+        match self {
+            Probe::IcmpV4(probe) => probe.try_receive_now(),
+            Probe::Tcp(probe) => probe.try_receive_now(),
+            Probe::Udp(probe) => probe.try_receive_now(),
+        }
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        match self {
+            Probe::IcmpV4(probe) => probe.raw_fd(),
+            Probe::Tcp(probe) => probe.raw_fd(),
+            Probe::Udp(probe) => probe.raw_fd(),
+        }
+    }
+
+    fn wake(&self) {
+        match self {
+            Probe::IcmpV4(probe) => probe.wake(),
+            Probe::Tcp(probe) => probe.wake(),
+            Probe::Udp(probe) => probe.wake(),
+        }
+    }
+}
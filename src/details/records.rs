@@ -1,6 +1,7 @@
 use crate::details::icmp::v4::{SequenceNumber, Ttl};
+use crate::ProbeKind;
+use std::net::IpAddr;
 use std::time::Instant;
-use std::{net::IpAddr, sync::mpsc};
 
 #[derive(PartialEq, Eq)]
 pub(crate) struct PingSendRecord {
@@ -9,15 +10,11 @@ pub(crate) struct PingSendRecord {
     pub sequence_number: SequenceNumber,
     pub send_time: Instant,
 }
-pub(crate) type PingSendRecordSender = mpsc::SyncSender<PingSendRecord>;
-pub(crate) type PingSendRecordReceiver = mpsc::Receiver<PingSendRecord>;
-pub(crate) fn ping_send_record_channel(channel_size: usize) -> (PingSendRecordSender, PingSendRecordReceiver) {
-    mpsc::sync_channel::<PingSendRecord>(channel_size)
-}
 
 #[derive(PartialEq, Eq)]
 pub(crate) enum PingReceiveRecord {
     Timeout,
+    Closed,
     Data(PingReceiveRecordData),
 }
 
@@ -28,4 +25,5 @@ pub(crate) struct PingReceiveRecordData {
     pub ttl: Ttl,
     pub sequence_number: SequenceNumber,
     pub receive_time: Instant,
+    pub probe_kind: ProbeKind,
 }
@@ -0,0 +1,206 @@
+use crate::details::sync::{lock, read, write};
+use crate::details::TProbe;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Condvar, Mutex, PoisonError, RwLock};
+use std::time::Duration;
+
+pub(crate) enum Readiness {
+    Readable,
+    TimedOut,
+    ShutDown,
+}
+
+// The probe shared by all senders and the receiver, until it is shut down.
+//
+// Shutting down takes the probe out, so its sockets are closed as soon as the calls in progress
+// on it return. Receivers poll an eventfd together with the socket of the probe; the eventfd
+// becomes readable on shutdown and wakes them up immediately.
+pub(crate) struct SharedProbe<P> {
+    probe: RwLock<Option<Arc<P>>>,
+    is_shut_down: Mutex<bool>,
+    shut_down: Condvar,
+    event_fd: Option<OwnedFd>,
+}
+
+impl<P> SharedProbe<P>
+where
+    P: TProbe,
+{
+    pub(crate) fn new(probe: P) -> Self {
+        let event_fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        let event_fd = if event_fd < 0 {
+            tracing::warn!(
+                "could not create eventfd, receivers notice a shutdown only after their timeout: {}",
+                io::Error::last_os_error()
+            );
+            None
+        } else {
+            // The file descriptor was just created and is owned by nothing else.
+            Some(unsafe { OwnedFd::from_raw_fd(event_fd) })
+        };
+        SharedProbe {
+            probe: RwLock::new(Some(Arc::new(probe))),
+            is_shut_down: Mutex::new(false),
+            shut_down: Condvar::new(),
+            event_fd,
+        }
+    }
+
+    // Returns the probe, or `None` after a shutdown.
+    pub(crate) fn get(&self) -> Option<Arc<P>> {
+        read(&self.probe).clone()
+    }
+
+    pub(crate) fn is_shut_down(&self) -> bool {
+        *lock(&self.is_shut_down)
+    }
+
+    pub(crate) fn shut_down(&self) {
+        let probe = write(&self.probe).take();
+        *lock(&self.is_shut_down) = true;
+        self.shut_down.notify_all();
+        if let Some(event_fd) = &self.event_fd {
+            let one: u64 = 1;
+            // The eventfd is never read, so it stays readable for all receivers.
+            let _ = unsafe { libc::write(event_fd.as_raw_fd(), std::ptr::addr_of!(one).cast(), size_of::<u64>()) };
+        }
+        if let Some(probe) = probe {
+            probe.wake();
+        }
+    }
+
+    // Waits up to `timeout` for a shutdown and returns whether there was one.
+    pub(crate) fn wait_timeout(&self, timeout: Duration) -> bool {
+        let is_shut_down = lock(&self.is_shut_down);
+        *self
+            .shut_down
+            .wait_timeout_while(is_shut_down, timeout, |is_shut_down| !*is_shut_down)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+    }
+
+    // Waits up to `timeout` until `fd` is readable or there is a shutdown. Without an eventfd,
+    // only `fd` is waited for.
+    pub(crate) fn poll(&self, fd: RawFd, timeout: Duration) -> io::Result<Readiness> {
+        let mut fds = [
+            libc::pollfd { fd, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: self.event_fd.as_ref().map_or(-1, AsRawFd::as_raw_fd), events: libc::POLLIN, revents: 0 },
+        ];
+        let n_fds = if self.event_fd.is_some() { 2 } else { 1 };
+        // Rounded up, so that a timeout below one millisecond does not become a busy loop.
+        let timeout_ms = libc::c_int::try_from(timeout.as_micros().div_ceil(1000)).unwrap_or(libc::c_int::MAX);
+        // Like a blocking receive, poll fails with `Interrupted` on a signal.
+        let n_ready = unsafe { libc::poll(fds.as_mut_ptr(), n_fds, timeout_ms) };
+        if n_ready < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if fds[1].revents != 0 {
+            Ok(Readiness::ShutDown)
+        } else if n_ready == 0 {
+            Ok(Readiness::TimedOut)
+        } else {
+            Ok(Readiness::Readable)
+        }
+    }
+
+    // Creates an epoll instance which is readable while the socket of the probe is readable and
+    // after a shutdown, so that it can be waited for in an event loop. Unlike the socket, it stays
+    // open after a shutdown.
+    pub(crate) fn new_epoll(&self) -> io::Result<OwnedFd> {
+        let epoll_fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epoll_fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // The file descriptor was just created and is owned by nothing else.
+        let epoll_fd = unsafe { OwnedFd::from_raw_fd(epoll_fd) };
+        let socket_fd = self.get().and_then(|probe| probe.raw_fd());
+        for fd in socket_fd.into_iter().chain(self.event_fd.as_ref().map(AsRawFd::as_raw_fd)) {
+            let mut event = libc::epoll_event { events: libc::EPOLLIN as u32, u64: 0 };
+            if unsafe { libc::epoll_ctl(epoll_fd.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, std::ptr::addr_of_mut!(event)) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(epoll_fd)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::details::icmp::v4::tests::{OnReceive, OnSend, SocketMock};
+    use crate::details::icmp::v4::IcmpV4;
+    use std::net::UdpSocket;
+    use std::time::Instant;
+
+    // Returns whether `fd` is readable, without waiting.
+    pub(crate) fn is_readable(fd: RawFd) -> bool {
+        let mut pollfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+        unsafe { libc::poll(std::ptr::addr_of_mut!(pollfd), 1, 0) == 1 }
+    }
+
+    fn shared_probe() -> SharedProbe<IcmpV4<SocketMock>> {
+        SharedProbe::new(IcmpV4::new(SocketMock::new(
+            OnSend::ReturnDefault,
+            OnReceive::ReturnWouldBlock,
+        )))
+    }
+
+    #[test]
+    fn shut_down_takes_probe_out() {
+        let shared_probe = shared_probe();
+        assert!(shared_probe.get().is_some());
+
+        shared_probe.shut_down();
+
+        assert!(shared_probe.get().is_none());
+        assert!(shared_probe.is_shut_down());
+        assert!(shared_probe.wait_timeout(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn poll_reports_readable_socket_and_timeout() {
+        let shared_probe = shared_probe();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        assert!(matches!(
+            shared_probe.poll(socket.as_raw_fd(), Duration::from_millis(10)).unwrap(),
+            Readiness::TimedOut
+        ));
+        socket.send_to(&[0], socket.local_addr().unwrap()).unwrap();
+        assert!(matches!(
+            shared_probe.poll(socket.as_raw_fd(), Duration::from_secs(1)).unwrap(),
+            Readiness::Readable
+        ));
+    }
+
+    #[test]
+    fn shut_down_wakes_up_poll() {
+        let shared_probe = Arc::new(shared_probe());
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let start = Instant::now();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(10));
+                shared_probe.shut_down();
+            });
+            assert!(matches!(
+                shared_probe.poll(socket.as_raw_fd(), Duration::from_secs(10)).unwrap(),
+                Readiness::ShutDown
+            ));
+        });
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn epoll_is_readable_after_shut_down() {
+        let shared_probe = shared_probe();
+        let epoll_fd = shared_probe.new_epoll().unwrap();
+        assert!(!is_readable(epoll_fd.as_raw_fd()));
+
+        shared_probe.shut_down();
+
+        assert!(is_readable(epoll_fd.as_raw_fd()));
+    }
+}
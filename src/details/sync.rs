@@ -0,0 +1,33 @@
+use std::sync::{mpsc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+// Longest wait between two receives of a socket which returns before the timeout without a reply,
+// e.g. a socket without a file descriptor.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Locks `mutex`. A panic while it was locked leaves no broken invariants in the data of this
+// crate, so a poisoned mutex is used as it is.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Like `lock`, for reading from an `RwLock`.
+pub(crate) fn read<T>(rw_lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    rw_lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Like `lock`, for writing to an `RwLock`.
+pub(crate) fn write<T>(rw_lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    rw_lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Returns an event callback which sends the events through `tx`. Events are dropped once the
+// receiver is gone.
+pub(crate) fn send_to_channel<T>(tx: mpsc::Sender<T>) -> impl FnMut(T) + Send + 'static
+where
+    T: Send + 'static,
+{
+    move |event| {
+        let _ = tx.send(event);
+    }
+}
@@ -0,0 +1,3 @@
+pub(crate) use tcp_probe::TcpProbe;
+
+mod tcp_probe;
@@ -0,0 +1,436 @@
+use crate::details::icmp::v4::{SequenceNumber, Ttl};
+use crate::details::netns::NetworkNamespace;
+use crate::details::records::PingReceiveRecordData;
+use crate::details::PingError;
+use crate::details::TProbe;
+use crate::{Clock, ProbeKind};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// A TCP probe does not carry a payload.
+const PAYLOAD_SIZE: usize = 0;
+
+// The TTL of a SYN-ACK segment cannot be observed through a connecting socket.
+const UNKNOWN_TTL: u8 = 0;
+
+// A connection attempt handed to the connect thread.
+struct ConnectRequest {
+    addr: SocketAddr,
+    sequence_number: SequenceNumber,
+    send_time: Instant,
+}
+
+// The sending ends to the connect thread. Writing to `waker` wakes the thread up from `poll`.
+struct ConnectThread {
+    requests: mpsc::Sender<ConnectRequest>,
+    waker: UnixStream,
+}
+
+impl ConnectThread {
+    fn wake(&mut self) {
+        // A full socket buffer holds a wake-up already.
+        let _ = self.waker.write(&[0]);
+    }
+}
+
+pub(crate) struct TcpProbe {
+    port: u16,
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+    network_namespace: Option<Arc<NetworkNamespace>>,
+    // Started with the first probe.
+    connect_thread: Mutex<Option<ConnectThread>>,
+    reply_tx: Mutex<mpsc::Sender<PingReceiveRecordData>>,
+    reply_rx: Mutex<mpsc::Receiver<PingReceiveRecordData>>,
+    // An eventfd in semaphore mode which counts the replies in `reply_rx`, so that it is readable
+    // while there is a reply to receive.
+    replies_ready: Arc<OwnedFd>,
+}
+
+impl TcpProbe {
+    pub(crate) fn new(
+        port: u16,
+        timeout: Duration,
+        clock: Arc<dyn Clock>,
+        network_namespace: Option<NetworkNamespace>,
+    ) -> io::Result<TcpProbe> {
+        tracing::trace!("creating TcpProbe");
+        let replies_ready = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK | libc::EFD_SEMAPHORE) };
+        if replies_ready < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // The file descriptor was just created and is owned by nothing else.
+        let replies_ready = Arc::new(unsafe { OwnedFd::from_raw_fd(replies_ready) });
+        let (reply_tx, reply_rx) = mpsc::channel();
+        Ok(TcpProbe {
+            port,
+            timeout,
+            clock,
+            network_namespace: network_namespace.map(Arc::new),
+            connect_thread: Mutex::new(None),
+            reply_tx: Mutex::new(reply_tx),
+            reply_rx: Mutex::new(reply_rx),
+            replies_ready,
+        })
+    }
+
+    fn start_connect_thread(&self) -> Result<ConnectThread, PingError> {
+        let (requests, request_rx) = mpsc::channel();
+        let (waker, wake_rx) = UnixStream::pair()?;
+        wake_rx.set_nonblocking(true)?;
+        waker.set_nonblocking(true)?;
+        let connector = Connector {
+            port: self.port,
+            timeout: self.timeout,
+            clock: self.clock.clone(),
+            request_rx,
+            wake_rx,
+            reply_tx: self
+                .reply_tx
+                .lock()
+                .map_err(|_| PingError { message: "TCP probe channel poisoned".to_owned() })?
+                .clone(),
+            replies_ready: self.replies_ready.clone(),
+            pending: Vec::new(),
+        };
+        let network_namespace = self.network_namespace.clone();
+        std::thread::Builder::new()
+            .name("ping-fox-tcp-probe".to_owned())
+            .spawn(move || {
+                // The thread only ever creates sockets for probes, so it does not need to leave
+                // the namespace again.
+                if let Some(network_namespace) = network_namespace {
+                    if let Err(e) = network_namespace.enter() {
+                        tracing::error!("could not enter network namespace for TCP probes: {}", e);
+                        return;
+                    }
+                }
+                connector.run();
+            })?;
+        Ok(ConnectThread { requests, waker })
+    }
+}
+
+impl Drop for TcpProbe {
+    // Stops the connect thread, which closes the sockets of the attempts in progress.
+    fn drop(&mut self) {
+        if let Ok(mut connect_thread) = self.connect_thread.lock() {
+            if let Some(ConnectThread { requests, mut waker }) = connect_thread.take() {
+                // The thread sees the disconnected channel once it is woken up.
+                drop(requests);
+                let _ = waker.write(&[0]);
+            }
+        }
+    }
+}
+
+impl TProbe for TcpProbe {
+    // Starts a TCP handshake with `ipv4` on the connect thread. The handshake completes when the
+    // SYN-ACK (or a RST) arrives, so the time until the socket is connected is the round-trip time.
+    fn send_to(
+        &self,
+        ipv4: Ipv4Addr,
+        sequence_number: SequenceNumber,
+    ) -> Result<(usize, IpAddr, SequenceNumber, Instant), PingError> {
+        let ip_addr = IpAddr::V4(ipv4);
+        let send_time = self.clock.now();
+        let mut connect_thread = self
+            .connect_thread
+            .lock()
+            .map_err(|_| PingError { message: "TCP probe connect thread poisoned".to_owned() })?;
+        if connect_thread.is_none() {
+            *connect_thread = Some(self.start_connect_thread()?);
+        }
+        let Some(connect_thread) = connect_thread.as_mut() else {
+            unreachable!("the connect thread has just been started");
+        };
+        connect_thread
+            .requests
+            .send(ConnectRequest { addr: SocketAddr::new(ip_addr, self.port), sequence_number, send_time })
+            .map_err(|_| PingError { message: "TCP probe connect thread has stopped".to_owned() })?;
+        connect_thread.wake();
+
+        Ok((PAYLOAD_SIZE, ip_addr, sequence_number, send_time))
+    }
+
+    // Returns a reply if one is ready, without waiting. Failed connection attempts are not
+    // replies and are not reported.
+    fn try_receive(&self) -> io::Result<Option<PingReceiveRecordData>> {
+        let reply_rx = self
+            .reply_rx
+            .lock()
+            .map_err(|_| io::Error::other("TCP probe channel poisoned"))?;
+        let mut n_ready: u64 = 0;
+        let result = unsafe {
+            libc::read(
+                self.replies_ready.as_raw_fd(),
+                std::ptr::addr_of_mut!(n_ready).cast(),
+                size_of::<u64>(),
+            )
+        };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            return if error.kind() == io::ErrorKind::WouldBlock {
+                Ok(None)
+            } else {
+                Err(error)
+            };
+        }
+        // The reply is sent before the eventfd is incremented for it.
+        match reply_rx.try_recv() {
+            Ok(record) => Ok(Some(record)),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Disconnected) => Err(io::Error::other("TCP probe channel disconnected")),
+        }
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.replies_ready.as_raw_fd())
+    }
+}
+
+// Performs the connection attempts of a TcpProbe: the sockets connect without blocking, and one
+// thread waits for all of them with `poll`.
+struct Connector {
+    port: u16,
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+    request_rx: mpsc::Receiver<ConnectRequest>,
+    wake_rx: UnixStream,
+    reply_tx: mpsc::Sender<PingReceiveRecordData>,
+    replies_ready: Arc<OwnedFd>,
+    pending: Vec<PendingConnect>,
+}
+
+struct PendingConnect {
+    socket: Socket,
+    request: ConnectRequest,
+    start_time: Instant,
+    // Measured with the system clock rather than `clock`, because it bounds the time spent in `poll`.
+    deadline: Instant,
+}
+
+impl Connector {
+    fn run(mut self) {
+        loop {
+            loop {
+                match self.request_rx.try_recv() {
+                    Ok(request) => self.start(request),
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    // The probe has been dropped.
+                    Err(mpsc::TryRecvError::Disconnected) => return,
+                }
+            }
+            if let Err(e) = self.poll() {
+                tracing::error!("TCP probe poll failed: {}", e);
+                return;
+            }
+        }
+    }
+
+    fn start(&mut self, request: ConnectRequest) {
+        let start_time = self.clock.now();
+        match Self::connect(&request.addr) {
+            Ok(Some(socket)) => {
+                let deadline = Instant::now() + self.timeout;
+                self.pending.push(PendingConnect { socket, request, start_time, deadline });
+            }
+            // Connected at once, e.g. to a local address.
+            Ok(None) => self.report(&request, start_time, true),
+            Err(e) => {
+                let is_alive = e.kind() == io::ErrorKind::ConnectionRefused;
+                if !is_alive {
+                    tracing::trace!("TCP probe to {} failed: {}", request.addr, e);
+                }
+                self.report(&request, start_time, is_alive);
+            }
+        }
+    }
+
+    // Starts to connect to `addr`. Returns the socket if the connection is in progress.
+    fn connect(addr: &SocketAddr) -> io::Result<Option<Socket>> {
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_nonblocking(true)?;
+        match socket.connect(&(*addr).into()) {
+            Ok(()) => Ok(None),
+            Err(e) if e.raw_os_error() == Some(libc::EINPROGRESS) => Ok(Some(socket)),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Waits for the next finished connection attempt, timeout or request, and reports the
+    // finished and timed out attempts.
+    fn poll(&mut self) -> io::Result<()> {
+        let mut poll_fds = vec![libc::pollfd { fd: self.wake_rx.as_raw_fd(), events: libc::POLLIN, revents: 0 }];
+        poll_fds.extend(self.pending.iter().map(|pending| libc::pollfd {
+            fd: pending.socket.as_raw_fd(),
+            events: libc::POLLOUT,
+            revents: 0,
+        }));
+        let now = Instant::now();
+        let timeout_ms = self
+            .pending
+            .iter()
+            .map(|pending| pending.deadline)
+            .min()
+            .map_or(-1, |deadline| {
+                // Rounded up, so that the deadline has passed when poll returns.
+                let wait = deadline.saturating_duration_since(now);
+                libc::c_int::try_from(wait.as_micros().div_ceil(1000)).unwrap_or(libc::c_int::MAX)
+            });
+        let n_fds = libc::nfds_t::try_from(poll_fds.len()).map_err(io::Error::other)?;
+        if unsafe { libc::poll(poll_fds.as_mut_ptr(), n_fds, timeout_ms) } < 0 {
+            let error = io::Error::last_os_error();
+            return if error.kind() == io::ErrorKind::Interrupted {
+                Ok(())
+            } else {
+                Err(error)
+            };
+        }
+        if poll_fds[0].revents != 0 {
+            let mut buffer = [0; 64];
+            while matches!(self.wake_rx.read(&mut buffer), Ok(n) if n > 0) {}
+        }
+
+        let now = Instant::now();
+        let pending = std::mem::take(&mut self.pending);
+        for (pending, poll_fd) in pending.into_iter().zip(&poll_fds[1..]) {
+            if poll_fd.revents != 0 {
+                // The handshake is done: either connected, or refused by a RST from the target,
+                // which is proof that it is alive as well.
+                let is_alive = match pending.socket.take_error() {
+                    Ok(None) => true,
+                    Ok(Some(e)) if e.kind() == io::ErrorKind::ConnectionRefused => true,
+                    Ok(Some(e)) | Err(e) => {
+                        tracing::trace!("TCP probe to {} failed: {}", pending.request.addr, e);
+                        false
+                    }
+                };
+                self.report(&pending.request, pending.start_time, is_alive);
+            } else if now >= pending.deadline {
+                tracing::trace!("TCP probe to {} timed out", pending.request.addr);
+                self.report(&pending.request, pending.start_time, false);
+            } else {
+                self.pending.push(pending);
+            }
+        }
+        Ok(())
+    }
+
+    // Sends the reply of an attempt which proved the target alive and makes the probe readable.
+    fn report(&self, request: &ConnectRequest, start_time: Instant, is_alive: bool) {
+        if !is_alive {
+            return;
+        }
+        // Measured from the start of the attempt, to exclude the hand-over to this thread from the
+        // round-trip time.
+        let receive_time = request.send_time + (self.clock.now() - start_time);
+        let record = PingReceiveRecordData {
+            package_size: PAYLOAD_SIZE,
+            ip_addr: request.addr.ip(),
+            ttl: Ttl(UNKNOWN_TTL),
+            sequence_number: request.sequence_number,
+            receive_time,
+            probe_kind: ProbeKind::Tcp { port: self.port },
+        };
+        if self.reply_tx.send(record).is_ok() {
+            let one: u64 = 1;
+            let _ = unsafe {
+                libc::write(
+                    self.replies_ready.as_raw_fd(),
+                    std::ptr::addr_of!(one).cast(),
+                    size_of::<u64>(),
+                )
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn tcp_probe(port: u16) -> TcpProbe {
+        TcpProbe::new(port, Duration::from_secs(1), Arc::new(crate::SystemClock), None).unwrap()
+    }
+
+    // Waits up to a second for the probe to become readable, like a receiver does, and receives.
+    fn receive(tcp_probe: &TcpProbe) -> Option<PingReceiveRecordData> {
+        let mut pollfd = libc::pollfd { fd: tcp_probe.raw_fd().unwrap(), events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(std::ptr::addr_of_mut!(pollfd), 1, 1000) } != 1 {
+            return None;
+        }
+        tcp_probe.try_receive().unwrap()
+    }
+
+    #[test]
+    fn when_port_is_listening_then_try_receive_returns_data() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let tcp_probe = tcp_probe(port);
+
+        let localhost = Ipv4Addr::new(127, 0, 0, 1);
+        let (payload_size, ip_addr, sequence_number, _) =
+            tcp_probe.send_to(localhost, SequenceNumber::start_value()).unwrap();
+        let record = receive(&tcp_probe).unwrap();
+
+        assert_eq!(PAYLOAD_SIZE, payload_size);
+        assert_eq!(IpAddr::V4(localhost), ip_addr);
+        assert_eq!(ip_addr, record.ip_addr);
+        assert_eq!(sequence_number, record.sequence_number);
+        assert_eq!(ProbeKind::Tcp { port }, record.probe_kind);
+    }
+
+    #[test]
+    fn when_port_is_closed_then_try_receive_returns_data() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let tcp_probe = tcp_probe(port);
+
+        tcp_probe
+            .send_to(Ipv4Addr::new(127, 0, 0, 1), SequenceNumber::start_value())
+            .unwrap();
+
+        assert!(receive(&tcp_probe).is_some());
+    }
+
+    #[test]
+    fn when_nothing_was_sent_then_try_receive_returns_none_at_once() {
+        let tcp_probe = TcpProbe::new(1, Duration::from_secs(30), Arc::new(crate::SystemClock), None).unwrap();
+        let start = Instant::now();
+
+        let record = tcp_probe.try_receive().unwrap();
+
+        assert!(record.is_none());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(!crate::details::shutdown::tests::is_readable(tcp_probe.raw_fd().unwrap()));
+    }
+
+    #[test]
+    fn when_many_probes_are_in_flight_then_each_one_is_answered() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let tcp_probe = tcp_probe(port);
+
+        let mut sequence_number = SequenceNumber::start_value();
+        let mut sent = Vec::new();
+        for _ in 0..20 {
+            tcp_probe.send_to(Ipv4Addr::new(127, 0, 0, 1), sequence_number).unwrap();
+            sent.push(sequence_number);
+            sequence_number = sequence_number.next();
+        }
+        let mut received: Vec<SequenceNumber> = (0..20).map(|_| receive(&tcp_probe).unwrap().sequence_number).collect();
+
+        received.sort_by_key(|sequence_number| u16::from(*sequence_number));
+        assert_eq!(sent, received);
+        assert!(!crate::details::shutdown::tests::is_readable(tcp_probe.raw_fd().unwrap()));
+    }
+}
@@ -0,0 +1,3 @@
+pub(crate) use udp_probe::UdpProbe;
+
+mod udp_probe;
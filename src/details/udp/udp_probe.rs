@@ -0,0 +1,228 @@
+use crate::details::icmp::v4::parse::parse_addr;
+use crate::details::icmp::v4::{SequenceNumber, Ttl};
+use crate::details::records::PingReceiveRecordData;
+use crate::details::PingError;
+use crate::details::TProbe;
+use crate::{Clock, ProbeKind};
+use rand::Rng;
+use socket2::{Domain, Protocol, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::os::fd::RawFd;
+use std::os::unix::prelude::AsRawFd;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod c_udp_dgram {
+    #![allow(clippy::pedantic)]
+    #![allow(non_upper_case_globals)]
+    #![allow(non_camel_case_types)]
+    #![allow(non_snake_case)]
+    #![allow(unused)]
+    include!(concat!(env!("OUT_DIR"), "/udp_bindings.rs"));
+}
+
+const PAYLOAD_SIZE: usize = 32;
+
+// Number of bytes, remote address and port, TTL and, for ICMP errors, the ICMP type and code.
+type UdpReceive = (usize, IpAddr, u16, Ttl, Option<(u8, u8)>);
+
+const ICMP_DEST_UNREACHABLE: u8 = 3;
+const ICMP_PORT_UNREACHABLE: u8 = 3;
+
+// Number of ports a probe can be sent to: all but port 0.
+const N_PORTS: u32 = u16::MAX as u32;
+
+pub(crate) struct UdpProbe {
+    port: u16,
+    clock: Arc<dyn Clock>,
+    payload: [u8; PAYLOAD_SIZE],
+    socket: socket2::Socket,
+}
+
+impl UdpProbe {
+    pub(crate) fn new(port: u16, timeout: Duration, clock: Arc<dyn Clock>) -> Result<Self, io::Error> {
+        tracing::trace!("creating UdpProbe");
+        let socket = socket2::Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_read_timeout(Some(timeout))?;
+        if unsafe { c_udp_dgram::udp_enable_recverr(socket.as_raw_fd()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut payload = [0u8; PAYLOAD_SIZE];
+        rand::thread_rng().fill(&mut payload[..]);
+        Ok(UdpProbe { port, clock, payload, socket })
+    }
+
+    // Like `traceroute -U`, the sequence number is encoded in the destination port. It comes back
+    // as the source port of a reply or in the UDP header quoted by an ICMP error. Ports above
+    // `u16::MAX` wrap around to 1, because port 0 cannot be sent to.
+    fn port_for(&self, sequence_number: SequenceNumber) -> u16 {
+        let offset = u32::from(u16::from(sequence_number)).wrapping_sub(u32::from(u16::from(SequenceNumber::start_value())));
+        let port_index = (u32::from(self.port.max(1)) - 1 + offset) % N_PORTS;
+        u16::try_from(port_index + 1).expect("port index is below the number of ports")
+    }
+
+    fn sequence_number_for(&self, port: u16) -> SequenceNumber {
+        let offset = (u32::from(port.max(1)) + N_PORTS - u32::from(self.port.max(1))) % N_PORTS;
+        let sequence_number = offset + u32::from(u16::from(SequenceNumber::start_value()));
+        u16::try_from(sequence_number)
+            .expect("sequence number is below the number of ports")
+            .into()
+    }
+
+    fn recv_from(&self) -> io::Result<UdpReceive> {
+        let mut buf = [0u8; 128];
+        let mut udp_data = c_udp_dgram::UdpData {
+            data_buffer: buf.as_mut_ptr(),
+            data_buffer_size: buf.len() as u64,
+            n_data_bytes_received: 0,
+            ttl: 0,
+            is_icmp_error: false,
+            icmp_type: 0,
+            icmp_code: 0,
+            port: 0,
+            addr_str: [0u8; 46],
+        };
+
+        let raw_fd: std::ffi::c_int = self.socket.as_raw_fd();
+        let result = unsafe { c_udp_dgram::udp_recv_from(raw_fd, std::ptr::addr_of_mut!(udp_data)) };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if result < 0 {
+            return Err(io::Error::other(format!("error {result} reading from socket")));
+        }
+        let ip_addr = parse_addr(&udp_data.addr_str)?;
+        let icmp_error = udp_data.is_icmp_error.then_some((udp_data.icmp_type, udp_data.icmp_code));
+        Ok((
+            udp_data.n_data_bytes_received,
+            ip_addr,
+            udp_data.port,
+            Ttl(udp_data.ttl),
+            icmp_error,
+        ))
+    }
+}
+
+impl TProbe for UdpProbe {
+    fn send_to(
+        &self,
+        ipv4: Ipv4Addr,
+        sequence_number: SequenceNumber,
+    ) -> Result<(usize, IpAddr, SequenceNumber, Instant), PingError> {
+        let ip_addr = IpAddr::V4(ipv4);
+        let addr: socket2::SockAddr = SocketAddr::new(ip_addr, self.port_for(sequence_number)).into();
+        // An ICMP error for an earlier probe leaves a pending socket error which would make this
+        // send fail. The error itself is still read from the error queue by `try_receive`.
+        let _ = self.socket.take_error()?;
+        let start_time: Instant = self.clock.now();
+        self.socket.send_to(&self.payload, &addr)?;
+
+        Ok((PAYLOAD_SIZE, ip_addr, sequence_number, start_time))
+    }
+
+    // ICMP errors in the error queue make the socket report `POLLERR`.
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.socket.as_raw_fd())
+    }
+
+    fn try_receive(&self) -> io::Result<Option<PingReceiveRecordData>> {
+        match self.recv_from() {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+            // Any other ICMP error (e.g. host unreachable) tells nothing about the target being alive.
+            Ok((_, ip_addr, port, _, Some((icmp_type, icmp_code))))
+                if (icmp_type, icmp_code) != (ICMP_DEST_UNREACHABLE, ICMP_PORT_UNREACHABLE) =>
+            {
+                tracing::trace!("ignoring ICMP error type {icmp_type} code {icmp_code} for {ip_addr}:{port}");
+                Ok(None)
+            }
+            Ok((package_size, ip_addr, port, ttl, _)) => {
+                let receive_time: Instant = self.clock.now();
+                Ok(Some(PingReceiveRecordData {
+                    package_size,
+                    ip_addr,
+                    ttl,
+                    sequence_number: self.sequence_number_for(port),
+                    receive_time,
+                    probe_kind: ProbeKind::Udp { port: self.port },
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+
+    #[test]
+    fn port_and_sequence_number_round_trip() {
+        let udp_probe = UdpProbe::new(u16::MAX - 1, Duration::from_millis(10), Arc::new(crate::SystemClock)).unwrap();
+
+        for sequence_number in [
+            SequenceNumber::start_value(),
+            SequenceNumber::from(2),
+            SequenceNumber::from(u16::MAX),
+        ] {
+            let port = udp_probe.port_for(sequence_number);
+            assert_eq!(sequence_number, udp_probe.sequence_number_for(port));
+        }
+        assert_eq!(u16::MAX - 1, udp_probe.port_for(SequenceNumber::start_value()));
+        assert_eq!(u16::MAX, udp_probe.port_for(SequenceNumber::from(2)));
+        assert_eq!(1, udp_probe.port_for(SequenceNumber::from(3)));
+        assert_eq!(u16::MAX - 2, udp_probe.port_for(SequenceNumber::from(u16::MAX)));
+    }
+
+    #[test]
+    fn when_port_is_closed_then_try_receive_returns_port_unreachable() {
+        let port = {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket.local_addr().unwrap().port()
+        };
+        let udp_probe = UdpProbe::new(port, Duration::from_secs(1), Arc::new(crate::SystemClock)).unwrap();
+
+        let localhost = Ipv4Addr::new(127, 0, 0, 1);
+        let sequence_number = SequenceNumber::start_value();
+        udp_probe.send_to(localhost, sequence_number).unwrap();
+        let record = udp_probe.try_receive().unwrap().unwrap();
+
+        assert_eq!(IpAddr::V4(localhost), record.ip_addr);
+        assert_eq!(sequence_number, record.sequence_number);
+        assert_eq!(PAYLOAD_SIZE, record.package_size);
+        assert_eq!(ProbeKind::Udp { port }, record.probe_kind);
+    }
+
+    #[test]
+    fn when_application_replies_then_try_receive_returns_reply() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let server_thread = std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (n, peer) = server.recv_from(&mut buf).unwrap();
+            server.send_to(&buf[..n / 2], peer).unwrap();
+        });
+        let udp_probe = UdpProbe::new(port, Duration::from_secs(1), Arc::new(crate::SystemClock)).unwrap();
+
+        let localhost = Ipv4Addr::new(127, 0, 0, 1);
+        let sequence_number = SequenceNumber::start_value();
+        udp_probe.send_to(localhost, sequence_number).unwrap();
+        let record = udp_probe.try_receive().unwrap().unwrap();
+        server_thread.join().unwrap();
+
+        assert_eq!(IpAddr::V4(localhost), record.ip_addr);
+        assert_eq!(sequence_number, record.sequence_number);
+        assert_eq!(PAYLOAD_SIZE / 2, record.package_size);
+        assert_ne!(Ttl(0), record.ttl);
+    }
+
+    #[test]
+    fn when_nothing_was_sent_then_try_receive_returns_timeout() {
+        let udp_probe = UdpProbe::new(1, Duration::from_millis(10), Arc::new(crate::SystemClock)).unwrap();
+
+        let record = udp_probe.try_receive().unwrap();
+
+        assert!(record.is_none());
+    }
+}
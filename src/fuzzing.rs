@@ -0,0 +1,51 @@
+//! Entry points for the fuzz targets in `fuzz/`. Not part of the public API.
+use crate::details::icmp::v4::icmpv4::new_payload;
+use crate::details::icmp::v4::parse::{parse_addr, parse_icmp_message, parse_ipv4_packet};
+use crate::details::icmp::v4::IcmpV4;
+use crate::details::sync::lock;
+use crate::details::TProbe;
+use crate::{SystemClock, TSocket};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+
+/// Parses `data` as an IPv4 packet, as received on a RAW socket.
+pub fn ipv4_packet(data: &[u8]) {
+    let _ = parse_ipv4_packet(data);
+}
+
+/// Parses `data` as an ICMP message.
+pub fn icmp_message(data: &[u8]) {
+    let _ = parse_icmp_message(data);
+}
+
+/// Parses `data` as the address string returned by the C helpers.
+pub fn addr(data: &[u8]) {
+    let _ = parse_addr(data);
+}
+
+// Returns `data` once, as if it had been received from 192.0.2.1.
+struct OneMessageSocket {
+    data: Mutex<Option<Vec<u8>>>,
+}
+
+impl TSocket for OneMessageSocket {
+    fn send_to(&self, buf: &[u8], _addr: IpAddr) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+        let data = lock(&self.data).take();
+        let data = data.ok_or_else(|| io::Error::from(io::ErrorKind::WouldBlock))?;
+        let n_bytes = data.len().min(buf.len());
+        buf[..n_bytes].copy_from_slice(&data[..n_bytes]);
+        Ok((data.len(), IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 64))
+    }
+}
+
+/// Receives `data` as an ICMP message, like an echo reply from a DGRAM socket.
+pub fn receive(data: &[u8]) {
+    let socket = OneMessageSocket { data: Mutex::new(Some(data.to_vec())) };
+    let icmpv4 = IcmpV4::with_payload(socket, new_payload(0, &[]), Arc::new(SystemClock));
+    let _ = icmpv4.try_receive();
+}
@@ -4,21 +4,25 @@
 //! receive an echo messages and its response.
 //!
 //! ```
-//! use ping_fox::{PingFoxConfig, PingReceive, PingReceiveData, PingSentToken, SocketType};
+//! use ping_fox::{PingFoxConfig, PingReceive, PingReceiveData, PingSentToken, ProbeKind, SocketType};
 //! use std::net::Ipv4Addr;
 //! use std::time::Duration;
 //!
 //! // ### Configure the library:
 //! // - `socket_type` can be `SocketType::RAW` or `SocketType::DGRAM`.
 //! // - Use `SocketType::DGRAM` to avoid the need for elevated privileges.
+//! // - Payload size and pattern, TTL and interface keep their defaults here.
+//! // - `probe_kind` can be `ProbeKind::Icmp`, `ProbeKind::Tcp { port }` or `ProbeKind::Udp { port }`.
 //! let config = PingFoxConfig {
 //!     socket_type: SocketType::DGRAM,
 //!     timeout: Duration::from_secs(1),
 //!     channel_size: 1,
+//!     probe_kind: ProbeKind::Icmp,
+//!     ..PingFoxConfig::default()
 //! };
 //!
 //! // ### Create a ping sender and a ping receiver.
-//! let (mut ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
+//! let (ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
 //!
 //! // ### Call `PingSender::send_to`
 //! let token: PingSentToken = ping_sender
@@ -35,6 +39,7 @@
 //!         ttl,
 //!         sequence_number,
 //!         ping_duration,
+//!         ..
 //!     }) => {
 //!         println!(
 //!             "{package_size} bytes from {ip_addr}: \
@@ -45,6 +50,9 @@
 //!     PingReceive::Timeout => {
 //!         println!("timeout");
 //!     }
+//!     PingReceive::Closed => {
+//!         println!("shut down");
+//!     }
 //! };
 //! ```
 //!
@@ -54,8 +62,33 @@
 #![warn(missing_docs)]
 
 pub use crate::ping_fox::*;
+pub use clock::*;
+pub use ping_iter::*;
 pub use ping_receive::*;
+pub use reachability::*;
+pub use session::*;
 
+#[cfg(feature = "async-io")]
+pub mod asynchronous;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod clock;
 mod details;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+pub mod metrics;
+#[cfg(feature = "serde")]
+pub mod output;
 mod ping_fox;
+mod ping_iter;
 mod ping_receive;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+#[cfg(feature = "python")]
+#[doc(hidden)]
+pub mod python;
+mod reachability;
+mod session;
+#[cfg(feature = "testing")]
+pub mod testing;
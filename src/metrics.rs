@@ -0,0 +1,494 @@
+//! Round-trip time statistics: percentiles from an HDR-style histogram, RFC 3550 interarrival
+//! jitter, and an estimate of the voice quality (R-factor and MOS) of the E-model.
+//!
+//! [`RttMetrics`] keeps these statistics over sliding time windows:
+//!
+//! ```
+//! use ping_fox::metrics::RttMetrics;
+//! use ping_fox::PingReceive;
+//! use std::time::Duration;
+//!
+//! let mut rtt_metrics = RttMetrics::default();
+//! rtt_metrics.record(&PingReceive::Timeout);
+//!
+//! let last_minute = rtt_metrics.window(Duration::from_secs(60));
+//! assert_eq!(1, last_minute.sent);
+//! assert_eq!(None, last_minute.histogram.value_at_quantile(0.99));
+//! assert!(last_minute.mos() < 1.5);
+//! ```
+use crate::{Clock, PingReceive, SystemClock};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Values below 2^SUB_BUCKET_BITS microseconds are recorded exactly. Larger values share a bucket
+// with values differing by less than 1 / 2^(SUB_BUCKET_BITS - 1), i.e. less than 0.8 %.
+const SUB_BUCKET_BITS: u32 = 8;
+const SUB_BUCKET_COUNT: u64 = 1 << SUB_BUCKET_BITS;
+const SUB_BUCKET_HALF_COUNT: u64 = SUB_BUCKET_COUNT / 2;
+
+// Gain of the jitter estimator of RFC 3550, section 6.4.1.
+const JITTER_GAIN: f64 = 1.0 / 16.0;
+
+fn as_micros(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
+fn bucket_index(value: u64) -> u64 {
+    if value < SUB_BUCKET_COUNT {
+        return value;
+    }
+    // `value >> shift` is in [SUB_BUCKET_HALF_COUNT, SUB_BUCKET_COUNT).
+    let shift = u64::from(value.ilog2() - (SUB_BUCKET_BITS - 1));
+    SUB_BUCKET_COUNT + (shift - 1) * SUB_BUCKET_HALF_COUNT + ((value >> shift) - SUB_BUCKET_HALF_COUNT)
+}
+
+fn highest_equivalent_value(index: u64) -> u64 {
+    if index < SUB_BUCKET_COUNT {
+        return index;
+    }
+    let shift = (index - SUB_BUCKET_COUNT) / SUB_BUCKET_HALF_COUNT + 1;
+    let sub_bucket = (index - SUB_BUCKET_COUNT) % SUB_BUCKET_HALF_COUNT + SUB_BUCKET_HALF_COUNT;
+    let value = (u128::from(sub_bucket + 1) << shift) - 1;
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+/// A histogram of durations with a resolution of one microsecond and a relative error below 1 %,
+/// like an HDR histogram with two significant digits.
+///
+/// Only buckets which hold values take up memory. Serialized, the buckets and values are integer
+/// numbers of microseconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Histogram {
+    counts: BTreeMap<u64, u64>,
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    /// Creates an empty histogram.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `value`.
+    pub fn record(&mut self, value: Duration) {
+        let value = as_micros(value);
+        *self.counts.entry(bucket_index(value)).or_default() += 1;
+        self.min = if self.count == 0 { value } else { self.min.min(value) };
+        self.max = self.max.max(value);
+        self.count += 1;
+        self.sum += u128::from(value);
+    }
+
+    /// Adds all values of `other`.
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.count == 0 {
+            return;
+        }
+        for (index, count) in &other.counts {
+            *self.counts.entry(*index).or_default() += count;
+        }
+        self.min = if self.count == 0 { other.min } else { self.min.min(other.min) };
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+        self.sum += other.sum;
+    }
+
+    /// Returns the number of values.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the smallest value, or `None` if the histogram is empty.
+    #[must_use]
+    pub fn min(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_micros(self.min))
+    }
+
+    /// Returns the largest value, or `None` if the histogram is empty.
+    #[must_use]
+    pub fn max(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_micros(self.max))
+    }
+
+    /// Returns the arithmetic mean, or `None` if the histogram is empty.
+    #[must_use]
+    pub fn mean(&self) -> Option<Duration> {
+        let mean = self.sum.checked_div(u128::from(self.count))?;
+        Some(Duration::from_micros(u64::try_from(mean).unwrap_or(u64::MAX)))
+    }
+
+    /// Returns the value below or at which the fraction `quantile` of all values lies, e.g. the
+    /// 99th percentile for 0.99. Returns `None` if the histogram is empty.
+    ///
+    /// The result is the largest value of its bucket, but never more than the largest value.
+    #[must_use]
+    pub fn value_at_quantile(&self, quantile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        // The rank is at most `count`, so the conversions are exact enough and cannot overflow.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut n_values = 0;
+        let index = self
+            .counts
+            .iter()
+            .find(|(_, count)| {
+                n_values += **count;
+                n_values >= rank
+            })
+            .map_or(bucket_index(self.max), |(index, _)| *index);
+        Some(Duration::from_micros(
+            highest_equivalent_value(index).clamp(self.min, self.max),
+        ))
+    }
+}
+
+/// The interarrival jitter of RFC 3550, section 6.4.1, computed from round-trip times.
+///
+/// The difference of consecutive round-trip times takes the place of the difference of transit
+/// times. Each difference moves the estimate by 1/16 of its distance, so the estimate follows
+/// the last few dozen replies.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Jitter {
+    jitter: f64,
+    last_rtt: Option<Duration>,
+}
+
+impl Jitter {
+    /// Creates an estimator without any round-trip times.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the estimate with the round-trip time of the next reply.
+    pub fn update(&mut self, rtt: Duration) {
+        if let Some(last_rtt) = self.last_rtt {
+            let difference = (rtt.as_secs_f64() - last_rtt.as_secs_f64()).abs();
+            self.jitter += (difference - self.jitter) * JITTER_GAIN;
+        }
+        self.last_rtt = Some(rtt);
+    }
+
+    /// Returns the current estimate.
+    #[must_use]
+    pub fn value(&self) -> Duration {
+        Duration::from_secs_f64(self.jitter)
+    }
+}
+
+/// Estimates the R-factor of a voice call over a path with the given round-trip time, jitter and
+/// `loss`, a fraction between 0 and 1.
+///
+/// This is the simplification of the ITU-T G.107 E-model by Cole and Rosenbluth. The one-way
+/// delay is taken as half the round-trip time, and twice the jitter is added for the jitter
+/// buffer. The result lies between 0 (unusable) and 93.2 (best possible).
+#[must_use]
+pub fn r_factor(rtt: Duration, jitter: Duration, loss: f64) -> f64 {
+    let effective_latency_ms = (rtt.as_secs_f64() / 2.0 + 2.0 * jitter.as_secs_f64()) * 1000.0 + 10.0;
+    let r_factor = if effective_latency_ms < 160.0 {
+        93.2 - effective_latency_ms / 40.0
+    } else {
+        93.2 - (effective_latency_ms - 120.0) / 10.0
+    };
+    (r_factor - 2.5 * loss.clamp(0.0, 1.0) * 100.0).clamp(0.0, 100.0)
+}
+
+/// Converts an R-factor to a mean opinion score (MOS) between 1 (bad) and 4.5 (excellent), as in
+/// ITU-T G.107.
+#[must_use]
+pub fn mos(r_factor: f64) -> f64 {
+    let r_factor = r_factor.clamp(0.0, 100.0);
+    (1.0 + 0.035 * r_factor + 0.000_007 * r_factor * (r_factor - 60.0) * (100.0 - r_factor)).clamp(1.0, 4.5)
+}
+
+/// Statistics over a window of time, see [`RttMetrics::window`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetricsSnapshot {
+    /// Number of pings with an outcome in the window.
+    pub sent: u64,
+    /// Number of replies in the window.
+    pub received: u64,
+    /// Round-trip times of the replies in the window.
+    pub histogram: Histogram,
+    /// The RFC 3550 jitter after the last reply, see [`Jitter`]. It is the same for all windows.
+    ///
+    /// Serialized as an integer number of microseconds.
+    #[cfg_attr(feature = "serde", serde(with = "crate::details::serde_with::duration_as_micros"))]
+    pub jitter: Duration,
+}
+
+impl MetricsSnapshot {
+    /// Returns the fraction of pings without a reply, or 0 if there were none.
+    #[must_use]
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let loss = (self.sent - self.received) as f64 / self.sent as f64;
+        loss
+    }
+
+    /// Returns the R-factor from the mean round-trip time, the jitter and the loss, see
+    /// [`r_factor`].
+    #[must_use]
+    pub fn r_factor(&self) -> f64 {
+        r_factor(self.histogram.mean().unwrap_or_default(), self.jitter, self.loss())
+    }
+
+    /// Returns the mean opinion score, see [`mos`].
+    #[must_use]
+    pub fn mos(&self) -> f64 {
+        mos(self.r_factor())
+    }
+}
+
+struct Slot {
+    index: u64,
+    sent: u64,
+    histogram: Histogram,
+}
+
+/// Round-trip time statistics over sliding time windows.
+///
+/// Outcomes are collected in slots of `slot_length`, and slots older than `retention` are
+/// dropped. A window therefore starts at a slot boundary and can be up to `slot_length` longer
+/// than asked for.
+pub struct RttMetrics {
+    slot_length: Duration,
+    n_slots: u64,
+    clock: Arc<dyn Clock>,
+    start: Instant,
+    slots: VecDeque<Slot>,
+    jitter: Jitter,
+}
+
+impl Default for RttMetrics {
+    /// Returns metrics with slots of ten seconds which are kept for 15 minutes.
+    fn default() -> Self {
+        let slot_length = Duration::from_secs(10);
+        Self::new(slot_length, slot_length * 90)
+    }
+}
+
+impl RttMetrics {
+    /// Creates metrics with slots of `slot_length` which are kept for `retention`.
+    #[must_use]
+    pub fn new(slot_length: Duration, retention: Duration) -> Self {
+        let slot_length = slot_length.max(Duration::from_millis(1));
+        let n_slots = u64::try_from(retention.as_nanos().div_ceil(slot_length.as_nanos())).unwrap_or(u64::MAX);
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        RttMetrics { slot_length, n_slots, start: clock.now(), clock, slots: VecDeque::new(), jitter: Jitter::new() }
+    }
+
+    /// Uses `clock` to assign outcomes to slots.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.start = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    fn current_slot_index(&self) -> u64 {
+        let elapsed = self.clock.now().saturating_duration_since(self.start);
+        u64::try_from(elapsed.as_nanos() / self.slot_length.as_nanos()).unwrap_or(u64::MAX)
+    }
+
+    /// Records the outcome of a ping. A [`PingReceive::Timeout`] counts as a loss, and a
+    /// [`PingReceive::Closed`] is ignored.
+    pub fn record(&mut self, outcome: &PingReceive) {
+        let ping_duration = match outcome {
+            PingReceive::Data(data) => Some(data.ping_duration),
+            PingReceive::Timeout => None,
+            // Not an outcome of a ping.
+            PingReceive::Closed => return,
+        };
+        let index = self.current_slot_index();
+        while self
+            .slots
+            .front()
+            .is_some_and(|slot| index.saturating_sub(slot.index) >= self.n_slots)
+        {
+            self.slots.pop_front();
+        }
+        if self.slots.back().is_none_or(|slot| slot.index != index) {
+            self.slots.push_back(Slot { index, sent: 0, histogram: Histogram::new() });
+        }
+        let Some(slot) = self.slots.back_mut() else {
+            return;
+        };
+        slot.sent += 1;
+        if let Some(ping_duration) = ping_duration {
+            slot.histogram.record(ping_duration);
+            self.jitter.update(ping_duration);
+        }
+    }
+
+    /// Returns the statistics of the last `length`, e.g. of the last minute.
+    #[must_use]
+    pub fn window(&self, length: Duration) -> MetricsSnapshot {
+        let index = self.current_slot_index();
+        let n_slots = u64::try_from(length.as_nanos().div_ceil(self.slot_length.as_nanos())).unwrap_or(u64::MAX);
+        let mut snapshot =
+            MetricsSnapshot { sent: 0, received: 0, histogram: Histogram::new(), jitter: self.jitter.value() };
+        for slot in self
+            .slots
+            .iter()
+            .filter(|slot| index.saturating_sub(slot.index) < n_slots.min(self.n_slots))
+        {
+            snapshot.sent += slot.sent;
+            snapshot.histogram.merge(&slot.histogram);
+        }
+        snapshot.received = snapshot.histogram.count();
+        snapshot
+    }
+
+    /// Returns the current RFC 3550 jitter, see [`Jitter`].
+    #[must_use]
+    pub fn jitter(&self) -> Duration {
+        self.jitter.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ManualClock, PingReceiveData, ProbeKind};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn reply(ping_duration: Duration) -> PingReceive {
+        PingReceive::Data(PingReceiveData {
+            package_size: 64,
+            ip_addr: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            ttl: 64,
+            sequence_number: 1,
+            ping_duration,
+            probe_kind: ProbeKind::Icmp,
+        })
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn bucket_index_and_highest_equivalent_value_agree() {
+        for value in [0, 1, 255, 256, 257, 1000, 123_456, 1 << 40, u64::MAX] {
+            let index = bucket_index(value);
+            let highest = highest_equivalent_value(index);
+            assert!(highest >= value);
+            assert_eq!(index, bucket_index(highest));
+            assert!((highest - value) as f64 <= value as f64 / 128.0);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn histogram_percentiles() {
+        let mut histogram = Histogram::new();
+        for millis in 1..=1000 {
+            histogram.record(Duration::from_millis(millis));
+        }
+
+        let within_one_percent = |expected: u64, actual: Option<Duration>| {
+            let actual = actual.unwrap().as_secs_f64() * 1000.0;
+            assert!(
+                (actual - expected as f64).abs() <= expected as f64 / 100.0,
+                "{actual} vs {expected}"
+            );
+        };
+        within_one_percent(500, histogram.value_at_quantile(0.5));
+        within_one_percent(900, histogram.value_at_quantile(0.9));
+        within_one_percent(990, histogram.value_at_quantile(0.99));
+        within_one_percent(999, histogram.value_at_quantile(0.999));
+        assert_eq!(Some(Duration::from_secs(1)), histogram.value_at_quantile(1.0));
+        assert_eq!(Some(Duration::from_millis(1)), histogram.min());
+        assert_eq!(Some(Duration::from_micros(500_500)), histogram.mean());
+        assert_eq!(None, Histogram::new().value_at_quantile(0.5));
+    }
+
+    #[test]
+    fn merged_histogram_contains_values_of_both() {
+        let mut histogram = Histogram::new();
+        histogram.record(Duration::from_millis(5));
+        let mut other = Histogram::new();
+        other.record(Duration::from_millis(1));
+        other.record(Duration::from_millis(9));
+
+        histogram.merge(&other);
+
+        assert_eq!(3, histogram.count());
+        assert_eq!(Some(Duration::from_millis(1)), histogram.min());
+        assert_eq!(Some(Duration::from_millis(9)), histogram.max());
+    }
+
+    #[test]
+    fn jitter_follows_rfc_3550() {
+        let mut jitter = Jitter::new();
+        jitter.update(Duration::from_millis(10));
+        assert_eq!(Duration::ZERO, jitter.value());
+
+        jitter.update(Duration::from_millis(26));
+        assert_eq!(Duration::from_millis(1), jitter.value());
+        jitter.update(Duration::from_millis(10));
+        assert_eq!(Duration::from_secs_f64(0.001 + (0.016 - 0.001) / 16.0), jitter.value());
+    }
+
+    #[test]
+    fn mos_decreases_with_latency_and_loss() {
+        let good = mos(r_factor(Duration::from_millis(20), Duration::from_millis(1), 0.0));
+        let slow = mos(r_factor(Duration::from_millis(600), Duration::from_millis(1), 0.0));
+        let lossy = mos(r_factor(Duration::from_millis(20), Duration::from_millis(1), 0.1));
+
+        assert!(good > 4.3 && good <= 4.5);
+        assert!(slow < good);
+        assert!(lossy < good);
+        assert!((1.0..=4.5).contains(&mos(r_factor(Duration::from_secs(10), Duration::ZERO, 1.0))));
+    }
+
+    #[test]
+    fn windows_only_contain_recent_outcomes() {
+        let slot_length = Duration::from_secs(10);
+        let clock = Arc::new(ManualClock::new());
+        let mut rtt_metrics = RttMetrics::new(slot_length, slot_length * 30).with_clock(clock.clone());
+
+        rtt_metrics.record(&reply(Duration::from_millis(100)));
+        rtt_metrics.record(&PingReceive::Timeout);
+        clock.advance(slot_length * 12);
+        rtt_metrics.record(&reply(Duration::from_millis(10)));
+
+        let last_minute = rtt_metrics.window(slot_length * 6);
+        assert_eq!(1, last_minute.sent);
+        assert_eq!(Some(Duration::from_millis(10)), last_minute.histogram.max());
+        let last_five_minutes = rtt_metrics.window(slot_length * 30);
+        assert_eq!(3, last_five_minutes.sent);
+        assert_eq!(2, last_five_minutes.received);
+        assert!((last_five_minutes.loss() - 1.0 / 3.0).abs() < 1e-9);
+
+        clock.advance(slot_length * 30);
+        rtt_metrics.record(&PingReceive::Timeout);
+        assert_eq!(1, rtt_metrics.window(slot_length * 90).sent);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_round_trips_through_json() {
+        let mut rtt_metrics = RttMetrics::default();
+        rtt_metrics.record(&reply(Duration::from_millis(10)));
+        rtt_metrics.record(&reply(Duration::from_millis(20)));
+        let snapshot = rtt_metrics.window(Duration::from_secs(30));
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+
+        assert_eq!(10_000, json["histogram"]["min"]);
+        assert_eq!(snapshot.jitter.as_micros(), u128::from(json["jitter"].as_u64().unwrap()));
+        assert_eq!(snapshot, serde_json::from_value(json).unwrap());
+    }
+}
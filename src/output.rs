@@ -0,0 +1,162 @@
+//! Writers for machine-readable streams of [`PingReceive`] results.
+//!
+//! - [`NdjsonWriter`] writes one JSON object per line.
+//! - [`CsvWriter`] writes one CSV record per line, preceded by a header.
+//!
+//! ```
+//! use ping_fox::output::NdjsonWriter;
+//! use ping_fox::PingReceive;
+//!
+//! let mut writer = NdjsonWriter::new(Vec::new());
+//! writer.write(&PingReceive::Timeout).unwrap();
+//! assert_eq!(b"{\"type\":\"Timeout\"}\n".to_vec(), writer.into_inner());
+//! ```
+use crate::{PingReceive, PingReceiveData, ProbeKind};
+use std::io::{self, Write};
+
+/// Writes [`PingReceive`] results as newline-delimited JSON (NDJSON).
+pub struct NdjsonWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    /// Creates an `NdjsonWriter` writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        NdjsonWriter { writer }
+    }
+
+    /// Writes `ping_receive` as a JSON object followed by a newline.
+    pub fn write(&mut self, ping_receive: &PingReceive) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, ping_receive)?;
+        self.writer.write_all(b"\n")
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Writes [`PingReceive`] results as comma-separated values (CSV).
+///
+/// The columns are `type,ip_addr,package_size,ttl,sequence_number,ping_duration_us,probe_kind,probe_port`.
+/// Columns without a value (e.g. all but `type` for a timeout) are left empty.
+pub struct CsvWriter<W> {
+    writer: W,
+    is_header_written: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Creates a `CsvWriter` writing to `writer`. The header is written together with the first record.
+    pub fn new(writer: W) -> Self {
+        CsvWriter { writer, is_header_written: false }
+    }
+
+    /// Writes `ping_receive` as a CSV record.
+    pub fn write(&mut self, ping_receive: &PingReceive) -> io::Result<()> {
+        if !self.is_header_written {
+            writeln!(
+                self.writer,
+                "type,ip_addr,package_size,ttl,sequence_number,ping_duration_us,probe_kind,probe_port"
+            )?;
+            self.is_header_written = true;
+        }
+        match ping_receive {
+            PingReceive::Data(PingReceiveData {
+                package_size,
+                ip_addr,
+                ttl,
+                sequence_number,
+                ping_duration,
+                probe_kind,
+            }) => {
+                let (probe_kind, probe_port) = match probe_kind {
+                    ProbeKind::Icmp => ("Icmp", None),
+                    ProbeKind::Tcp { port } => ("Tcp", Some(port)),
+                    ProbeKind::Udp { port } => ("Udp", Some(port)),
+                };
+                writeln!(
+                    self.writer,
+                    "Data,{ip_addr},{package_size},{ttl},{sequence_number},{},{probe_kind},{}",
+                    ping_duration.as_micros(),
+                    probe_port.map(ToString::to_string).unwrap_or_default()
+                )
+            }
+            PingReceive::Timeout => writeln!(self.writer, "Timeout,,,,,,,"),
+            PingReceive::Closed => writeln!(self.writer, "Closed,,,,,,,"),
+        }
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    fn receive_data(probe_kind: ProbeKind) -> PingReceive {
+        PingReceive::Data(PingReceiveData {
+            package_size: 64,
+            ip_addr: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            ttl: 63,
+            sequence_number: 7,
+            ping_duration: Duration::from_nanos(1_234_567),
+            probe_kind,
+        })
+    }
+
+    #[test]
+    fn ndjson_writer_writes_one_object_per_line() {
+        let mut writer = NdjsonWriter::new(Vec::new());
+
+        writer.write(&receive_data(ProbeKind::Tcp { port: 443 })).unwrap();
+        writer.write(&PingReceive::Timeout).unwrap();
+
+        assert_eq!(
+            "{\"type\":\"Data\",\"package_size\":64,\"ip_addr\":\"192.0.2.1\",\"ttl\":63,\"sequence_number\":7,\
+             \"ping_duration\":1234,\"probe_kind\":{\"kind\":\"Tcp\",\"port\":443}}\n\
+             {\"type\":\"Timeout\"}\n",
+            String::from_utf8(writer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn ndjson_round_trip() {
+        let mut writer = NdjsonWriter::new(Vec::new());
+        writer.write(&receive_data(ProbeKind::Icmp)).unwrap();
+        let output = writer.into_inner();
+
+        let ping_receive: PingReceive = serde_json::from_slice(&output).unwrap();
+
+        match ping_receive {
+            PingReceive::Data(data) => {
+                assert_eq!(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), data.ip_addr);
+                assert_eq!(Duration::from_micros(1234), data.ping_duration);
+                assert_eq!(ProbeKind::Icmp, data.probe_kind);
+            }
+            PingReceive::Timeout | PingReceive::Closed => panic!("expected data"),
+        }
+    }
+
+    #[test]
+    fn csv_writer_writes_header_once() {
+        let mut writer = CsvWriter::new(Vec::new());
+
+        writer.write(&receive_data(ProbeKind::Icmp)).unwrap();
+        writer.write(&receive_data(ProbeKind::Udp { port: 33434 })).unwrap();
+        writer.write(&PingReceive::Timeout).unwrap();
+
+        assert_eq!(
+            "type,ip_addr,package_size,ttl,sequence_number,ping_duration_us,probe_kind,probe_port\n\
+             Data,192.0.2.1,64,63,7,1234,Icmp,\n\
+             Data,192.0.2.1,64,63,7,1234,Udp,33434\n\
+             Timeout,,,,,,,\n",
+            String::from_utf8(writer.into_inner()).unwrap()
+        );
+    }
+}
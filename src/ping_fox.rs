@@ -1,6 +1,10 @@
 use crate::details;
-use crate::PingReceive;
+pub use crate::details::icmp::v4::{ParseError, TSocket};
+pub use crate::details::in_flight::WouldExceedInFlight;
+use crate::{Clock, PingReceive};
 use std::net::Ipv4Addr;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -11,8 +15,57 @@ pub struct PingFoxConfig {
     pub socket_type: SocketType,
     /// Timeout for `receive` calls on a socket.
     pub timeout: Duration,
-    /// Size of the communiation channel used between a [`PingSender`] and a [`PingReceiver`].
+    /// Maximum number of pings in flight, i.e. sent by a [`PingSender`] and not yet matched to a
+    /// reply by a [`PingReceiver`]. At least one ping can be in flight.
+    ///
+    /// Beyond this, [`PingSender::send_to`] fails with [`WouldExceedInFlight`] instead of blocking.
+    /// A ping without a reply stops counting once it is older than `timeout`.
     pub channel_size: usize,
+    /// The kind of probe sent to a target.
+    pub probe_kind: ProbeKind,
+    /// Number of payload bytes in an ICMP echo request.
+    pub payload_size: usize,
+    /// Pattern which is repeated to fill the payload of an ICMP echo request.
+    ///
+    /// The payload is random if the pattern is empty.
+    pub payload_pattern: Vec<u8>,
+    /// Time to live (TTL) of ICMP echo requests. The system default is used if `None`.
+    pub ttl: Option<u8>,
+    /// Name of the network interface, or local IPv4 address, ICMP echo requests are sent from.
+    ///
+    /// Binding to an interface by name may need elevated privileges.
+    pub interface: Option<String>,
+    /// Path of the Linux network namespace probes are sent from, e.g. `/var/run/netns/blue` or
+    /// `/proc/<pid>/ns/net`. The namespace of the process is used if `None`.
+    ///
+    /// Sockets are created on a helper thread which enters the namespace, so one process can send
+    /// probes from many namespaces. Entering a namespace needs `CAP_SYS_ADMIN`.
+    pub network_namespace: Option<PathBuf>,
+    /// Path of a pcap file every sent and received ICMP message is written to, e.g. for
+    /// inspection with Wireshark. No file is written if `None`.
+    ///
+    /// Sockets do not return IP headers, so ping-fox synthesizes them. The local address in these
+    /// headers is 0.0.0.0. Only ICMP probes are captured.
+    pub pcap_file: Option<PathBuf>,
+}
+
+impl Default for PingFoxConfig {
+    /// Returns a configuration for ICMP echo requests with 56 random payload bytes on a
+    /// [`SocketType::DGRAM`] socket with a timeout of one second.
+    fn default() -> Self {
+        PingFoxConfig {
+            socket_type: SocketType::DGRAM,
+            timeout: Duration::from_secs(1),
+            channel_size: 1,
+            probe_kind: ProbeKind::Icmp,
+            payload_size: details::icmp::v4::icmpv4::PAYLOAD_SIZE,
+            payload_pattern: Vec::new(),
+            ttl: None,
+            interface: None,
+            network_namespace: None,
+            pcap_file: None,
+        }
+    }
 }
 
 /// Type of socket used for network communication.
@@ -31,30 +84,93 @@ pub enum SocketType {
     RAW,
 }
 
+/// Kind of probe used to determine whether a target is reachable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(tag = "kind"))]
+pub enum ProbeKind {
+    /// ICMP echo request.
+    ///
+    /// The socket is determined by [`PingFoxConfig::socket_type`].
+    Icmp,
+    /// TCP connection attempt to a port.
+    ///
+    /// The measured duration is the time until the SYN-ACK or a RST arrives. Both are taken as a
+    /// reply. This works where ICMP is filtered and needs no elevated privileges.
+    Tcp {
+        /// Destination port of the connection attempt.
+        port: u16,
+    },
+    /// UDP datagram to a port, usually a closed one.
+    ///
+    /// An ICMP port unreachable error or any reply from the target is taken as a reply. As with
+    /// `traceroute -U`, the sequence number is encoded in the destination port: the n-th probe to
+    /// a target is sent to `port + n - 1`. This needs no elevated privileges.
+    Udp {
+        /// Destination port of the first datagram.
+        port: u16,
+    },
+}
+
 /// A `PingSentToken` represents an evidence that a ping message has been sent.
 // The attribute non_exhaustive prevents construction outside of this crate.
 #[non_exhaustive]
 pub struct PingSentToken {}
 
 /// Structure used for sending ping echo messages.
-pub struct PingSender(details::PingSender<details::icmp::v4::Socket>);
+///
+/// A `PingSender` can be cloned and shared between threads. All clones send through the same
+/// socket, share the pings in flight and count sequence numbers per destination together, so the
+/// replies to all of them arrive at the one [`PingReceiver`].
+#[derive(Clone)]
+pub struct PingSender(details::PingSender<details::Probe>);
 impl PingSender {
     /// Sends a ping echo message and returns a [`PingSentToken`].
     ///
     /// # Arguments
     ///
     /// * `ip` - The address to send the ping to.
-    pub fn send_to(&mut self, ip: Ipv4Addr) -> details::PingResult<PingSentToken> {
+    ///
+    /// Never blocks on the [`PingReceiver`]: if [`PingFoxConfig::channel_size`] pings are in
+    /// flight, the error wraps a [`WouldExceedInFlight`] and no ping is sent.
+    pub fn send_to(&self, ip: Ipv4Addr) -> details::PingResult<PingSentToken> {
         self.0.send_to(ip)
     }
+
+    /// Returns the number of pings in flight, see [`PingFoxConfig::channel_size`].
+    #[must_use]
+    pub fn in_flight(&self) -> usize {
+        self.0.in_flight()
+    }
+
+    /// Returns the maximum number of pings in flight, see [`PingFoxConfig::channel_size`].
+    #[must_use]
+    pub fn max_in_flight(&self) -> usize {
+        self.0.max_in_flight()
+    }
+
+    pub(crate) fn send(&self, ip: Ipv4Addr) -> details::PingResult<u16> {
+        self.0.send(ip).map(u16::from)
+    }
+
+    /// Returns a [`ShutdownHandle`] for this `PingSender`, its clones and its [`PingReceiver`].
+    #[must_use]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.0.shared_probe().clone())
+    }
 }
 
 /// Structure used for receiving ping echo reply messages.
-pub struct PingReceiver(details::PingReceiver<details::icmp::v4::Socket>);
+///
+/// A `PingReceiver` can be waited for in an event loop like `epoll` or, with the `mio` feature, a
+/// `mio::Poll`: its file descriptor is readable while [`PingReceiver::try_receive`] may return
+/// something. This is an epoll instance watching the socket, which stays valid after a shutdown.
+/// Custom [`TSocket`]s without a file descriptor only make it readable on shutdown.
+pub struct PingReceiver(details::PingReceiver<details::Probe>);
 impl PingReceiver {
     /// Blocks and waits for an echo reply message.
     /// Returns the data from the received echo reply message in [`PingReceive::Data`] or a
-    /// [`PingReceive::Timeout`] representing a timeout.
+    /// [`PingReceive::Timeout`] representing a timeout. After a shutdown, it returns
+    /// [`PingReceive::Closed`] without waiting.
     ///
     /// # Arguments
     ///
@@ -62,25 +178,160 @@ impl PingReceiver {
     pub fn receive(&mut self, token: PingSentToken) -> details::PingResult<PingReceive> {
         self.0.receive(token)
     }
+
+    /// Returns the next echo reply message in [`PingReceive::Data`] without waiting, or `None` if
+    /// nothing has been received. After a shutdown, it returns [`PingReceive::Closed`].
+    ///
+    /// In an edge-triggered event loop, call it until it returns `None` whenever the file
+    /// descriptor of the `PingReceiver` is readable. A custom [`TSocket`] without a file descriptor
+    /// receives with [`TSocket::try_recv_from`].
+    pub fn try_receive(&mut self) -> details::PingResult<Option<PingReceive>> {
+        self.0.try_receive()
+    }
+
+    pub(crate) fn receive_within(&mut self, token: PingSentToken, timeout: Duration) -> details::PingResult<PingReceive> {
+        self.0.receive_within(token, timeout)
+    }
+
+    // The clock on which timeouts are measured.
+    pub(crate) fn clock(&self) -> Arc<dyn Clock> {
+        self.0.clock().clone()
+    }
+
+    #[cfg(feature = "async-io")]
+    pub(crate) fn readiness_fd(&self) -> std::io::Result<BorrowedFd<'_>> {
+        self.0.readiness_fd()
+    }
+
+    #[cfg(feature = "async-io")]
+    pub(crate) fn timeout(&self) -> Duration {
+        self.0.timeout()
+    }
+
+    #[cfg(feature = "async-io")]
+    pub(crate) fn has_socket_fd(&self) -> bool {
+        self.0.has_socket_fd()
+    }
+
+    /// Returns a [`ShutdownHandle`] for this `PingReceiver` and its [`PingSender`]s.
+    #[must_use]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.0.shared_probe().clone())
+    }
+}
+
+impl AsFd for PingReceiver {
+    /// Returns the file descriptor to wait for in an event loop, see [`PingReceiver`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the epoll instance cannot be created, e.g. because the process has run out of
+    /// file descriptors. It is created on the first call only.
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0
+            .readiness_fd()
+            .expect("could not create epoll instance of PingReceiver")
+    }
+}
+
+impl AsRawFd for PingReceiver {
+    /// Returns the file descriptor to wait for in an event loop, see [`AsFd::as_fd`].
+    fn as_raw_fd(&self) -> RawFd {
+        self.as_fd().as_raw_fd()
+    }
+}
+
+#[cfg(feature = "mio")]
+impl mio::event::Source for PingReceiver {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.0.readiness_fd()?.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.0.readiness_fd()?.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.0.readiness_fd()?.as_raw_fd()).deregister(registry)
+    }
+}
+
+/// Shuts down a [`PingSender`], its clones and its [`PingReceiver`], e.g. from a signal handler
+/// thread.
+///
+/// A receiver blocked in [`PingReceiver::receive`] wakes up immediately and returns
+/// [`PingReceive::Closed`], as do all later receives. Sending fails. The socket is closed as
+/// soon as the sends and receives in progress return, even while the `PingSender` and the
+/// `PingReceiver` are still alive.
+///
+/// A custom [`TSocket`] without a file descriptor notices the shutdown when its receive returns,
+/// at the latest after the timeout.
+#[derive(Clone)]
+pub struct ShutdownHandle(Arc<details::SharedProbe<details::Probe>>);
+impl ShutdownHandle {
+    /// Shuts down. Shutting down again has no effect.
+    pub fn shutdown(&self) {
+        self.0.shut_down();
+    }
+
+    /// Returns whether [`ShutdownHandle::shutdown`] has been called.
+    #[must_use]
+    pub fn is_shut_down(&self) -> bool {
+        self.0.is_shut_down()
+    }
+
+    /// Waits up to `timeout` for a shutdown and returns whether there was one, e.g. to pause
+    /// between pings.
+    #[must_use]
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.0.wait_timeout(timeout)
+    }
 }
 
 /// Principal function in ping-fox. It creates a [`PingSender`] and a [`PingReceiver`].
 pub fn create(config: &PingFoxConfig) -> details::PingResult<(PingSender, PingReceiver)> {
-    let socket = details::icmp::v4::Socket::new(config.socket_type, config.timeout)?;
-    let (sender, receiver) = create_with_socket::<details::icmp::v4::Socket>(socket, config.channel_size);
-    Ok((PingSender(sender), PingReceiver(receiver)))
+    let probe = details::Probe::new(config)?;
+    Ok(create_from_probe(probe, Arc::new(crate::SystemClock), config))
 }
 
-fn create_with_socket<S>(socket: S, channel_size: usize) -> (details::PingSender<S>, details::PingReceiver<S>)
+/// Creates a [`PingSender`] and a [`PingReceiver`] which send ICMP echo requests through a
+/// custom `socket`, with time stamps taken from `clock`.
+///
+/// Of `config`, only `channel_size`, `timeout` and the payload settings are used. The timeout is
+/// measured on `clock`. It determines when a ping without a reply stops counting as in flight
+/// and, if the socket has a file descriptor, how long a receive waits for it. TTL, interface and
+/// network namespace are properties of the socket.
+pub fn create_with_socket<S>(socket: S, clock: Arc<dyn Clock>, config: &PingFoxConfig) -> (PingSender, PingReceiver)
 where
-    S: details::icmp::v4::TSocket + 'static,
+    S: TSocket + 'static,
 {
-    let icmpv4 = Arc::new(details::icmp::v4::IcmpV4::new(socket));
-    let (send_record_tx, send_record_rx) = details::records::ping_send_record_channel(channel_size);
-    let ping_data_buffer = details::PingDataBuffer::new(send_record_rx);
+    let payload = details::icmp::v4::icmpv4::new_payload(config.payload_size, &config.payload_pattern);
+    let socket = details::icmp::v4::Socket::Custom(Box::new(socket));
+    let probe = details::Probe::IcmpV4(details::icmp::v4::IcmpV4::with_payload(socket, payload, clock.clone()));
+    create_from_probe(probe, clock, config)
+}
+
+// `clock` is the clock of `probe`.
+fn create_from_probe(probe: details::Probe, clock: Arc<dyn Clock>, config: &PingFoxConfig) -> (PingSender, PingReceiver) {
+    let (sender, receiver) = create_with_probe::<details::Probe>(probe, clock, config.channel_size, config.timeout);
+    (PingSender(sender), PingReceiver(receiver))
+}
+
+fn create_with_probe<P>(
+    probe: P,
+    clock: Arc<dyn Clock>,
+    max_in_flight: usize,
+    timeout: Duration,
+) -> (details::PingSender<P>, details::PingReceiver<P>)
+where
+    P: details::TProbe + 'static,
+{
+    let probe = Arc::new(details::SharedProbe::new(probe));
+    let in_flight = Arc::new(details::in_flight::InFlightTable::new(max_in_flight, timeout, clock.clone()));
+    let ping_data_buffer = details::PingDataBuffer::new(in_flight.clone());
     (
-        details::PingSender::new(icmpv4.clone(), send_record_tx),
-        details::PingReceiver::new(icmpv4, ping_data_buffer),
+        details::PingSender::new(probe.clone(), in_flight),
+        details::PingReceiver::new(probe, ping_data_buffer, timeout, clock),
     )
 }
 
@@ -88,6 +339,7 @@ where
 mod tests {
     use super::*;
     use details::icmp::v4::tests::SocketMock;
+    use details::icmp::v4::IcmpV4;
 
     #[test]
     fn ping_localhost_succeeds() {
@@ -95,7 +347,12 @@ mod tests {
         let channel_size = 4;
         let socket = SocketMock::new_default();
 
-        let (mut ping_sender, mut ping_receiver) = super::create_with_socket(socket, channel_size);
+        let (ping_sender, mut ping_receiver) = super::create_with_probe(
+            IcmpV4::new(socket),
+            Arc::new(crate::SystemClock),
+            channel_size,
+            Duration::from_secs(1),
+        );
         let token = ping_sender.send_to(ip).unwrap();
         let ping_response = ping_receiver.receive(token);
 
@@ -106,4 +363,153 @@ mod tests {
 
         assert!(ping_response.is_ok());
     }
+
+    #[test]
+    fn ping_duration_is_measured_with_injected_clock() {
+        let ip = Ipv4Addr::new(127, 0, 0, 1);
+        let clock = Arc::new(crate::ManualClock::new());
+
+        let (ping_sender, mut ping_receiver) =
+            super::create_with_socket(SocketMock::new_default(), clock.clone(), &PingFoxConfig::default());
+        let token = ping_sender.send_to(ip).unwrap();
+        clock.advance(Duration::from_micros(1234));
+        let ping_response = ping_receiver.receive(token).unwrap();
+
+        match ping_response {
+            PingReceive::Data(data) => assert_eq!(Duration::from_micros(1234), data.ping_duration),
+            PingReceive::Timeout | PingReceive::Closed => panic!("expected data"),
+        }
+    }
+
+    #[test]
+    fn sender_does_not_block_while_too_many_pings_are_in_flight() {
+        let ip = Ipv4Addr::new(127, 0, 0, 1);
+        let config = PingFoxConfig { channel_size: 2, ..PingFoxConfig::default() };
+        let (ping_sender, mut ping_receiver) =
+            super::create_with_socket(SocketMock::new_default(), Arc::new(crate::SystemClock), &config);
+
+        let tokens = [ping_sender.send_to(ip).unwrap(), ping_sender.send_to(ip).unwrap()];
+        let Err(error) = ping_sender.send_to(ip) else {
+            panic!("expected an error");
+        };
+        assert!(error.is::<WouldExceedInFlight>());
+        assert_eq!(2, ping_sender.in_flight());
+
+        for token in tokens {
+            assert!(matches!(ping_receiver.receive(token), Ok(PingReceive::Data(_))));
+        }
+        assert_eq!(0, ping_sender.in_flight());
+        assert!(ping_sender.send_to(ip).is_ok());
+    }
+
+    #[test]
+    fn ping_sender_is_clone_send_and_sync() {
+        fn assert_clone_send_sync<T: Clone + Send + Sync>() {}
+        assert_clone_send_sync::<PingSender>();
+    }
+
+    #[test]
+    fn shutdown_wakes_up_blocked_receiver() {
+        let config = PingFoxConfig { timeout: Duration::from_secs(30), ..PingFoxConfig::default() };
+        let socket = UdpSocketWithFd(std::net::UdpSocket::bind("127.0.0.1:0").unwrap());
+        let (ping_sender, mut ping_receiver) = super::create_with_socket(socket, Arc::new(crate::SystemClock), &config);
+        let shutdown_handle = ping_sender.shutdown_handle();
+
+        let start = std::time::Instant::now();
+        let receiver = std::thread::spawn(move || ping_receiver.receive(PingSentToken {}).unwrap());
+        std::thread::sleep(Duration::from_millis(10));
+        shutdown_handle.shutdown();
+
+        assert!(matches!(receiver.join().unwrap(), PingReceive::Closed));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(ping_sender.send_to(Ipv4Addr::LOCALHOST).is_err());
+    }
+
+    #[test]
+    fn try_receive_returns_at_once() {
+        let config = PingFoxConfig { timeout: Duration::from_secs(30), ..PingFoxConfig::default() };
+        let socket = UdpSocketWithFd(std::net::UdpSocket::bind("127.0.0.1:0").unwrap());
+        let (ping_sender, mut ping_receiver) = super::create_with_socket(socket, Arc::new(crate::SystemClock), &config);
+
+        let start = std::time::Instant::now();
+        assert!(ping_receiver.try_receive().unwrap().is_none());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(!details::shutdown::tests::is_readable(ping_receiver.as_raw_fd()));
+
+        ping_sender.shutdown_handle().shutdown();
+
+        assert!(details::shutdown::tests::is_readable(ping_receiver.as_raw_fd()));
+        assert!(matches!(ping_receiver.try_receive().unwrap(), Some(PingReceive::Closed)));
+    }
+
+    #[test]
+    fn try_receive_returns_at_once_after_skipped_message() {
+        let config = PingFoxConfig { timeout: Duration::from_secs(30), ..PingFoxConfig::default() };
+        let socket = UdpSocketWithMessages::new();
+        // ICMP port unreachable, which is not a reply to a ping.
+        socket.inject(&[3, 3, 0xFC, 0xFC, 0, 0, 0, 0]);
+        let (_ping_sender, mut ping_receiver) = super::create_with_socket(socket, Arc::new(crate::SystemClock), &config);
+
+        let start = std::time::Instant::now();
+        assert!(ping_receiver.try_receive().unwrap().is_none());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    // A socket which never receives anything, with a file descriptor to wait on.
+    struct UdpSocketWithFd(std::net::UdpSocket);
+
+    impl TSocket for UdpSocketWithFd {
+        fn send_to(&self, buf: &[u8], _addr: std::net::IpAddr) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, _buf: &mut [u8]) -> std::io::Result<(usize, std::net::IpAddr, u8)> {
+            Err(std::io::ErrorKind::WouldBlock.into())
+        }
+
+        fn raw_fd(&self) -> Option<std::os::fd::RawFd> {
+            Some(std::os::fd::AsRawFd::as_raw_fd(&self.0))
+        }
+    }
+
+    // A socket which receives the messages injected into it. Without messages, a receive blocks
+    // until the socket times out.
+    struct UdpSocketWithMessages(std::net::UdpSocket);
+
+    impl UdpSocketWithMessages {
+        fn new() -> Self {
+            let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            UdpSocketWithMessages(socket)
+        }
+
+        fn inject(&self, message: &[u8]) {
+            self.0.send_to(message, self.0.local_addr().unwrap()).unwrap();
+        }
+    }
+
+    impl TSocket for UdpSocketWithMessages {
+        fn send_to(&self, buf: &[u8], _addr: std::net::IpAddr) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, std::net::IpAddr, u8)> {
+            let (n, _) = self.0.recv_from(buf)?;
+            Ok((n, Ipv4Addr::LOCALHOST.into(), 64))
+        }
+
+        fn raw_fd(&self) -> Option<std::os::fd::RawFd> {
+            Some(std::os::fd::AsRawFd::as_raw_fd(&self.0))
+        }
+    }
+
+    #[test]
+    fn create_fails_if_network_namespace_does_not_exist() {
+        let config = PingFoxConfig {
+            network_namespace: Some(PathBuf::from("/var/run/netns/ping-fox-does-not-exist")),
+            ..PingFoxConfig::default()
+        };
+
+        assert!(super::create(&config).is_err());
+    }
 }
@@ -0,0 +1,308 @@
+use crate::details::sync::POLL_INTERVAL;
+use crate::details::{GenericError, PingResult};
+use crate::{PingFoxConfig, PingReceive, PingReceiver, PingSender, PingSentToken};
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+/// Returns a [`Ping`] builder for pinging `target` once per second, like the `ping` command.
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// use std::time::Duration;
+///
+/// let summary = ping_fox::ping("127.0.0.1".parse()?).count(3).interval(Duration::from_millis(200)).summary()?;
+/// println!("{} of {} pings received", summary.received, summary.transmitted);
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub fn ping(target: Ipv4Addr) -> Ping {
+    Ping { target, count: None, interval: Duration::from_secs(1), config: PingFoxConfig::default() }
+}
+
+/// Builder for pinging a single target, see [`ping`].
+pub struct Ping {
+    target: Ipv4Addr,
+    count: Option<u64>,
+    interval: Duration,
+    config: PingFoxConfig,
+}
+
+impl Ping {
+    /// Stops after `count` pings. Without a count, pinging goes on forever.
+    #[must_use]
+    pub fn count(mut self, count: u64) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    /// Waits `interval` from sending one ping to sending the next. The default is one second.
+    #[must_use]
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Waits up to `timeout` for each reply. The default is one second.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    /// Uses `config` for the socket and the probes, e.g. a [`crate::SocketType::RAW`] socket. This
+    /// replaces a timeout set before. The number of pings in flight is raised as needed for the
+    /// interval and the timeout.
+    #[must_use]
+    pub fn config(mut self, config: PingFoxConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Creates the socket and returns an iterator which sends a ping on every call of
+    /// [`Iterator::next`] and returns its reply or a timeout.
+    pub fn start(mut self) -> PingResult<Pings> {
+        // A ping without a reply stays in flight for the timeout, while further pings are sent.
+        let n_in_flight = self.config.timeout.as_nanos() / self.interval.as_nanos().max(1) + 2;
+        self.config.channel_size = self
+            .config
+            .channel_size
+            .max(usize::try_from(n_in_flight).unwrap_or(usize::MAX));
+        let (ping_sender, ping_receiver) = crate::create(&self.config)?;
+        Ok(Pings {
+            ping_sender,
+            ping_receiver,
+            target: self.target,
+            remaining: self.count,
+            interval: self.interval,
+            timeout: self.config.timeout,
+            next_send_time: None,
+            statistics: Statistics::default(),
+            error: None,
+        })
+    }
+
+    /// Sends all pings and returns their statistics. Without a [`Ping::count`], this never
+    /// returns.
+    pub fn summary(self) -> PingResult<PingSummary> {
+        let mut pings = self.start()?;
+        pings.by_ref().for_each(drop);
+        match pings.error {
+            Some(error) => Err(error),
+            None => Ok(pings.summary()),
+        }
+    }
+}
+
+/// Statistics of the pings to a single target, see [`Ping::summary`].
+///
+/// Round-trip times are serialized as integer numbers of microseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PingSummary {
+    /// The address the pings were sent to.
+    pub target: Ipv4Addr,
+    /// Number of pings sent.
+    pub transmitted: u64,
+    /// Number of replies received within the timeout.
+    pub received: u64,
+    /// Minimum round-trip time, or `None` without replies.
+    #[cfg_attr(feature = "serde", serde(with = "crate::details::serde_with::option_duration_as_micros"))]
+    pub min: Option<Duration>,
+    /// Mean round-trip time, or `None` without replies.
+    #[cfg_attr(feature = "serde", serde(with = "crate::details::serde_with::option_duration_as_micros"))]
+    pub avg: Option<Duration>,
+    /// Maximum round-trip time, or `None` without replies.
+    #[cfg_attr(feature = "serde", serde(with = "crate::details::serde_with::option_duration_as_micros"))]
+    pub max: Option<Duration>,
+    /// Mean deviation of the round-trip times as reported by `ping`, i.e. their standard
+    /// deviation, or `None` without replies.
+    #[cfg_attr(feature = "serde", serde(with = "crate::details::serde_with::option_duration_as_micros"))]
+    pub mdev: Option<Duration>,
+}
+
+impl PingSummary {
+    /// Returns the fraction of pings without a reply, or 0 if none were sent.
+    #[must_use]
+    pub fn loss(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let loss = (self.transmitted - self.received) as f64 / self.transmitted as f64;
+        loss
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Statistics {
+    transmitted: u64,
+    received: u64,
+    min: Option<Duration>,
+    max: Option<Duration>,
+    // Sums of the round-trip times and of their squares, in seconds.
+    sum: f64,
+    sum_of_squares: f64,
+}
+
+impl Statistics {
+    fn on_reply(&mut self, rtt: Duration) {
+        self.received += 1;
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+        self.sum += rtt.as_secs_f64();
+        self.sum_of_squares += rtt.as_secs_f64() * rtt.as_secs_f64();
+    }
+
+    fn summary(&self, target: Ipv4Addr) -> PingSummary {
+        let Statistics { transmitted, received, min, max, sum, sum_of_squares } = *self;
+        let (avg, mdev) = if received == 0 {
+            (None, None)
+        } else {
+            #[allow(clippy::cast_precision_loss)]
+            let n = received as f64;
+            let avg = sum / n;
+            let mdev = (sum_of_squares / n - avg * avg).max(0.0).sqrt();
+            (Some(Duration::from_secs_f64(avg)), Some(Duration::from_secs_f64(mdev)))
+        };
+        PingSummary { target, transmitted, received, min, avg, max, mdev }
+    }
+}
+
+/// Iterator over the outcomes of the pings to a single target, see [`Ping::start`].
+///
+/// Each item is the [`PingReceive::Data`] of the reply to one ping, or a [`PingReceive::Timeout`]
+/// if there was none within the timeout. Late replies to earlier pings are skipped. The iterator
+/// ends after [`Ping::count`] pings, or after a ping could not be sent, see [`Pings::error`].
+pub struct Pings {
+    ping_sender: PingSender,
+    ping_receiver: PingReceiver,
+    target: Ipv4Addr,
+    remaining: Option<u64>,
+    interval: Duration,
+    timeout: Duration,
+    next_send_time: Option<Instant>,
+    statistics: Statistics,
+    error: Option<GenericError>,
+}
+
+impl Pings {
+    /// Returns the statistics of the pings so far.
+    #[must_use]
+    pub fn summary(&self) -> PingSummary {
+        self.statistics.summary(self.target)
+    }
+
+    /// Returns the error which ended the iteration, if sending a ping failed.
+    #[must_use]
+    pub fn error(&self) -> Option<&(dyn Error + Send + Sync + 'static)> {
+        self.error.as_deref()
+    }
+
+    // Receives until the reply to the ping with `sequence_number` or the timeout.
+    fn receive(&mut self, sequence_number: u16) -> Option<PingReceive> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            match self.ping_receiver.receive_within(PingSentToken {}, wait) {
+                Ok(PingReceive::Data(data))
+                    if data.sequence_number == sequence_number && data.ip_addr == IpAddr::V4(self.target) =>
+                {
+                    self.statistics.on_reply(data.ping_duration);
+                    return Some(PingReceive::Data(data));
+                }
+                // A late reply to an earlier ping.
+                Ok(PingReceive::Data(_)) => {}
+                Ok(PingReceive::Timeout) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Some(PingReceive::Timeout);
+                    }
+                    std::thread::sleep(remaining.min(POLL_INTERVAL));
+                }
+                Ok(PingReceive::Closed) => return None,
+                // E.g. a reply which does not match any ping in flight.
+                Err(e) => {
+                    tracing::debug!("could not receive reply: {}", e);
+                    // A persistent error, e.g. of a closed custom socket, must not become a busy loop.
+                    std::thread::sleep(deadline.saturating_duration_since(Instant::now()).min(POLL_INTERVAL));
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Pings {
+    type Item = PingReceive;
+
+    fn next(&mut self) -> Option<PingReceive> {
+        if self.error.is_some() || self.remaining == Some(0) {
+            return None;
+        }
+        if let Some(next_send_time) = self.next_send_time {
+            std::thread::sleep(next_send_time.saturating_duration_since(Instant::now()));
+        }
+        self.next_send_time = Some(Instant::now() + self.interval);
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+        let sequence_number = match self.ping_sender.send(self.target) {
+            Ok(sequence_number) => sequence_number,
+            Err(e) => {
+                self.error = Some(e);
+                return None;
+            }
+        };
+        self.statistics.transmitted += 1;
+        self.receive(sequence_number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_has_loss_and_round_trip_times() {
+        let mut statistics = Statistics { transmitted: 4, ..Statistics::default() };
+        for rtt_ms in [1, 2, 3] {
+            statistics.on_reply(Duration::from_millis(rtt_ms));
+        }
+
+        let summary = statistics.summary(Ipv4Addr::LOCALHOST);
+
+        assert_eq!(3, summary.received);
+        assert!((summary.loss() - 0.25).abs() < 1e-9);
+        assert_eq!(Some(Duration::from_millis(1)), summary.min);
+        assert_eq!(Some(Duration::from_millis(3)), summary.max);
+        assert!(summary.avg.unwrap().abs_diff(Duration::from_millis(2)) < Duration::from_micros(1));
+        // The standard deviation of 1, 2 and 3 ms is the square root of 2/3 ms.
+        assert!(summary.mdev.unwrap().abs_diff(Duration::from_micros(816)) < Duration::from_micros(1));
+    }
+
+    #[test]
+    fn summary_without_replies_has_no_round_trip_times() {
+        let summary = Statistics { transmitted: 2, ..Statistics::default() }.summary(Ipv4Addr::LOCALHOST);
+
+        assert_eq!(None, summary.avg);
+        assert_eq!(None, summary.mdev);
+        assert!((summary.loss() - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn summary_serializes_round_trip_times_as_micros() {
+        let mut statistics = Statistics { transmitted: 2, ..Statistics::default() };
+        statistics.on_reply(Duration::from_micros(1500));
+        let summary = statistics.summary(Ipv4Addr::LOCALHOST);
+
+        let json = serde_json::to_value(summary).unwrap();
+
+        assert_eq!("127.0.0.1", json["target"]);
+        assert_eq!(1500, json["min"]);
+        assert_eq!(summary, serde_json::from_value(json).unwrap());
+        let json = serde_json::to_value(Statistics::default().summary(Ipv4Addr::LOCALHOST)).unwrap();
+        assert!(json["avg"].is_null());
+    }
+}
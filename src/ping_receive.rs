@@ -1,27 +1,44 @@
+use crate::ProbeKind;
 use std::net::IpAddr;
 use std::time::Duration;
 
 /// Structure representing ping receive cases.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(tag = "type"))]
 pub enum PingReceive {
     /// Case represeting the data from a received echo reply message.
     Data(PingReceiveData),
     /// Case representing a timeout on an attempt to receive an echo reply message.
     Timeout,
+    /// Case representing a receive after a [`crate::ShutdownHandle::shutdown`]. No further
+    /// replies can be received.
+    Closed,
 }
 
 /// Structure represeting a received echo reply message.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::module_name_repetitions)]
 pub struct PingReceiveData {
     /// The size of the payload in the received reply message.
     pub package_size: usize,
     /// The IP address of the host which sent the reply.
+    ///
+    /// Serialized as a string.
+    #[cfg_attr(feature = "serde", serde(with = "crate::details::serde_with::ip_addr_as_string"))]
     pub ip_addr: IpAddr,
     /// The time to live (TTL) of the received reply message.
+    ///
+    /// The TTL cannot be observed for [`ProbeKind::Tcp`] probes and for ICMP errors answering
+    /// [`ProbeKind::Udp`] probes. It is reported as 0 then.
     pub ttl: u8,
     /// The sequence number of the echo reply.
     pub sequence_number: u16,
     /// The measured duration between sending the echo message and receiving the reply.
+    ///
+    /// Serialized as an integer number of microseconds.
+    #[cfg_attr(feature = "serde", serde(with = "crate::details::serde_with::duration_as_micros"))]
     pub ping_duration: Duration,
+    /// The kind of probe which produced the reply.
+    pub probe_kind: ProbeKind,
 }
@@ -0,0 +1,58 @@
+use crate::details::sync::lock;
+use crate::prometheus::metrics::Metrics;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Serves `/metrics` until `should_stop` is set. A blocked `accept` is woken up by connecting to
+// the listener after setting `should_stop`.
+pub(crate) fn serve(listener: &TcpListener, metrics: &Arc<Mutex<Metrics>>, should_stop: &AtomicBool) {
+    for stream in listener.incoming() {
+        if should_stop.load(Ordering::SeqCst) {
+            break;
+        }
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_connection(&stream, metrics) {
+                    tracing::warn!("error handling HTTP request: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("error accepting HTTP connection: {}", e),
+        }
+    }
+}
+
+fn handle_connection(stream: &TcpStream, metrics: &Arc<Mutex<Metrics>>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the request headers.
+    let mut header_line = String::new();
+    while reader.read_line(&mut header_line)? > 0 && header_line.trim_end() != "" {
+        header_line.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = lock(metrics).render();
+            response("200 OK", "text/plain; version=0.0.4", &body)
+        }
+        (Some("GET"), Some(_)) => response("404 Not Found", "text/plain", "Not Found\n"),
+        _ => response("405 Method Not Allowed", "text/plain", "Method Not Allowed\n"),
+    };
+    let mut stream = stream;
+    stream.write_all(response.as_bytes())
+}
+
+fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
@@ -0,0 +1,166 @@
+use crate::PingReceiveData;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Upper bounds (in seconds) of the RTT histogram buckets.
+const RTT_BUCKETS: [f64; 12] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+#[derive(Default)]
+pub(crate) struct TargetMetrics {
+    sent: u64,
+    received: u64,
+    ttl: Option<u8>,
+    last_success: Option<SystemTime>,
+    // Cumulative counts per bucket in `RTT_BUCKETS`.
+    rtt_buckets: [u64; RTT_BUCKETS.len()],
+    rtt_sum: Duration,
+}
+
+#[derive(Default)]
+pub(crate) struct Metrics {
+    targets: BTreeMap<IpAddr, TargetMetrics>,
+}
+
+impl Metrics {
+    pub(crate) fn on_sent(&mut self, target: IpAddr) {
+        self.targets.entry(target).or_default().sent += 1;
+    }
+
+    pub(crate) fn on_received(&mut self, data: &PingReceiveData, receive_time: SystemTime) {
+        let target_metrics = self.targets.entry(data.ip_addr).or_default();
+        target_metrics.received += 1;
+        target_metrics.ttl = Some(data.ttl);
+        target_metrics.last_success = Some(receive_time);
+        target_metrics.rtt_sum += data.ping_duration;
+        let rtt = data.ping_duration.as_secs_f64();
+        for (bucket, upper_bound) in target_metrics.rtt_buckets.iter_mut().zip(RTT_BUCKETS) {
+            if rtt <= upper_bound {
+                *bucket += 1;
+            }
+        }
+    }
+
+    // Renders the metrics in the Prometheus text exposition format (version 0.0.4).
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+        self.render_family(
+            &mut out,
+            "ping_fox_probe_sent_total",
+            "counter",
+            "Number of probes sent.",
+            |t| Some(t.sent.to_string()),
+        );
+        self.render_family(
+            &mut out,
+            "ping_fox_probe_received_total",
+            "counter",
+            "Number of replies received.",
+            |t| Some(t.received.to_string()),
+        );
+        self.render_family(
+            &mut out,
+            "ping_fox_probe_loss_ratio",
+            "gauge",
+            "Ratio of probes without a reply.",
+            |t| {
+                #[allow(clippy::cast_precision_loss)]
+                (t.sent > 0).then(|| (1.0 - (t.received.min(t.sent) as f64 / t.sent as f64)).to_string())
+            },
+        );
+        self.render_family(&mut out, "ping_fox_probe_ttl", "gauge", "TTL of the last reply.", |t| {
+            t.ttl.map(|ttl| ttl.to_string())
+        });
+        self.render_family(
+            &mut out,
+            "ping_fox_probe_last_success_timestamp_seconds",
+            "gauge",
+            "Time of the last reply in seconds since the Unix epoch.",
+            |t| {
+                t.last_success
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map(|since_epoch| since_epoch.as_secs_f64().to_string())
+            },
+        );
+
+        let name = "ping_fox_probe_rtt_seconds";
+        let _ = writeln!(out, "# HELP {name} Round-trip time of the replies.");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for (target, t) in &self.targets {
+            for (count, upper_bound) in t.rtt_buckets.iter().zip(RTT_BUCKETS) {
+                let _ = writeln!(out, "{name}_bucket{{target=\"{target}\",le=\"{upper_bound}\"}} {count}");
+            }
+            let _ = writeln!(out, "{name}_bucket{{target=\"{target}\",le=\"+Inf\"}} {}", t.received);
+            let _ = writeln!(out, "{name}_sum{{target=\"{target}\"}} {}", t.rtt_sum.as_secs_f64());
+            let _ = writeln!(out, "{name}_count{{target=\"{target}\"}} {}", t.received);
+        }
+        out
+    }
+
+    fn render_family<F>(&self, out: &mut String, name: &str, metric_type: &str, help: &str, value: F)
+    where
+        F: Fn(&TargetMetrics) -> Option<String>,
+    {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {metric_type}");
+        for (target, target_metrics) in &self.targets {
+            if let Some(value) = value(target_metrics) {
+                let _ = writeln!(out, "{name}{{target=\"{target}\"}} {value}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProbeKind;
+    use std::net::Ipv4Addr;
+
+    fn receive_data(ip_addr: IpAddr, ping_duration: Duration) -> PingReceiveData {
+        PingReceiveData {
+            package_size: 64,
+            ip_addr,
+            ttl: 63,
+            sequence_number: 1,
+            ping_duration,
+            probe_kind: ProbeKind::Icmp,
+        }
+    }
+
+    #[test]
+    fn render_without_targets() {
+        let rendered = Metrics::default().render();
+
+        assert!(rendered.contains("# TYPE ping_fox_probe_sent_total counter\n"));
+        assert!(rendered.contains("# TYPE ping_fox_probe_rtt_seconds histogram\n"));
+        assert!(!rendered.contains("target="));
+    }
+
+    #[test]
+    fn render_with_replies_and_losses() {
+        let target = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let mut metrics = Metrics::default();
+        for _ in 0..4 {
+            metrics.on_sent(target);
+        }
+        let receive_time = UNIX_EPOCH + Duration::from_secs(1_000);
+        metrics.on_received(&receive_data(target, Duration::from_millis(3)), receive_time);
+        metrics.on_received(&receive_data(target, Duration::from_millis(200)), receive_time);
+
+        let rendered = metrics.render();
+
+        assert!(rendered.contains("ping_fox_probe_sent_total{target=\"192.0.2.1\"} 4\n"));
+        assert!(rendered.contains("ping_fox_probe_received_total{target=\"192.0.2.1\"} 2\n"));
+        assert!(rendered.contains("ping_fox_probe_loss_ratio{target=\"192.0.2.1\"} 0.5\n"));
+        assert!(rendered.contains("ping_fox_probe_ttl{target=\"192.0.2.1\"} 63\n"));
+        assert!(rendered.contains("ping_fox_probe_last_success_timestamp_seconds{target=\"192.0.2.1\"} 1000\n"));
+        assert!(rendered.contains("ping_fox_probe_rtt_seconds_bucket{target=\"192.0.2.1\",le=\"0.0025\"} 0\n"));
+        assert!(rendered.contains("ping_fox_probe_rtt_seconds_bucket{target=\"192.0.2.1\",le=\"0.005\"} 1\n"));
+        assert!(rendered.contains("ping_fox_probe_rtt_seconds_bucket{target=\"192.0.2.1\",le=\"0.25\"} 2\n"));
+        assert!(rendered.contains("ping_fox_probe_rtt_seconds_bucket{target=\"192.0.2.1\",le=\"+Inf\"} 2\n"));
+        assert!(rendered.contains("ping_fox_probe_rtt_seconds_sum{target=\"192.0.2.1\"} 0.203\n"));
+        assert!(rendered.contains("ping_fox_probe_rtt_seconds_count{target=\"192.0.2.1\"} 2\n"));
+    }
+}
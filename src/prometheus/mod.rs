@@ -0,0 +1,237 @@
+//! A Prometheus exporter in the style of `blackbox_exporter`.
+//!
+//! The [`Exporter`] pings a fixed set of targets in regular intervals and serves per-target
+//! metrics over HTTP at `/metrics`:
+//!
+//! - `ping_fox_probe_sent_total` and `ping_fox_probe_received_total`,
+//! - `ping_fox_probe_loss_ratio`,
+//! - `ping_fox_probe_ttl` of the last reply,
+//! - `ping_fox_probe_last_success_timestamp_seconds`,
+//! - the histogram `ping_fox_probe_rtt_seconds`.
+//!
+//! ```no_run
+//! use ping_fox::prometheus::{Exporter, ExporterConfig};
+//! use ping_fox::{PingFoxConfig, ProbeKind, SocketType};
+//! use std::net::Ipv4Addr;
+//! use std::time::Duration;
+//!
+//! let exporter = Exporter::start(ExporterConfig {
+//!     ping_fox_config: PingFoxConfig {
+//!         socket_type: SocketType::DGRAM,
+//!         timeout: Duration::from_secs(1),
+//!         channel_size: 16,
+//!         probe_kind: ProbeKind::Icmp,
+//!         ..PingFoxConfig::default()
+//!     },
+//!     targets: vec![Ipv4Addr::new(192, 0, 2, 1)],
+//!     interval: Duration::from_secs(5),
+//!     listen_addr: "0.0.0.0:9427".parse().unwrap(),
+//! })
+//! .unwrap();
+//! println!("serving metrics at http://{}/metrics", exporter.local_addr());
+//! ```
+use crate::details::sync::lock;
+use crate::details::PingResult;
+use crate::{PingEvent, PingFoxConfig, PingReceive, PingSession, ShutdownHandle};
+use metrics::Metrics;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+mod http_server;
+mod metrics;
+
+/// Configuration of an [`Exporter`].
+pub struct ExporterConfig {
+    /// Configuration of the [`crate::PingSession`] used for probing. Its `channel_size` must be at
+    /// least the number of targets, so that a whole round of probes can be in flight.
+    pub ping_fox_config: PingFoxConfig,
+    /// The targets to probe.
+    pub targets: Vec<Ipv4Addr>,
+    /// Time between two rounds of probes.
+    pub interval: Duration,
+    /// Address of the HTTP server. Use port 0 to let the operating system choose a port.
+    pub listen_addr: SocketAddr,
+}
+
+/// A running Prometheus exporter.
+///
+/// Dropping the exporter stops probing and the HTTP server.
+pub struct Exporter {
+    local_addr: SocketAddr,
+    shutdown_handle: ShutdownHandle,
+    http_should_stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Exporter {
+    /// Creates the sockets and starts probing and serving metrics on background threads.
+    ///
+    /// Fails if the `channel_size` of the configuration is smaller than the number of targets.
+    pub fn start(config: ExporterConfig) -> PingResult<Exporter> {
+        if config.ping_fox_config.channel_size < config.targets.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "channel_size {} is smaller than the number of targets {}",
+                    config.ping_fox_config.channel_size,
+                    config.targets.len()
+                ),
+            )
+            .into());
+        }
+        let listener = TcpListener::bind(config.listen_addr)?;
+        let local_addr = listener.local_addr()?;
+        let metrics = Arc::new(Mutex::new(Metrics::default()));
+
+        let session_metrics = metrics.clone();
+        let session = PingSession::new(&config.ping_fox_config, move |event: PingEvent| {
+            if let PingReceive::Data(data) = event.outcome {
+                lock(&session_metrics).on_received(&data, SystemTime::now());
+            }
+        })?;
+        let shutdown_handle = session.shutdown_handle();
+        let http_should_stop = Arc::new(AtomicBool::new(false));
+
+        let scheduler_metrics = metrics.clone();
+        let scheduler_shutdown_handle = shutdown_handle.clone();
+        // Owns the session, which is dropped and joined when the scheduler stops on shutdown.
+        let scheduler = std::thread::spawn(move || loop {
+            for target in &config.targets {
+                match session.send_to(*target) {
+                    Ok(_) => lock(&scheduler_metrics).on_sent(IpAddr::V4(*target)),
+                    Err(e) => tracing::warn!("error sending probe to {}: {}", target, e),
+                }
+            }
+            if scheduler_shutdown_handle.wait_timeout(config.interval) {
+                return;
+            }
+        });
+
+        let server_should_stop = http_should_stop.clone();
+        let server = std::thread::spawn(move || http_server::serve(&listener, &metrics, &server_should_stop));
+
+        Ok(Exporter { local_addr, shutdown_handle, http_should_stop, threads: vec![scheduler, server] })
+    }
+
+    /// Returns the address the HTTP server is listening on.
+    #[must_use]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        self.shutdown_handle.shutdown();
+        self.http_should_stop.store(true, Ordering::SeqCst);
+        // Wake up the HTTP server blocked in `accept`.
+        let _ = TcpStream::connect_timeout(&wake_up_addr(self.local_addr), Duration::from_secs(1));
+        for thread in self.threads.drain(..) {
+            if thread.join().is_err() {
+                tracing::error!("exporter thread panicked");
+            }
+        }
+    }
+}
+
+// An unspecified listen address cannot be connected to; the loopback address can.
+fn wake_up_addr(local_addr: SocketAddr) -> SocketAddr {
+    match local_addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), local_addr.port()),
+        IpAddr::V6(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V6(std::net::Ipv6Addr::LOCALHOST), local_addr.port())
+        }
+        _ => local_addr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ProbeKind, SocketType};
+    use std::io::{Read, Write};
+
+    fn http_get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn exporter_serves_metrics_of_localhost() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let exporter = Exporter::start(ExporterConfig {
+            ping_fox_config: PingFoxConfig {
+                socket_type: SocketType::DGRAM,
+                timeout: Duration::from_secs(1),
+                channel_size: 4,
+                probe_kind: ProbeKind::Tcp { port },
+                ..PingFoxConfig::default()
+            },
+            targets: vec![Ipv4Addr::new(127, 0, 0, 1)],
+            interval: Duration::from_millis(20),
+            listen_addr: "127.0.0.1:0".parse().unwrap(),
+        })
+        .unwrap();
+
+        let mut response = String::new();
+        for _ in 0..100 {
+            response = http_get(exporter.local_addr(), "/metrics");
+            if response.contains("ping_fox_probe_rtt_seconds_count{target=\"127.0.0.1\"}") {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(response.contains("ping_fox_probe_sent_total{target=\"127.0.0.1\"}"));
+        assert!(response.contains("ping_fox_probe_rtt_seconds_count{target=\"127.0.0.1\"}"));
+    }
+
+    #[test]
+    fn exporter_returns_not_found_for_other_paths() {
+        let exporter = Exporter::start(ExporterConfig {
+            ping_fox_config: PingFoxConfig {
+                socket_type: SocketType::DGRAM,
+                timeout: Duration::from_millis(10),
+                channel_size: 1,
+                probe_kind: ProbeKind::Tcp { port: 1 },
+                ..PingFoxConfig::default()
+            },
+            targets: vec![],
+            interval: Duration::from_secs(1),
+            listen_addr: "127.0.0.1:0".parse().unwrap(),
+        })
+        .unwrap();
+
+        let response = http_get(exporter.local_addr(), "/");
+
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn channel_size_below_number_of_targets_is_rejected() {
+        let result = Exporter::start(ExporterConfig {
+            ping_fox_config: PingFoxConfig {
+                socket_type: SocketType::DGRAM,
+                timeout: Duration::from_millis(10),
+                channel_size: 1,
+                probe_kind: ProbeKind::Tcp { port: 1 },
+                ..PingFoxConfig::default()
+            },
+            targets: vec![Ipv4Addr::new(127, 0, 0, 1), Ipv4Addr::new(127, 0, 0, 2)],
+            interval: Duration::from_secs(1),
+            listen_addr: "127.0.0.1:0".parse().unwrap(),
+        });
+
+        assert!(result.is_err());
+    }
+}
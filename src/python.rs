@@ -0,0 +1,425 @@
+// Python bindings, enabled by the `python` feature and built with maturin, see pyproject.toml.
+//
+// The doc comments of the classes and functions here are their Python docstrings.
+use crate::details::GenericError;
+use crate::metrics::{MetricsSnapshot, RttMetrics};
+use crate::{
+    PingFoxConfig, PingReceive, PingReceiveData, PingSentToken, PingSummary, ProbeKind, SocketType, WouldExceedInFlight,
+};
+use pyo3::exceptions::{PyOSError, PyPermissionError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+pyo3::create_exception!(
+    ping_fox,
+    WouldExceedInFlightError,
+    PyRuntimeError,
+    "Raised instead of sending while the maximum number of pings is in flight."
+);
+
+fn to_py_err(error: &GenericError) -> PyErr {
+    if error.is::<WouldExceedInFlight>() {
+        WouldExceedInFlightError::new_err(error.to_string())
+    } else if let Some(io_error) = error.downcast_ref::<std::io::Error>() {
+        match io_error.kind() {
+            std::io::ErrorKind::PermissionDenied => PyPermissionError::new_err(error.to_string()),
+            _ => PyOSError::new_err(error.to_string()),
+        }
+    } else {
+        PyRuntimeError::new_err(error.to_string())
+    }
+}
+
+fn duration_from_secs(name: &str, secs: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(secs).map_err(|_| PyValueError::new_err(format!("{name} must be a non-negative number")))
+}
+
+fn parse_ipv4(address: &str) -> PyResult<Ipv4Addr> {
+    address
+        .parse()
+        .map_err(|_| PyValueError::new_err(format!("not an IPv4 address: {address}")))
+}
+
+/// A received echo reply. `rtt` is the round-trip time in seconds.
+#[pyclass(module = "ping_fox", frozen, get_all, eq)]
+#[derive(Clone, PartialEq)]
+struct PingData {
+    ip_addr: String,
+    sequence_number: u16,
+    ttl: u8,
+    package_size: usize,
+    rtt: f64,
+}
+
+#[pymethods]
+impl PingData {
+    fn __repr__(&self) -> String {
+        format!(
+            "PingData(ip_addr='{}', sequence_number={}, ttl={}, package_size={}, rtt={})",
+            self.ip_addr, self.sequence_number, self.ttl, self.package_size, self.rtt
+        )
+    }
+}
+
+impl PingData {
+    fn from_data(data: &PingReceiveData) -> Self {
+        PingData {
+            ip_addr: data.ip_addr.to_string(),
+            sequence_number: data.sequence_number,
+            ttl: data.ttl,
+            package_size: data.package_size,
+            rtt: data.ping_duration.as_secs_f64(),
+        }
+    }
+}
+
+/// No echo reply was received within the timeout.
+#[pyclass(module = "ping_fox", frozen, eq)]
+#[derive(PartialEq)]
+struct PingTimeout;
+
+#[pymethods]
+impl PingTimeout {
+    #[new]
+    fn new() -> Self {
+        PingTimeout
+    }
+
+    #[allow(clippy::unused_self)]
+    fn __repr__(&self) -> &'static str {
+        "PingTimeout()"
+    }
+}
+
+/// The sender and the receiver have been shut down.
+#[pyclass(module = "ping_fox", frozen, eq)]
+#[derive(PartialEq)]
+struct PingClosed;
+
+#[pymethods]
+impl PingClosed {
+    #[new]
+    fn new() -> Self {
+        PingClosed
+    }
+
+    #[allow(clippy::unused_self)]
+    fn __repr__(&self) -> &'static str {
+        "PingClosed()"
+    }
+}
+
+fn outcome_to_py(py: Python<'_>, outcome: &PingReceive) -> PyResult<PyObject> {
+    Ok(match outcome {
+        PingReceive::Data(data) => Py::new(py, PingData::from_data(data))?.into_any(),
+        PingReceive::Timeout => Py::new(py, PingTimeout)?.into_any(),
+        PingReceive::Closed => Py::new(py, PingClosed)?.into_any(),
+    })
+}
+
+/// Sends ICMP echo requests. It can be shared between threads.
+#[pyclass(module = "ping_fox", frozen)]
+struct PingSender(crate::PingSender);
+
+#[pymethods]
+impl PingSender {
+    /// Sends an echo request to the IPv4 address `address`, e.g. "127.0.0.1".
+    fn send_to(&self, address: &str) -> PyResult<()> {
+        self.0.send_to(parse_ipv4(address)?).map_err(|error| to_py_err(&error))?;
+        Ok(())
+    }
+
+    /// Shuts down this sender and its receiver. A blocked `receive` returns `PingClosed`.
+    fn shutdown(&self) {
+        self.0.shutdown_handle().shutdown();
+    }
+}
+
+/// Receives echo replies.
+#[pyclass(module = "ping_fox")]
+struct PingReceiver(crate::PingReceiver);
+
+#[pymethods]
+impl PingReceiver {
+    /// Waits for the next echo reply and returns a `PingData`, or `PingTimeout` after the
+    /// timeout. Returns `PingClosed` after a shutdown. Other threads run while it waits.
+    fn receive(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let outcome = py
+            .allow_threads(|| self.0.receive(PingSentToken {}))
+            .map_err(|error| to_py_err(&error))?;
+        outcome_to_py(py, &outcome)
+    }
+}
+
+/// Creates a sender and a receiver. `socket_type` is "dgram", which needs no privileges, or
+/// "raw". `timeout` is in seconds.
+#[pyfunction]
+#[pyo3(signature = (*, socket_type = "dgram", timeout = 1.0, max_in_flight = 1, ttl = None, payload_size = 56))]
+fn create(
+    socket_type: &str,
+    timeout: f64,
+    max_in_flight: usize,
+    ttl: Option<u8>,
+    payload_size: usize,
+) -> PyResult<(PingSender, PingReceiver)> {
+    let socket_type = match socket_type {
+        "dgram" => SocketType::DGRAM,
+        "raw" => SocketType::RAW,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "socket_type must be 'dgram' or 'raw': {socket_type}"
+            )))
+        }
+    };
+    let config = PingFoxConfig {
+        socket_type,
+        timeout: duration_from_secs("timeout", timeout)?,
+        channel_size: max_in_flight,
+        ttl,
+        payload_size,
+        ..PingFoxConfig::default()
+    };
+    let (ping_sender, ping_receiver) = crate::create(&config).map_err(|error| to_py_err(&error))?;
+    Ok((PingSender(ping_sender), PingReceiver(ping_receiver)))
+}
+
+/// Statistics of pings to a single target, returned by `ping`. Times are in seconds, and are
+/// None without replies.
+#[pyclass(module = "ping_fox", name = "PingSummary", frozen, get_all, eq)]
+#[derive(PartialEq)]
+struct PySummary {
+    target: String,
+    transmitted: u64,
+    received: u64,
+    loss: f64,
+    min: Option<f64>,
+    avg: Option<f64>,
+    max: Option<f64>,
+    mdev: Option<f64>,
+}
+
+#[pymethods]
+impl PySummary {
+    fn __repr__(&self) -> String {
+        format!(
+            "PingSummary(target='{}', transmitted={}, received={}, loss={}, min={:?}, avg={:?}, max={:?}, mdev={:?})",
+            self.target, self.transmitted, self.received, self.loss, self.min, self.avg, self.max, self.mdev
+        )
+    }
+}
+
+impl From<PingSummary> for PySummary {
+    fn from(summary: PingSummary) -> Self {
+        PySummary {
+            target: summary.target.to_string(),
+            transmitted: summary.transmitted,
+            received: summary.received,
+            loss: summary.loss(),
+            min: summary.min.map(|min| min.as_secs_f64()),
+            avg: summary.avg.map(|avg| avg.as_secs_f64()),
+            max: summary.max.map(|max| max.as_secs_f64()),
+            mdev: summary.mdev.map(|mdev| mdev.as_secs_f64()),
+        }
+    }
+}
+
+/// Pings the IPv4 address `target` `count` times, every `interval` seconds, and returns a
+/// `PingSummary`, like the `ping` command. Other threads run while it waits.
+#[pyfunction]
+#[pyo3(signature = (target, *, count = 4, interval = 1.0, timeout = 1.0))]
+fn ping(py: Python<'_>, target: &str, count: u64, interval: f64, timeout: f64) -> PyResult<PySummary> {
+    let pings = crate::ping(parse_ipv4(target)?)
+        .count(count)
+        .interval(duration_from_secs("interval", interval)?)
+        .timeout(duration_from_secs("timeout", timeout)?);
+    let summary = py.allow_threads(|| pings.summary()).map_err(|error| to_py_err(&error))?;
+    Ok(summary.into())
+}
+
+/// Statistics over a window of time, returned by `RttMetrics.window`. Times are in seconds, and
+/// are None without replies.
+#[pyclass(module = "ping_fox", name = "MetricsSnapshot", frozen)]
+struct PyMetricsSnapshot(MetricsSnapshot);
+
+#[pymethods]
+impl PyMetricsSnapshot {
+    /// Number of pings with an outcome in the window.
+    #[getter]
+    fn sent(&self) -> u64 {
+        self.0.sent
+    }
+
+    /// Number of replies in the window.
+    #[getter]
+    fn received(&self) -> u64 {
+        self.0.received
+    }
+
+    /// Fraction of pings without a reply.
+    #[getter]
+    fn loss(&self) -> f64 {
+        self.0.loss()
+    }
+
+    /// Mean round-trip time.
+    #[getter]
+    fn mean(&self) -> Option<f64> {
+        self.0.histogram.mean().map(|mean| mean.as_secs_f64())
+    }
+
+    /// RFC 3550 jitter.
+    #[getter]
+    fn jitter(&self) -> f64 {
+        self.0.jitter.as_secs_f64()
+    }
+
+    /// R-factor of the E-model.
+    #[getter]
+    fn r_factor(&self) -> f64 {
+        self.0.r_factor()
+    }
+
+    /// Mean opinion score.
+    #[getter]
+    fn mos(&self) -> f64 {
+        self.0.mos()
+    }
+
+    /// Returns the round-trip time at `quantile`, e.g. 0.99 for p99.
+    fn quantile(&self, quantile: f64) -> Option<f64> {
+        self.0.histogram.value_at_quantile(quantile).map(|value| value.as_secs_f64())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "MetricsSnapshot(sent={}, received={}, loss={}, mean={:?}, jitter={})",
+            self.sent(),
+            self.received(),
+            self.loss(),
+            self.mean(),
+            self.jitter()
+        )
+    }
+}
+
+/// Round-trip time statistics over sliding windows. Outcomes are kept in slots of `slot_length`
+/// seconds for `retention` seconds.
+#[pyclass(module = "ping_fox", name = "RttMetrics")]
+struct PyRttMetrics(RttMetrics);
+
+#[pymethods]
+impl PyRttMetrics {
+    #[new]
+    #[pyo3(signature = (slot_length = 10.0, retention = 900.0))]
+    fn new(slot_length: f64, retention: f64) -> PyResult<Self> {
+        Ok(PyRttMetrics(RttMetrics::new(
+            duration_from_secs("slot_length", slot_length)?,
+            duration_from_secs("retention", retention)?,
+        )))
+    }
+
+    /// Records an outcome returned by `PingReceiver.receive`.
+    fn record(&mut self, outcome: &Bound<'_, PyAny>) -> PyResult<()> {
+        let outcome = if let Ok(data) = outcome.downcast::<PingData>() {
+            let data = data.get();
+            PingReceive::Data(PingReceiveData {
+                package_size: data.package_size,
+                ip_addr: data.ip_addr.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+                ttl: data.ttl,
+                sequence_number: data.sequence_number,
+                ping_duration: duration_from_secs("rtt", data.rtt)?,
+                probe_kind: ProbeKind::Icmp,
+            })
+        } else if outcome.is_instance_of::<PingTimeout>() {
+            PingReceive::Timeout
+        } else if outcome.is_instance_of::<PingClosed>() {
+            PingReceive::Closed
+        } else {
+            return Err(PyValueError::new_err("expected PingData, PingTimeout or PingClosed"));
+        };
+        self.0.record(&outcome);
+        Ok(())
+    }
+
+    /// Returns a `MetricsSnapshot` of the last `length` seconds.
+    fn window(&self, length: f64) -> PyResult<PyMetricsSnapshot> {
+        Ok(PyMetricsSnapshot(self.0.window(duration_from_secs("length", length)?)))
+    }
+}
+
+// Public only for tests/tests_with_python.rs, which needs to create the module.
+/// ICMP echo requests without root or setuid.
+#[pymodule]
+pub fn ping_fox(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(create, m)?)?;
+    m.add_function(wrap_pyfunction!(ping, m)?)?;
+    m.add_class::<PingSender>()?;
+    m.add_class::<PingReceiver>()?;
+    m.add_class::<PingData>()?;
+    m.add_class::<PingTimeout>()?;
+    m.add_class::<PingClosed>()?;
+    m.add_class::<PySummary>()?;
+    m.add_class::<PyRttMetrics>()?;
+    m.add_class::<PyMetricsSnapshot>()?;
+    m.add("WouldExceedInFlightError", m.py().get_type::<WouldExceedInFlightError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+    use std::ffi::CString;
+
+    // Runs `code` with the module imported as `ping_fox`.
+    fn run_python(code: &str) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "ping_fox").unwrap();
+            ping_fox(&module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("ping_fox", module).unwrap();
+            let code = CString::new(code).unwrap();
+            if let Err(error) = py.run(&code, Some(&globals), None) {
+                error.print(py);
+                panic!("Python code failed");
+            }
+        });
+    }
+
+    #[test]
+    fn invalid_arguments_are_value_errors() {
+        run_python(
+            r"
+for call in [
+    lambda: ping_fox.create(socket_type='stream'),
+    lambda: ping_fox.create(timeout=-1.0),
+    lambda: ping_fox.ping('::1'),
+    lambda: ping_fox.RttMetrics(slot_length=-1.0),
+    lambda: ping_fox.RttMetrics().record(None),
+]:
+    try:
+        call()
+        raise AssertionError('expected ValueError')
+    except ValueError:
+        pass
+",
+        );
+    }
+
+    #[test]
+    fn metrics_from_python() {
+        run_python(
+            r"
+metrics = ping_fox.RttMetrics()
+metrics.record(ping_fox.PingTimeout())
+metrics.record(ping_fox.PingClosed())
+snapshot = metrics.window(60.0)
+assert snapshot.sent == 1 and snapshot.received == 0, snapshot
+assert snapshot.loss == 1.0
+assert snapshot.quantile(0.5) is None
+",
+        );
+    }
+}
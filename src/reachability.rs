@@ -0,0 +1,379 @@
+use crate::details::sync::send_to_channel;
+use crate::details::PingResult;
+use crate::{Clock, PingReceive, SystemClock};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::IpAddr;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+/// Configuration of a [`ReachabilityTracker`]. All thresholds must be at least 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReachabilityConfig {
+    /// Number of consecutive losses after which a target is down.
+    pub down_after: u32,
+    /// Number of consecutive replies after which a target is up.
+    pub up_after: u32,
+    /// Suppression of events for targets which go up and down too often. Disabled if `None`.
+    pub flap_damping: Option<FlapDamping>,
+}
+
+impl Default for ReachabilityConfig {
+    /// Returns a configuration in which a target is down after three losses and up after two
+    /// replies, without flap damping.
+    fn default() -> Self {
+        ReachabilityConfig { down_after: 3, up_after: 2, flap_damping: None }
+    }
+}
+
+/// Flap damping of a [`ReachabilityTracker`].
+///
+/// A target flaps once it changed between up and down `transitions` times within `window`. A
+/// [`ReachabilityEvent::Flapping`] is emitted then, and no further up or down events until the
+/// state of the target has been stable for `window`. The current state is emitted at that point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlapDamping {
+    /// Period in which state changes are counted.
+    pub window: Duration,
+    /// Number of state changes within `window` from which on a target flaps.
+    pub transitions: usize,
+}
+
+/// Reachability of a target as seen by a [`ReachabilityTracker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reachability {
+    /// Neither enough consecutive replies nor enough consecutive losses have been recorded yet.
+    Unknown,
+    /// The target is reachable.
+    Up,
+    /// The target is not reachable.
+    Down,
+}
+
+/// A change in the reachability of a target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReachabilityEvent {
+    /// `target` became reachable, or, after flapping, is stable and reachable.
+    HostUp {
+        /// The target.
+        target: IpAddr,
+        /// Time of the outcome which caused the event.
+        timestamp: Instant,
+    },
+    /// `target` became unreachable, or, after flapping, is stable and unreachable.
+    HostDown {
+        /// The target.
+        target: IpAddr,
+        /// Time of the outcome which caused the event.
+        timestamp: Instant,
+    },
+    /// `target` changes between up and down too often, see [`FlapDamping`].
+    Flapping {
+        /// The target.
+        target: IpAddr,
+        /// Time of the outcome which caused the event.
+        timestamp: Instant,
+    },
+}
+
+struct TargetState {
+    reachability: Reachability,
+    consecutive_replies: u32,
+    consecutive_losses: u32,
+    // Times of the recent changes between up and down, for flap damping.
+    transitions: VecDeque<Instant>,
+    is_flapping: bool,
+}
+
+impl TargetState {
+    fn new() -> Self {
+        TargetState {
+            reachability: Reachability::Unknown,
+            consecutive_replies: 0,
+            consecutive_losses: 0,
+            transitions: VecDeque::new(),
+            is_flapping: false,
+        }
+    }
+}
+
+/// Turns the outcomes of pings into up and down events per target.
+///
+/// A target goes down after [`ReachabilityConfig::down_after`] consecutive losses and up after
+/// [`ReachabilityConfig::up_after`] consecutive replies. Events are passed to a callback, or sent
+/// through a channel with [`ReachabilityTracker::with_channel`]. The first state a target reaches
+/// is reported as well.
+///
+/// ```
+/// use ping_fox::{PingReceive, ReachabilityConfig, ReachabilityEvent, ReachabilityTracker};
+/// use std::net::{IpAddr, Ipv4Addr};
+///
+/// let target = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+/// let (mut tracker, events) = ReachabilityTracker::with_channel(ReachabilityConfig::default()).unwrap();
+/// for _ in 0..3 {
+///     tracker.record(target, &PingReceive::Timeout);
+/// }
+///
+/// assert!(matches!(events.try_recv(), Ok(ReachabilityEvent::HostDown { .. })));
+/// ```
+pub struct ReachabilityTracker {
+    config: ReachabilityConfig,
+    clock: Arc<dyn Clock>,
+    targets: HashMap<IpAddr, TargetState>,
+    on_event: Box<dyn FnMut(ReachabilityEvent) + Send>,
+}
+
+impl ReachabilityTracker {
+    /// Creates a tracker which calls `on_event` for every event. Fails if a threshold of `config`
+    /// is 0.
+    pub fn new<F>(config: ReachabilityConfig, on_event: F) -> PingResult<Self>
+    where
+        F: FnMut(ReachabilityEvent) + Send + 'static,
+    {
+        if config.down_after == 0 || config.up_after == 0 {
+            return Err(invalid_config("down_after and up_after must be at least 1").into());
+        }
+        if config.flap_damping.is_some_and(|flap_damping| flap_damping.transitions == 0) {
+            return Err(invalid_config("flap damping transitions must be at least 1").into());
+        }
+        Ok(ReachabilityTracker {
+            config,
+            clock: Arc::new(SystemClock),
+            targets: HashMap::new(),
+            on_event: Box::new(on_event),
+        })
+    }
+
+    /// Creates a tracker which sends its events through the returned channel. Fails like
+    /// [`ReachabilityTracker::new`].
+    pub fn with_channel(config: ReachabilityConfig) -> PingResult<(Self, mpsc::Receiver<ReachabilityEvent>)> {
+        let (tx, rx) = mpsc::channel();
+        let tracker = Self::new(config, send_to_channel(tx))?;
+        Ok((tracker, rx))
+    }
+
+    /// Uses `clock` for the time stamps of events and for flap damping.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Records the outcome of a ping to `target`. A [`PingReceive::Data`] is a reply and a
+    /// [`PingReceive::Timeout`] a loss. A [`PingReceive::Closed`] is ignored.
+    pub fn record(&mut self, target: IpAddr, outcome: &PingReceive) {
+        let is_reply = match outcome {
+            PingReceive::Data(_) => true,
+            PingReceive::Timeout => false,
+            // Not an outcome of a ping.
+            PingReceive::Closed => return,
+        };
+        let timestamp = self.clock.now();
+        let config = self.config;
+        let state = self.targets.entry(target).or_insert_with(TargetState::new);
+        let next = if is_reply {
+            state.consecutive_replies = state.consecutive_replies.saturating_add(1);
+            state.consecutive_losses = 0;
+            (state.consecutive_replies >= config.up_after).then_some(Reachability::Up)
+        } else {
+            state.consecutive_losses = state.consecutive_losses.saturating_add(1);
+            state.consecutive_replies = 0;
+            (state.consecutive_losses >= config.down_after).then_some(Reachability::Down)
+        };
+
+        let mut events = Vec::new();
+        if let Some(next) = next.filter(|next| *next != state.reachability) {
+            let previous = state.reachability;
+            state.reachability = next;
+            if previous != Reachability::Unknown {
+                state.transitions.push_back(timestamp);
+            }
+            if !state.is_flapping {
+                events.extend(state_event(target, next, timestamp));
+            }
+        }
+        if let Some(flap_damping) = config.flap_damping {
+            while state
+                .transitions
+                .front()
+                .is_some_and(|transition| timestamp.saturating_duration_since(*transition) >= flap_damping.window)
+            {
+                state.transitions.pop_front();
+            }
+            if !state.is_flapping && state.transitions.len() >= flap_damping.transitions {
+                state.is_flapping = true;
+                events.push(ReachabilityEvent::Flapping { target, timestamp });
+            } else if state.is_flapping && state.transitions.is_empty() {
+                state.is_flapping = false;
+                // Nothing to report for a target which flapped before it was ever up or down.
+                events.extend(state_event(target, state.reachability, timestamp));
+            }
+        }
+
+        for event in events {
+            (self.on_event)(event);
+        }
+    }
+
+    /// Returns the reachability of `target`.
+    #[must_use]
+    pub fn reachability(&self, target: IpAddr) -> Reachability {
+        self.targets
+            .get(&target)
+            .map_or(Reachability::Unknown, |state| state.reachability)
+    }
+
+    /// Returns whether `target` is flapping, see [`FlapDamping`].
+    #[must_use]
+    pub fn is_flapping(&self, target: IpAddr) -> bool {
+        self.targets.get(&target).is_some_and(|state| state.is_flapping)
+    }
+}
+
+// Returns the event which reports `reachability`, or `None` for a target which has never been up
+// or down.
+fn state_event(target: IpAddr, reachability: Reachability, timestamp: Instant) -> Option<ReachabilityEvent> {
+    match reachability {
+        Reachability::Down => Some(ReachabilityEvent::HostDown { target, timestamp }),
+        Reachability::Up => Some(ReachabilityEvent::HostUp { target, timestamp }),
+        Reachability::Unknown => None,
+    }
+}
+
+fn invalid_config(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid reachability configuration: {message}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ManualClock, PingReceiveData, ProbeKind};
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const TARGET: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    fn reply() -> PingReceive {
+        PingReceive::Data(PingReceiveData {
+            package_size: 64,
+            ip_addr: TARGET,
+            ttl: 64,
+            sequence_number: 1,
+            ping_duration: Duration::from_millis(1),
+            probe_kind: ProbeKind::Icmp,
+        })
+    }
+
+    fn tracker(config: ReachabilityConfig) -> (ReachabilityTracker, mpsc::Receiver<ReachabilityEvent>, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new());
+        let (tracker, events) = ReachabilityTracker::with_channel(config).unwrap();
+        (tracker.with_clock(clock.clone()), events, clock)
+    }
+
+    fn record_n(tracker: &mut ReachabilityTracker, outcome: &PingReceive, n: usize) {
+        for _ in 0..n {
+            tracker.record(TARGET, outcome);
+        }
+    }
+
+    #[test]
+    fn target_goes_down_after_consecutive_losses() {
+        let (mut tracker, events, clock) = tracker(ReachabilityConfig::default());
+
+        record_n(&mut tracker, &PingReceive::Timeout, 2);
+        tracker.record(TARGET, &reply());
+        record_n(&mut tracker, &PingReceive::Timeout, 2);
+        assert_eq!(Reachability::Unknown, tracker.reachability(TARGET));
+        tracker.record(TARGET, &PingReceive::Timeout);
+
+        assert_eq!(Reachability::Down, tracker.reachability(TARGET));
+        assert_eq!(
+            Ok(ReachabilityEvent::HostDown { target: TARGET, timestamp: clock.now() }),
+            events.try_recv()
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn target_comes_back_after_consecutive_replies() {
+        let (mut tracker, events, _) = tracker(ReachabilityConfig::default());
+
+        record_n(&mut tracker, &PingReceive::Timeout, 3);
+        tracker.record(TARGET, &reply());
+        record_n(&mut tracker, &PingReceive::Timeout, 1);
+        record_n(&mut tracker, &reply(), 2);
+        record_n(&mut tracker, &reply(), 5);
+
+        assert!(matches!(events.try_recv(), Ok(ReachabilityEvent::HostDown { .. })));
+        assert!(matches!(events.try_recv(), Ok(ReachabilityEvent::HostUp { .. })));
+        assert!(events.try_recv().is_err());
+        assert_eq!(Reachability::Up, tracker.reachability(TARGET));
+    }
+
+    #[test]
+    fn flapping_target_is_damped_until_stable() {
+        let config = ReachabilityConfig {
+            down_after: 1,
+            up_after: 1,
+            flap_damping: Some(FlapDamping { window: Duration::from_secs(30), transitions: 3 }),
+        };
+        let (mut tracker, events, clock) = tracker(config);
+
+        for _ in 0..3 {
+            tracker.record(TARGET, &reply());
+            tracker.record(TARGET, &PingReceive::Timeout);
+            clock.advance(Duration::from_secs(1));
+        }
+        assert!(tracker.is_flapping(TARGET));
+        clock.advance(Duration::from_secs(30));
+        tracker.record(TARGET, &PingReceive::Timeout);
+
+        let events: Vec<ReachabilityEvent> = events.try_iter().collect();
+        assert!(matches!(
+            events[..],
+            [
+                ReachabilityEvent::HostUp { .. },
+                ReachabilityEvent::HostDown { .. },
+                ReachabilityEvent::HostUp { .. },
+                ReachabilityEvent::HostDown { .. },
+                ReachabilityEvent::Flapping { .. },
+                ReachabilityEvent::HostDown { .. },
+            ]
+        ));
+        assert!(!tracker.is_flapping(TARGET));
+    }
+
+    #[test]
+    fn targets_are_tracked_independently() {
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        let n_events = Arc::new(AtomicUsize::new(0));
+        let n_events_clone = n_events.clone();
+        let mut tracker = ReachabilityTracker::new(ReachabilityConfig::default(), move |_| {
+            n_events_clone.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+
+        record_n(&mut tracker, &PingReceive::Timeout, 3);
+        tracker.record(other, &reply());
+
+        assert_eq!(1, n_events.load(Ordering::SeqCst));
+        assert_eq!(Reachability::Down, tracker.reachability(TARGET));
+        assert_eq!(Reachability::Unknown, tracker.reachability(other));
+    }
+
+    #[test]
+    fn zero_thresholds_are_rejected() {
+        let flap_damping = FlapDamping { window: Duration::from_secs(30), transitions: 0 };
+
+        for config in [
+            ReachabilityConfig { down_after: 0, ..ReachabilityConfig::default() },
+            ReachabilityConfig { up_after: 0, ..ReachabilityConfig::default() },
+            ReachabilityConfig { flap_damping: Some(flap_damping), ..ReachabilityConfig::default() },
+        ] {
+            assert!(ReachabilityTracker::with_channel(config).is_err());
+        }
+    }
+}
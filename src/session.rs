@@ -0,0 +1,332 @@
+use crate::details::sync::{lock, send_to_channel, POLL_INTERVAL};
+use crate::details::PingResult;
+use crate::{Clock, PingFoxConfig, PingReceive, PingReceiver, PingSender, PingSentToken, ShutdownHandle};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The outcome of one ping sent by a [`PingSession`].
+#[derive(Debug)]
+pub struct PingEvent {
+    /// The address the ping was sent to.
+    pub target: IpAddr,
+    /// The sequence number of the ping, as returned by [`PingSession::send_to`].
+    pub sequence_number: u16,
+    /// The reply in [`PingReceive::Data`], or [`PingReceive::Timeout`] if there was no reply
+    /// within the timeout.
+    pub outcome: PingReceive,
+}
+
+// The pings without a reply yet and the deadlines of their replies.
+type Pending = Mutex<HashMap<(IpAddr, u16), Instant>>;
+
+/// Sends pings and receives their replies on a background thread.
+///
+/// Every ping sent with [`PingSession::send_to`] results in exactly one [`PingEvent`]: the reply,
+/// or a timeout once [`PingFoxConfig::timeout`] has passed without one on the clock of the
+/// [`PingReceiver`]. Replies arriving after the timeout are dropped. Events are passed to a
+/// callback on the background thread, or sent through a channel.
+///
+/// Dropping the session shuts it down and waits for the background thread. Pings in flight at
+/// that point have no events.
+pub struct PingSession {
+    ping_sender: PingSender,
+    pending: Arc<Pending>,
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+    shutdown_handle: ShutdownHandle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PingSession {
+    /// Creates a session as configured by `config` which calls `on_event` for every event.
+    pub fn new<F>(config: &PingFoxConfig, on_event: F) -> PingResult<Self>
+    where
+        F: FnMut(PingEvent) + Send + 'static,
+    {
+        let (ping_sender, ping_receiver) = crate::create(config)?;
+        Self::from_parts(ping_sender, ping_receiver, config.timeout, on_event)
+    }
+
+    /// Creates a session as configured by `config` which sends its events through the returned
+    /// channel.
+    pub fn with_channel(config: &PingFoxConfig) -> PingResult<(Self, mpsc::Receiver<PingEvent>)> {
+        let (tx, rx) = mpsc::channel();
+        let session = Self::new(config, send_to_channel(tx))?;
+        Ok((session, rx))
+    }
+
+    /// Creates a session from a [`PingSender`] and its [`PingReceiver`], e.g. those of
+    /// [`crate::create_with_socket`], in which a ping times out after `timeout`.
+    ///
+    /// Pings sent through other clones of `ping_sender` have no events.
+    pub fn from_parts<F>(
+        ping_sender: PingSender,
+        ping_receiver: PingReceiver,
+        timeout: Duration,
+        on_event: F,
+    ) -> PingResult<Self>
+    where
+        F: FnMut(PingEvent) + Send + 'static,
+    {
+        let pending = Arc::new(Pending::default());
+        let shutdown_handle = ping_sender.shutdown_handle();
+        let clock = ping_receiver.clock();
+        let receive_loop = ReceiveLoop {
+            clock: clock.clone(),
+            ping_receiver,
+            pending: pending.clone(),
+            shutdown_handle: shutdown_handle.clone(),
+            on_event: Box::new(on_event),
+        };
+        let thread = std::thread::Builder::new()
+            .name("ping-fox-session".to_string())
+            .spawn(move || receive_loop.run())?;
+        Ok(PingSession { ping_sender, pending, timeout, clock, shutdown_handle, thread: Some(thread) })
+    }
+
+    /// Sends a ping to `ip` and returns its sequence number.
+    ///
+    /// Fails like [`PingSender::send_to`], without an event.
+    pub fn send_to(&self, ip: Ipv4Addr) -> PingResult<u16> {
+        // The lock is held while sending, so that the background thread cannot see the reply
+        // before the ping is pending.
+        let mut pending = lock(&self.pending);
+        let sequence_number = self.ping_sender.send(ip)?;
+        pending.insert((IpAddr::V4(ip), sequence_number), self.clock.now() + self.timeout);
+        Ok(sequence_number)
+    }
+
+    /// Returns the number of pings without an event yet.
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        lock(&self.pending).len()
+    }
+
+    /// Returns a [`ShutdownHandle`] for this session, e.g. to stop it from a signal handler
+    /// thread. After a shutdown, no further events are emitted and sending fails.
+    #[must_use]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown_handle.clone()
+    }
+}
+
+impl Drop for PingSession {
+    fn drop(&mut self) {
+        self.shutdown_handle.shutdown();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                tracing::error!("ping session thread panicked");
+            }
+        }
+    }
+}
+
+struct ReceiveLoop {
+    ping_receiver: PingReceiver,
+    clock: Arc<dyn Clock>,
+    pending: Arc<Pending>,
+    shutdown_handle: ShutdownHandle,
+    on_event: Box<dyn FnMut(PingEvent) + Send>,
+}
+
+impl ReceiveLoop {
+    fn run(mut self) {
+        loop {
+            let wait = self.emit_timeouts();
+            let start = self.clock.now();
+            match self.ping_receiver.receive_within(PingSentToken {}, wait) {
+                Ok(PingReceive::Data(data)) => {
+                    let is_pending = lock(&self.pending).remove(&(data.ip_addr, data.sequence_number)).is_some();
+                    if is_pending {
+                        (self.on_event)(PingEvent {
+                            target: data.ip_addr,
+                            sequence_number: data.sequence_number,
+                            outcome: PingReceive::Data(data),
+                        });
+                    }
+                }
+                Ok(PingReceive::Timeout) => {
+                    let remaining = wait.saturating_sub(self.clock.now().saturating_duration_since(start));
+                    if !remaining.is_zero() && self.shutdown_handle.wait_timeout(remaining.min(POLL_INTERVAL)) {
+                        return;
+                    }
+                }
+                Ok(PingReceive::Closed) => return,
+                Err(e) => {
+                    tracing::warn!("could not receive reply: {}", e);
+                    // A persistent error, e.g. of a closed custom socket, must not become a busy loop.
+                    if self.shutdown_handle.wait_timeout(POLL_INTERVAL) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    // Emits a timeout for every pending ping past its deadline and returns how long to wait for
+    // the next deadline.
+    fn emit_timeouts(&mut self) -> Duration {
+        let now = self.clock.now();
+        let mut timed_out = Vec::new();
+        let mut next_deadline = None::<Instant>;
+        lock(&self.pending).retain(|&key, &mut deadline| {
+            if deadline <= now {
+                timed_out.push(key);
+                false
+            } else {
+                next_deadline = Some(next_deadline.map_or(deadline, |next| next.min(deadline)));
+                true
+            }
+        });
+        timed_out.sort_unstable_by_key(|&(_, sequence_number)| sequence_number);
+        for (target, sequence_number) in timed_out {
+            (self.on_event)(PingEvent { target, sequence_number, outcome: PingReceive::Timeout });
+        }
+        next_deadline.map_or(POLL_INTERVAL * 10, |deadline| deadline.saturating_duration_since(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TSocket;
+    use pnet_packet::icmp::{checksum, IcmpPacket};
+    use std::collections::VecDeque;
+    use std::io;
+
+    // A socket which answers every echo request to `LOCALHOST` and drops all others.
+    #[derive(Default)]
+    struct EchoSocket {
+        replies: Mutex<VecDeque<(Vec<u8>, IpAddr)>>,
+    }
+
+    impl TSocket for EchoSocket {
+        fn send_to(&self, buf: &[u8], addr: IpAddr) -> io::Result<usize> {
+            if addr == IpAddr::V4(Ipv4Addr::LOCALHOST) {
+                let mut reply = buf.to_vec();
+                reply[0] = 0; // echo reply
+                reply[2..4].fill(0);
+                let reply_checksum = checksum(&IcmpPacket::new(&reply).unwrap());
+                reply[2..4].copy_from_slice(&reply_checksum.to_be_bytes());
+                self.replies.lock().unwrap().push_back((reply, addr));
+            }
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+            let Some((reply, addr)) = self.replies.lock().unwrap().pop_front() else {
+                return Err(io::ErrorKind::WouldBlock.into());
+            };
+            buf[..reply.len()].copy_from_slice(&reply);
+            Ok((reply.len(), addr, 64))
+        }
+    }
+
+    fn session(timeout: Duration) -> (PingSession, mpsc::Receiver<PingEvent>) {
+        session_with_clock(timeout, Arc::new(crate::SystemClock))
+    }
+
+    fn session_with_clock(timeout: Duration, clock: Arc<dyn Clock>) -> (PingSession, mpsc::Receiver<PingEvent>) {
+        let config = PingFoxConfig { channel_size: 4, timeout, ..PingFoxConfig::default() };
+        let (ping_sender, ping_receiver) = crate::create_with_socket(EchoSocket::default(), clock, &config);
+        let (tx, rx) = mpsc::channel();
+        let session = PingSession::from_parts(ping_sender, ping_receiver, timeout, move |event| {
+            tx.send(event).unwrap();
+        })
+        .unwrap();
+        (session, rx)
+    }
+
+    #[test]
+    fn every_ping_has_one_event() {
+        let unreachable = Ipv4Addr::new(192, 0, 2, 1);
+        let (session, rx) = session(Duration::from_millis(50));
+
+        assert_eq!(1, session.send_to(Ipv4Addr::LOCALHOST).unwrap());
+        assert_eq!(1, session.send_to(unreachable).unwrap());
+
+        let reply = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(IpAddr::V4(Ipv4Addr::LOCALHOST), reply.target);
+        assert_eq!(1, reply.sequence_number);
+        assert!(matches!(reply.outcome, PingReceive::Data(_)));
+        let timeout = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(IpAddr::V4(unreachable), timeout.target);
+        assert_eq!(1, timeout.sequence_number);
+        assert!(matches!(timeout.outcome, PingReceive::Timeout));
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        assert_eq!(0, session.pending());
+    }
+
+    #[test]
+    fn timeout_is_emitted_per_ping() {
+        let timeout = Duration::from_millis(100);
+        let (session, rx) = session(timeout);
+
+        let start = Instant::now();
+        session.send_to(Ipv4Addr::new(192, 0, 2, 1)).unwrap();
+
+        let event = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(event.outcome, PingReceive::Timeout));
+        assert!(start.elapsed() >= timeout);
+        assert!(start.elapsed() < timeout * 10);
+    }
+
+    #[test]
+    fn timeout_is_measured_on_clock_of_receiver() {
+        let clock = Arc::new(crate::ManualClock::new());
+        let timeout = Duration::from_secs(30);
+        let (session, rx) = session_with_clock(timeout, clock.clone());
+
+        session.send_to(Ipv4Addr::new(192, 0, 2, 1)).unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        clock.advance(timeout);
+
+        let event = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(event.outcome, PingReceive::Timeout));
+        assert_eq!(0, session.pending());
+    }
+
+    #[test]
+    fn drop_stops_background_thread() {
+        let (session, rx) = session(Duration::from_secs(30));
+        session.send_to(Ipv4Addr::new(192, 0, 2, 1)).unwrap();
+        let shutdown_handle = session.shutdown_handle();
+
+        drop(session);
+
+        assert!(shutdown_handle.is_shut_down());
+        // The callback, and with it the sending end of the channel, is gone.
+        assert!(matches!(rx.recv(), Err(mpsc::RecvError)));
+    }
+
+    // A socket whose receives always fail, counting them.
+    struct FailingSocket(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl TSocket for FailingSocket {
+        fn send_to(&self, buf: &[u8], _addr: IpAddr) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, _buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(io::Error::other("socket is gone"))
+        }
+    }
+
+    #[test]
+    fn receive_errors_do_not_cause_a_busy_loop() {
+        let n_receives = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let config = PingFoxConfig { timeout: Duration::from_secs(1), ..PingFoxConfig::default() };
+        let (ping_sender, ping_receiver) =
+            crate::create_with_socket(FailingSocket(n_receives.clone()), Arc::new(crate::SystemClock), &config);
+        let session = PingSession::from_parts(ping_sender, ping_receiver, config.timeout, |_| {}).unwrap();
+
+        std::thread::sleep(Duration::from_millis(100));
+        drop(session);
+
+        assert!(n_receives.load(std::sync::atomic::Ordering::SeqCst) < 100);
+    }
+}
@@ -0,0 +1,572 @@
+//! A simulated network for testing code built on ping-fox without a network or privileges.
+//!
+//! A [`SimulatedNetwork`] answers ICMP echo requests for the hosts it knows. Each
+//! [`SimulatedHost`] has its own latency distribution, loss, duplication, reordering and corruption
+//! rates, TTL, and may answer with an ICMP error instead of an echo reply. Requests to unknown
+//! hosts are dropped.
+//!
+//! Together with a [`ManualClock`](crate::ManualClock), ping durations are exact:
+//!
+//! ```
+//! use ping_fox::testing::{Latency, SimulatedHost, SimulatedNetwork};
+//! use ping_fox::{ManualClock, PingFoxConfig, PingReceive};
+//! use std::net::Ipv4Addr;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let host = Ipv4Addr::new(192, 0, 2, 1);
+//! let clock = Arc::new(ManualClock::new());
+//! let network = SimulatedNetwork::new().with_clock(clock.clone()).with_host(
+//!     host,
+//!     SimulatedHost { latency: Latency::Fixed(Duration::from_millis(20)), ttl: 57, ..SimulatedHost::default() },
+//! );
+//!
+//! let (ping_sender, mut ping_receiver) = ping_fox::testing::create(&network, &PingFoxConfig::default());
+//! let token = ping_sender.send_to(host).unwrap();
+//! clock.advance(Duration::from_millis(20));
+//!
+//! match ping_receiver.receive(token).unwrap() {
+//!     PingReceive::Data(data) => {
+//!         assert_eq!(Duration::from_millis(20), data.ping_duration);
+//!         assert_eq!(57, data.ttl);
+//!     }
+//!     PingReceive::Timeout | PingReceive::Closed => panic!("expected a reply"),
+//! }
+//! ```
+//!
+//! A [`PcapReplaySocket`] instead returns the ICMP messages of a pcap file, so that captured
+//! traffic can be fed through the receive pipeline of ping-fox with [`replay`].
+pub use pcap_replay::*;
+
+use crate::details::sync;
+use crate::{Clock, PingFoxConfig, PingReceiver, PingSender, SystemClock, TSocket};
+use pnet_packet::icmp::echo_reply::MutableEchoReplyPacket;
+use pnet_packet::icmp::echo_request::EchoRequestPacket;
+use pnet_packet::icmp::{IcmpCode, IcmpPacket, IcmpTypes};
+use pnet_packet::Packet;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+mod pcap_replay;
+
+// Waiting receivers look at the clock at least this often, so that they notice a `ManualClock`
+// being advanced.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+const IPV4_HEADER_SIZE: usize = 20;
+// An ICMP error quotes the IP header and the first eight bytes of the offending datagram.
+const ICMP_ERROR_QUOTE_SIZE: usize = 8;
+const DEFAULT_TTL: u8 = 64;
+
+/// Creates a [`PingSender`] and a [`PingReceiver`] which ping through `network`.
+///
+/// `timeout`, `channel_size` and the payload settings of `config` are used; the socket type,
+/// probe kind, TTL and interface are ignored. The timeout of pings, e.g. in a
+/// [`PingSession`](crate::PingSession), is measured on the clock of the network. A single receive
+/// still returns after `timeout` of real time without a reply, like on a socket with a receive
+/// timeout.
+#[must_use]
+pub fn create(network: &SimulatedNetwork, config: &PingFoxConfig) -> (PingSender, PingReceiver) {
+    let socket = SimulatedNetwork { timeout: config.timeout, ..network.clone() };
+    crate::create_with_socket(socket, network.clock.clone(), config)
+}
+
+/// Distribution of the round-trip time of a [`SimulatedHost`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Latency {
+    /// Always the same round-trip time.
+    Fixed(Duration),
+    /// Uniformly distributed between `min` and `max`.
+    Uniform {
+        /// Lower bound.
+        min: Duration,
+        /// Upper bound.
+        max: Duration,
+    },
+    /// Normally distributed, cut off at zero.
+    Normal {
+        /// Mean.
+        mean: Duration,
+        /// Standard deviation.
+        std_dev: Duration,
+    },
+}
+
+impl Latency {
+    fn sample(&self, rng: &mut StdRng) -> Duration {
+        match *self {
+            Latency::Fixed(latency) => latency,
+            Latency::Uniform { min, max } if max <= min => min,
+            Latency::Uniform { min, max } => Duration::from_secs_f64(rng.gen_range(min.as_secs_f64()..max.as_secs_f64())),
+            Latency::Normal { mean, std_dev } => {
+                // Box-Muller transform; `1 - u` keeps the logarithm finite.
+                let u1: f64 = 1.0 - rng.gen::<f64>();
+                let u2: f64 = rng.gen();
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                Duration::from_secs_f64((mean.as_secs_f64() + z * std_dev.as_secs_f64()).max(0.0))
+            }
+        }
+    }
+}
+
+/// An ICMP error a [`SimulatedHost`] answers with instead of an echo reply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SimulatedIcmpError {
+    /// ICMP type, e.g. 3 for destination unreachable or 11 for time exceeded.
+    pub icmp_type: u8,
+    /// ICMP code, e.g. 1 for host unreachable.
+    pub icmp_code: u8,
+    /// Address of the host, typically a router, which sends the error.
+    pub source: Ipv4Addr,
+}
+
+/// Behaviour of a host in a [`SimulatedNetwork`].
+///
+/// Rates are probabilities between 0 and 1, applied independently to each request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulatedHost {
+    /// Round-trip time of a request and its reply.
+    pub latency: Latency,
+    /// Probability that a request or its reply is lost.
+    pub loss_rate: f64,
+    /// Probability that a reply arrives twice.
+    pub duplicate_rate: f64,
+    /// Probability that a reply is held back by `reorder_delay`, so that later replies overtake it.
+    pub reorder_rate: f64,
+    /// Additional delay of a reordered reply.
+    pub reorder_delay: Duration,
+    /// Probability that one byte of a reply is flipped. A receive fails with a
+    /// [`ParseError`](crate::ParseError) if this breaks the checksum.
+    pub corruption_rate: f64,
+    /// Time to live (TTL) of replies when they arrive.
+    pub ttl: u8,
+    /// If set, the host answers every request with this ICMP error.
+    pub icmp_error: Option<SimulatedIcmpError>,
+}
+
+impl Default for SimulatedHost {
+    /// Returns a host which answers every request after one millisecond with a TTL of 64.
+    fn default() -> Self {
+        SimulatedHost {
+            latency: Latency::Fixed(Duration::from_millis(1)),
+            loss_rate: 0.0,
+            duplicate_rate: 0.0,
+            reorder_rate: 0.0,
+            reorder_delay: Duration::ZERO,
+            corruption_rate: 0.0,
+            ttl: DEFAULT_TTL,
+            icmp_error: None,
+        }
+    }
+}
+
+// A reply on its way back to the pinging socket.
+struct InFlightPacket {
+    arrival_time: Instant,
+    // Breaks ties between packets arriving at the same time.
+    order: u64,
+    source: Ipv4Addr,
+    ttl: u8,
+    bytes: Vec<u8>,
+}
+
+struct NetworkState {
+    hosts: HashMap<Ipv4Addr, SimulatedHost>,
+    rng: StdRng,
+    in_flight: Vec<InFlightPacket>,
+    n_queued: u64,
+    sent_counts: HashMap<Ipv4Addr, usize>,
+}
+
+/// A simulated network of hosts answering ICMP echo requests.
+///
+/// Clones share the same hosts and packets in flight. Randomness comes from a seeded generator, so
+/// a test run is reproducible as long as the sequence of requests is the same.
+#[derive(Clone)]
+pub struct SimulatedNetwork {
+    state: Arc<(Mutex<NetworkState>, Condvar)>,
+    clock: Arc<dyn Clock>,
+    timeout: Duration,
+}
+
+impl SimulatedNetwork {
+    /// Creates an empty network using the [`SystemClock`] and a fixed seed.
+    #[must_use]
+    pub fn new() -> Self {
+        let state = NetworkState {
+            hosts: HashMap::new(),
+            rng: StdRng::seed_from_u64(0),
+            in_flight: Vec::new(),
+            n_queued: 0,
+            sent_counts: HashMap::new(),
+        };
+        SimulatedNetwork {
+            state: Arc::new((Mutex::new(state), Condvar::new())),
+            clock: Arc::new(SystemClock),
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Uses `clock` for the arrival times of replies and the time stamps of pings.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Seeds the random number generator.
+    #[must_use]
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.lock_state().rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Adds a host, see [`SimulatedNetwork::set_host`].
+    #[must_use]
+    pub fn with_host(self, ip: Ipv4Addr, host: SimulatedHost) -> Self {
+        self.set_host(ip, host);
+        self
+    }
+
+    /// Adds the host `ip` or changes its behaviour. Replies already in flight are not affected.
+    pub fn set_host(&self, ip: Ipv4Addr, host: SimulatedHost) {
+        self.lock_state().hosts.insert(ip, host);
+    }
+
+    /// Removes the host `ip`, so that further requests to it are dropped.
+    pub fn remove_host(&self, ip: Ipv4Addr) {
+        self.lock_state().hosts.remove(&ip);
+    }
+
+    /// Returns the number of requests sent to `ip`, whether or not the host exists.
+    #[must_use]
+    pub fn sent_count(&self, ip: Ipv4Addr) -> usize {
+        self.lock_state().sent_counts.get(&ip).copied().unwrap_or_default()
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, NetworkState> {
+        sync::lock(&self.state.0)
+    }
+}
+
+impl Default for SimulatedNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn chance(rng: &mut StdRng, rate: f64) -> bool {
+    rate > 0.0 && rng.gen::<f64>() < rate
+}
+
+fn set_icmp_checksum(bytes: &mut [u8]) {
+    bytes[2..4].fill(0);
+    if let Some(packet) = IcmpPacket::new(bytes) {
+        let checksum = pnet_packet::icmp::checksum(&packet);
+        bytes[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+}
+
+fn echo_reply(request: &EchoRequestPacket<'_>) -> Vec<u8> {
+    let mut bytes = vec![0u8; MutableEchoReplyPacket::minimum_packet_size() + request.payload().len()];
+    if let Some(mut reply) = MutableEchoReplyPacket::new(&mut bytes) {
+        reply.set_icmp_type(IcmpTypes::EchoReply);
+        reply.set_icmp_code(IcmpCode::new(0));
+        reply.set_identifier(request.get_identifier());
+        reply.set_sequence_number(request.get_sequence_number());
+        reply.set_payload(request.payload());
+    }
+    set_icmp_checksum(&mut bytes);
+    bytes
+}
+
+// An ICMP error quoting a minimal IPv4 header and the start of `request`.
+fn icmp_error(error: SimulatedIcmpError, destination: Ipv4Addr, request: &[u8]) -> Vec<u8> {
+    let quote = &request[..request.len().min(ICMP_ERROR_QUOTE_SIZE)];
+    let mut bytes = vec![0u8; 8 + IPV4_HEADER_SIZE + quote.len()];
+    bytes[0] = error.icmp_type;
+    bytes[1] = error.icmp_code;
+    let ip_header = &mut bytes[8..8 + IPV4_HEADER_SIZE];
+    ip_header[0] = 0x45;
+    let total_length = u16::try_from(IPV4_HEADER_SIZE + request.len()).unwrap_or(u16::MAX);
+    ip_header[2..4].copy_from_slice(&total_length.to_be_bytes());
+    ip_header[8] = DEFAULT_TTL;
+    ip_header[9] = 1; // ICMP
+    ip_header[16..20].copy_from_slice(&destination.octets());
+    bytes[8 + IPV4_HEADER_SIZE..].copy_from_slice(quote);
+    set_icmp_checksum(&mut bytes);
+    bytes
+}
+
+impl TSocket for SimulatedNetwork {
+    fn send_to(&self, buf: &[u8], addr: IpAddr) -> io::Result<usize> {
+        let IpAddr::V4(ip) = addr else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "only IPv4 is simulated"));
+        };
+        let request = EchoRequestPacket::new(buf)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not an ICMP echo request"))?;
+        let now = self.clock.now();
+
+        let (lock, cvar) = &*self.state;
+        let mut state = sync::lock(lock);
+        *state.sent_counts.entry(ip).or_default() += 1;
+        let Some(host) = state.hosts.get(&ip).cloned() else {
+            return Ok(buf.len());
+        };
+        if chance(&mut state.rng, host.loss_rate) {
+            return Ok(buf.len());
+        }
+        let (source, reply) = match &host.icmp_error {
+            Some(error) => (error.source, icmp_error(*error, ip, buf)),
+            None => (ip, echo_reply(&request)),
+        };
+        let n_copies = if chance(&mut state.rng, host.duplicate_rate) { 2 } else { 1 };
+        for _ in 0..n_copies {
+            let mut latency = host.latency.sample(&mut state.rng);
+            if chance(&mut state.rng, host.reorder_rate) {
+                latency += host.reorder_delay;
+            }
+            let mut bytes = reply.clone();
+            if chance(&mut state.rng, host.corruption_rate) && !bytes.is_empty() {
+                let i = state.rng.gen_range(0..bytes.len());
+                bytes[i] ^= 0xFF;
+            }
+            let order = state.n_queued;
+            state.n_queued += 1;
+            state
+                .in_flight
+                .push(InFlightPacket { arrival_time: now + latency, order, source, ttl: host.ttl, bytes });
+        }
+        cvar.notify_all();
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+        self.receive(buf, self.timeout)
+    }
+
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+        self.receive(buf, Duration::ZERO)
+    }
+}
+
+impl SimulatedNetwork {
+    // Receives the next packet which has arrived, waiting up to `timeout` of real time for one.
+    fn receive(&self, buf: &mut [u8], timeout: Duration) -> io::Result<(usize, IpAddr, u8)> {
+        let deadline = Instant::now() + timeout;
+        let (lock, cvar) = &*self.state;
+        let mut state = sync::lock(lock);
+        loop {
+            let now = self.clock.now();
+            let next = state
+                .in_flight
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, packet)| (packet.arrival_time, packet.order))
+                .map(|(i, packet)| (i, packet.arrival_time));
+            if let Some((i, arrival_time)) = next {
+                if arrival_time <= now {
+                    let packet = state.in_flight.swap_remove(i);
+                    let n_bytes = packet.bytes.len().min(buf.len());
+                    buf[..n_bytes].copy_from_slice(&packet.bytes[..n_bytes]);
+                    return Ok((n_bytes, IpAddr::V4(packet.source), packet.ttl));
+                }
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "simulated receive timed out"));
+            }
+            state = cvar
+                .wait_timeout(state, remaining.min(POLL_INTERVAL))
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::details::icmp::v4::icmpv4::new_icmpv4_package;
+    use crate::details::icmp::v4::SequenceNumber;
+    use crate::{ManualClock, PingReceive};
+    use pnet_packet::icmp::echo_reply::EchoReplyPacket;
+
+    const HOST: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const OTHER_HOST: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 2);
+
+    fn fixed(millis: u64) -> Latency {
+        Latency::Fixed(Duration::from_millis(millis))
+    }
+
+    fn network_with_manual_clock(timeout: Duration) -> (SimulatedNetwork, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new());
+        let network = SimulatedNetwork { timeout, ..SimulatedNetwork::new().with_clock(clock.clone()) };
+        (network, clock)
+    }
+
+    fn send_request(network: &SimulatedNetwork, ip: Ipv4Addr, sequence_number: u16) {
+        let package = new_icmpv4_package(SequenceNumber::from(sequence_number), &[0xAB; 8]).unwrap();
+        network.send_to(package.packet(), IpAddr::V4(ip)).unwrap();
+    }
+
+    fn receive(network: &SimulatedNetwork) -> io::Result<(Vec<u8>, IpAddr, u8)> {
+        let mut buf = [0u8; 128];
+        let (n_bytes, ip_addr, ttl) = network.recv_from(&mut buf)?;
+        Ok((buf[..n_bytes].to_vec(), ip_addr, ttl))
+    }
+
+    fn sequence_number_of(reply: &[u8]) -> u16 {
+        EchoReplyPacket::new(reply).unwrap().get_sequence_number()
+    }
+
+    #[test]
+    fn reply_arrives_after_latency() {
+        let (network, clock) = network_with_manual_clock(Duration::from_millis(10));
+        network.set_host(HOST, SimulatedHost { latency: fixed(5), ttl: 50, ..SimulatedHost::default() });
+
+        send_request(&network, HOST, 7);
+        clock.advance(Duration::from_millis(4));
+        let early = receive(&network);
+        clock.advance(Duration::from_millis(1));
+        let (reply, ip_addr, ttl) = receive(&network).unwrap();
+
+        assert_eq!(io::ErrorKind::WouldBlock, early.unwrap_err().kind());
+        assert_eq!(IpAddr::V4(HOST), ip_addr);
+        assert_eq!(50, ttl);
+        assert_eq!(7, sequence_number_of(&reply));
+        let checksum = pnet_packet::icmp::checksum(&IcmpPacket::new(&reply).unwrap());
+        assert_eq!(checksum, IcmpPacket::new(&reply).unwrap().get_checksum());
+        assert_eq!(1, network.sent_count(HOST));
+    }
+
+    #[test]
+    fn requests_to_unknown_or_lossy_hosts_are_dropped() {
+        let (network, _) = network_with_manual_clock(Duration::from_millis(10));
+        network.set_host(HOST, SimulatedHost { loss_rate: 1.0, ..SimulatedHost::default() });
+
+        send_request(&network, HOST, 1);
+        send_request(&network, OTHER_HOST, 1);
+
+        assert_eq!(io::ErrorKind::WouldBlock, receive(&network).unwrap_err().kind());
+        assert_eq!(1, network.sent_count(HOST));
+        assert_eq!(1, network.sent_count(OTHER_HOST));
+    }
+
+    #[test]
+    fn duplicated_reply_arrives_twice() {
+        let (network, clock) = network_with_manual_clock(Duration::from_millis(10));
+        network.set_host(HOST, SimulatedHost { duplicate_rate: 1.0, ..SimulatedHost::default() });
+
+        send_request(&network, HOST, 3);
+        clock.advance(Duration::from_millis(1));
+
+        assert_eq!(3, sequence_number_of(&receive(&network).unwrap().0));
+        assert_eq!(3, sequence_number_of(&receive(&network).unwrap().0));
+        assert!(receive(&network).is_err());
+    }
+
+    #[test]
+    fn reordered_reply_is_overtaken() {
+        let (network, clock) = network_with_manual_clock(Duration::from_millis(10));
+        network.set_host(
+            HOST,
+            SimulatedHost { reorder_rate: 1.0, reorder_delay: Duration::from_millis(5), ..SimulatedHost::default() },
+        );
+        network.set_host(OTHER_HOST, SimulatedHost::default());
+
+        send_request(&network, HOST, 1);
+        send_request(&network, OTHER_HOST, 2);
+        clock.advance(Duration::from_millis(6));
+
+        assert_eq!(IpAddr::V4(OTHER_HOST), receive(&network).unwrap().1);
+        assert_eq!(IpAddr::V4(HOST), receive(&network).unwrap().1);
+    }
+
+    #[test]
+    fn corrupted_reply_has_wrong_checksum() {
+        let (network, clock) = network_with_manual_clock(Duration::from_millis(10));
+        network.set_host(HOST, SimulatedHost { corruption_rate: 1.0, ..SimulatedHost::default() });
+
+        send_request(&network, HOST, 1);
+        clock.advance(Duration::from_millis(1));
+        let (reply, _, _) = receive(&network).unwrap();
+
+        let packet = IcmpPacket::new(&reply).unwrap();
+        assert_ne!(pnet_packet::icmp::checksum(&packet), packet.get_checksum());
+    }
+
+    #[test]
+    fn icmp_error_comes_from_its_source() {
+        let router = Ipv4Addr::new(198, 51, 100, 1);
+        let (network, clock) = network_with_manual_clock(Duration::from_millis(10));
+        let icmp_error = SimulatedIcmpError { icmp_type: 3, icmp_code: 1, source: router };
+        network.set_host(
+            HOST,
+            SimulatedHost { icmp_error: Some(icmp_error), ..SimulatedHost::default() },
+        );
+
+        send_request(&network, HOST, 1);
+        clock.advance(Duration::from_millis(1));
+        let (reply, ip_addr, _) = receive(&network).unwrap();
+
+        assert_eq!(IpAddr::V4(router), ip_addr);
+        assert_eq!([3, 1], reply[..2]);
+        assert_eq!(HOST.octets(), reply[8 + 16..8 + 20]);
+        assert_eq!(8 + IPV4_HEADER_SIZE + ICMP_ERROR_QUOTE_SIZE, reply.len());
+    }
+
+    #[test]
+    fn latency_samples_stay_in_range() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let uniform = Latency::Uniform { min: Duration::from_millis(10), max: Duration::from_millis(20) };
+        let normal = Latency::Normal { mean: Duration::from_millis(1), std_dev: Duration::from_millis(5) };
+
+        for _ in 0..1000 {
+            let latency = uniform.sample(&mut rng);
+            assert!(Duration::from_millis(10) <= latency && latency < Duration::from_millis(20));
+            assert!(normal.sample(&mut rng) >= Duration::ZERO);
+        }
+        assert_eq!(Duration::from_millis(3), fixed(3).sample(&mut rng));
+    }
+
+    #[test]
+    fn try_receive_does_not_wait_for_timeout() {
+        let (network, _) = network_with_manual_clock(Duration::from_secs(30));
+        network.set_host(HOST, SimulatedHost { latency: fixed(5), ..SimulatedHost::default() });
+        let config = PingFoxConfig { timeout: Duration::from_secs(30), ..PingFoxConfig::default() };
+        let (ping_sender, mut ping_receiver) = create(&network, &config);
+        let start = Instant::now();
+
+        ping_sender.send_to(HOST).unwrap();
+
+        assert!(ping_receiver.try_receive().unwrap().is_none());
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn create_pings_through_network() {
+        let clock = Arc::new(ManualClock::new());
+        let network = SimulatedNetwork::new()
+            .with_clock(clock.clone())
+            .with_host(HOST, SimulatedHost { latency: fixed(12), ..SimulatedHost::default() });
+        let config = PingFoxConfig { timeout: Duration::from_millis(10), ..PingFoxConfig::default() };
+
+        let (ping_sender, mut ping_receiver) = create(&network, &config);
+        let token = ping_sender.send_to(HOST).unwrap();
+        clock.advance(Duration::from_millis(12));
+
+        match ping_receiver.receive(token).unwrap() {
+            PingReceive::Data(data) => {
+                assert_eq!(IpAddr::V4(HOST), data.ip_addr);
+                assert_eq!(Duration::from_millis(12), data.ping_duration);
+            }
+            PingReceive::Timeout | PingReceive::Closed => panic!("expected a reply"),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use crate::details::icmp::v4::parse::parse_ipv4_packet;
+use crate::details::pcap::{IP_PROTOCOL_ICMP, LINKTYPE_RAW, PCAP_MAGIC};
+use crate::details::sync::lock;
+use crate::{PingFoxConfig, PingReceiver, PingSender, SystemClock, TSocket};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// Magic number of pcap files with time stamps in nanoseconds instead of microseconds.
+const PCAP_MAGIC_NANOSECONDS: u32 = 0xa1b2_3c4d;
+const PCAP_HEADER_SIZE: usize = 24;
+const PCAP_RECORD_HEADER_SIZE: usize = 16;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const ICMP_ECHO_REQUEST: u8 = 8;
+
+/// Creates a [`PingSender`] and a [`PingReceiver`] which receive the ICMP messages of `socket`.
+///
+/// `timeout`, `channel_size` and the payload settings of `config` are used. Replies are matched
+/// to requests by address and sequence number as usual, so the requests sent have to be those of
+/// the capture.
+#[must_use]
+pub fn replay(socket: PcapReplaySocket, config: &PingFoxConfig) -> (PingSender, PingReceiver) {
+    let socket = PcapReplaySocket { timeout: config.timeout, ..socket };
+    crate::create_with_socket(socket, Arc::new(SystemClock), config)
+}
+
+/// How fast a [`PcapReplaySocket`] returns the messages of a capture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayTiming {
+    /// Messages arrive with the delays of the capture, measured from the first request sent or
+    /// receive call, whichever comes first.
+    Original,
+    /// Messages arrive as soon as they are received.
+    AsFastAsPossible,
+}
+
+struct ReplayPacket {
+    // Time since the first packet of the capture.
+    offset: Duration,
+    source: Ipv4Addr,
+    ttl: u8,
+    bytes: Vec<u8>,
+}
+
+struct ReplayState {
+    packets: VecDeque<ReplayPacket>,
+    start: Option<Instant>,
+}
+
+/// A [`TSocket`] which returns the ICMP messages of a pcap file, e.g. one written with
+/// [`PingFoxConfig::pcap_file`](crate::PingFoxConfig::pcap_file).
+///
+/// Odd messages seen in production, e.g. truncated ones, ICMP errors or duplicates, can be fed
+/// through the receive pipeline of ping-fox this way. Sent requests are discarded, and so are the
+/// ICMP echo requests of the capture. Messages which are not ICMP over IPv4 are skipped.
+///
+/// Classic pcap files with raw IP, IPv4, Ethernet and Linux cooked capture (v1 and v2) link
+/// layers are supported; pcapng files are not.
+pub struct PcapReplaySocket {
+    state: Mutex<ReplayState>,
+    timing: ReplayTiming,
+    timeout: Duration,
+}
+
+impl PcapReplaySocket {
+    /// Reads the capture in the pcap file at `path`.
+    pub fn open(path: impl AsRef<Path>, timing: ReplayTiming) -> io::Result<Self> {
+        Self::from_reader(File::open(path)?, timing)
+    }
+
+    /// Reads a capture in the pcap format from `reader`.
+    pub fn from_reader(mut reader: impl Read, timing: ReplayTiming) -> io::Result<Self> {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        let packets = parse_pcap(&content)?;
+        Ok(PcapReplaySocket {
+            state: Mutex::new(ReplayState { packets, start: None }),
+            timing,
+            timeout: Duration::from_secs(1),
+        })
+    }
+
+    /// Returns the number of messages which have not been received yet.
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.lock_state().packets.len()
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, ReplayState> {
+        lock(&self.state)
+    }
+}
+
+fn would_block() -> io::Error {
+    io::Error::new(io::ErrorKind::WouldBlock, "replay receive timed out")
+}
+
+impl TSocket for PcapReplaySocket {
+    fn send_to(&self, buf: &[u8], _addr: IpAddr) -> io::Result<usize> {
+        self.lock_state().start.get_or_insert_with(Instant::now);
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+        let mut state = self.lock_state();
+        let start = *state.start.get_or_insert_with(Instant::now);
+        let due = match (self.timing, state.packets.front()) {
+            (ReplayTiming::AsFastAsPossible, None) => return Err(would_block()),
+            (ReplayTiming::AsFastAsPossible, Some(_)) => None,
+            (ReplayTiming::Original, next) => {
+                let due = next.map(|packet| start + packet.offset);
+                if due.is_none_or(|due| due > Instant::now() + self.timeout) {
+                    drop(state);
+                    std::thread::sleep(self.timeout);
+                    return Err(would_block());
+                }
+                due
+            }
+        };
+        let Some(packet) = state.packets.pop_front() else {
+            return Err(would_block());
+        };
+        drop(state);
+        if let Some(due) = due {
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        let n_bytes = packet.bytes.len().min(buf.len());
+        buf[..n_bytes].copy_from_slice(&packet.bytes[..n_bytes]);
+        Ok((n_bytes, IpAddr::V4(packet.source), packet.ttl))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse_pcap(content: &[u8]) -> io::Result<VecDeque<ReplayPacket>> {
+    let header = content
+        .get(..PCAP_HEADER_SIZE)
+        .ok_or_else(|| invalid_data("pcap header truncated"))?;
+    let magic = [header[0], header[1], header[2], header[3]];
+    let (big_endian, nanoseconds) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+        (PCAP_MAGIC, _) => (false, false),
+        (PCAP_MAGIC_NANOSECONDS, _) => (false, true),
+        (_, PCAP_MAGIC) => (true, false),
+        (_, PCAP_MAGIC_NANOSECONDS) => (true, true),
+        _ => return Err(invalid_data("not a pcap file")),
+    };
+    let read_u32 = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+    // The upper bits of the link type field may carry the length of a frame check sequence.
+    let link_type = read_u32(&header[20..24]) & 0xffff;
+
+    let mut packets = VecDeque::new();
+    let mut first_timestamp = None;
+    let mut records = &content[PCAP_HEADER_SIZE..];
+    while !records.is_empty() {
+        let record_header = records
+            .get(..PCAP_RECORD_HEADER_SIZE)
+            .ok_or_else(|| invalid_data("pcap record header truncated"))?;
+        let fraction = u64::from(read_u32(&record_header[4..8]));
+        let timestamp = Duration::from_secs(u64::from(read_u32(&record_header[0..4])))
+            + if nanoseconds {
+                Duration::from_nanos(fraction)
+            } else {
+                Duration::from_micros(fraction)
+            };
+        let captured_size =
+            usize::try_from(read_u32(&record_header[8..12])).map_err(|_| invalid_data("record too large"))?;
+        let frame = records[PCAP_RECORD_HEADER_SIZE..]
+            .get(..captured_size)
+            .ok_or_else(|| invalid_data("pcap record truncated"))?;
+        records = &records[PCAP_RECORD_HEADER_SIZE + captured_size..];
+
+        let first_timestamp = *first_timestamp.get_or_insert(timestamp);
+        let Some((source, ttl, icmp_message)) = ip_packet(link_type, frame)?.and_then(icmpv4_message) else {
+            continue;
+        };
+        if icmp_message.first() == Some(&ICMP_ECHO_REQUEST) {
+            continue;
+        }
+        packets.push_back(ReplayPacket {
+            offset: timestamp.saturating_sub(first_timestamp),
+            source,
+            ttl,
+            bytes: icmp_message.to_vec(),
+        });
+    }
+    Ok(packets)
+}
+
+// Strips the link-layer header of `frame`. Returns `None` if the frame does not carry IPv4.
+fn ip_packet(link_type: u32, frame: &[u8]) -> io::Result<Option<&[u8]>> {
+    let ethertype_at = |offset: usize| {
+        frame
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let packet = match link_type {
+        LINKTYPE_RAW | LINKTYPE_IPV4 => Some(frame),
+        LINKTYPE_ETHERNET => match ethertype_at(12) {
+            Some(ETHERTYPE_IPV4) => frame.get(14..),
+            Some(ETHERTYPE_VLAN) if ethertype_at(16) == Some(ETHERTYPE_IPV4) => frame.get(18..),
+            _ => None,
+        },
+        LINKTYPE_LINUX_SLL => (ethertype_at(14) == Some(ETHERTYPE_IPV4)).then(|| &frame[16..]),
+        LINKTYPE_LINUX_SLL2 => (ethertype_at(0) == Some(ETHERTYPE_IPV4)).then(|| frame.get(20..)).flatten(),
+        _ => return Err(invalid_data("unsupported pcap link type")),
+    };
+    Ok(packet)
+}
+
+// Returns the source address, TTL and ICMP message of an IPv4 packet, or `None` if the packet is
+// not ICMP or its IP header is malformed. A truncated ICMP message is returned as it is.
+fn icmpv4_message(packet: &[u8]) -> Option<(Ipv4Addr, u8, &[u8])> {
+    let packet = parse_ipv4_packet(packet).ok()?;
+    (packet.protocol == IP_PROTOCOL_ICMP).then_some((packet.source, packet.ttl, packet.payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::details::icmp::v4::icmpv4::new_icmpv4_package;
+    use crate::details::icmp::v4::SequenceNumber;
+    use crate::details::pcap::tests::temp_pcap_path;
+    use crate::details::pcap::{PcapWriter, IPV4_HEADER_SIZE};
+    use crate::PingReceive;
+    use pnet_packet::icmp::echo_reply::EchoReplyPacket;
+    use pnet_packet::icmp::echo_request::EchoRequestPacket;
+    use pnet_packet::Packet;
+
+    const HOST: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+
+    // Captures `n` echo requests to `HOST`, each followed by its reply.
+    fn capture(name: &str, n: u16) -> std::path::PathBuf {
+        let path = temp_pcap_path(name);
+        let pcap_writer = PcapWriter::create(&path, 64).unwrap();
+        for sequence_number in 1..=n {
+            let request = new_icmpv4_package(SequenceNumber::from(sequence_number), &[0xAB; 8]).unwrap();
+            let reply = super::super::echo_reply(&EchoRequestPacket::new(request.packet()).unwrap());
+            pcap_writer.write_outgoing(HOST, request.packet()).unwrap();
+            pcap_writer.write_incoming(HOST, 57, &reply).unwrap();
+        }
+        path
+    }
+
+    fn pcap_header(link_type: u32) -> Vec<u8> {
+        let mut content = Vec::new();
+        content.extend_from_slice(&PCAP_MAGIC.to_be_bytes());
+        content.extend_from_slice(&2u16.to_be_bytes());
+        content.extend_from_slice(&4u16.to_be_bytes());
+        content.extend_from_slice(&[0u8; 8]);
+        content.extend_from_slice(&65535u32.to_be_bytes());
+        content.extend_from_slice(&link_type.to_be_bytes());
+        content
+    }
+
+    fn push_record(content: &mut Vec<u8>, frame: &[u8]) {
+        content.extend_from_slice(&[0u8; 8]);
+        content.extend_from_slice(&u32::try_from(frame.len()).unwrap().to_be_bytes());
+        content.extend_from_slice(&u32::try_from(frame.len()).unwrap().to_be_bytes());
+        content.extend_from_slice(frame);
+    }
+
+    fn ipv4_icmp_packet(source: Ipv4Addr, icmp_message: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 50, IP_PROTOCOL_ICMP, 0, 0];
+        packet[2..4].copy_from_slice(&u16::try_from(IPV4_HEADER_SIZE + icmp_message.len()).unwrap().to_be_bytes());
+        packet.extend_from_slice(&source.octets());
+        packet.extend_from_slice(&[0u8; 4]);
+        packet.extend_from_slice(icmp_message);
+        packet
+    }
+
+    fn receive(socket: &PcapReplaySocket) -> io::Result<(Vec<u8>, IpAddr, u8)> {
+        let mut buf = [0u8; 128];
+        let (n_bytes, ip_addr, ttl) = socket.recv_from(&mut buf)?;
+        Ok((buf[..n_bytes].to_vec(), ip_addr, ttl))
+    }
+
+    #[test]
+    fn replay_feeds_captured_replies_through_receiver() {
+        let path = capture("replay", 2);
+        let socket = PcapReplaySocket::open(&path, ReplayTiming::AsFastAsPossible).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let config = PingFoxConfig { channel_size: 2, ..PingFoxConfig::default() };
+
+        let (ping_sender, mut ping_receiver) = replay(socket, &config);
+        for sequence_number in 1..=2 {
+            let token = ping_sender.send_to(HOST).unwrap();
+            match ping_receiver.receive(token).unwrap() {
+                PingReceive::Data(data) => {
+                    assert_eq!(IpAddr::V4(HOST), data.ip_addr);
+                    assert_eq!(57, data.ttl);
+                    assert_eq!(sequence_number, data.sequence_number);
+                }
+                PingReceive::Timeout | PingReceive::Closed => panic!("expected a reply"),
+            }
+        }
+        let token = ping_sender.send_to(HOST).unwrap();
+        assert!(matches!(ping_receiver.receive(token).unwrap(), PingReceive::Timeout));
+    }
+
+    #[test]
+    fn ethernet_frames_and_truncated_messages_are_replayed() {
+        let reply = [0u8, 0, 0, 0, 0, 0, 0, 7, 0xAB, 0xAB];
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend_from_slice(&ipv4_icmp_packet(HOST, &reply));
+        let mut content = pcap_header(LINKTYPE_ETHERNET);
+        push_record(&mut content, &frame);
+        push_record(&mut content, &frame[..14 + IPV4_HEADER_SIZE + 4]);
+        push_record(&mut content, &frame[..14 + 10]);
+
+        let socket = PcapReplaySocket::from_reader(content.as_slice(), ReplayTiming::AsFastAsPossible).unwrap();
+        let (complete, ip_addr, ttl) = receive(&socket).unwrap();
+        let (truncated, _, _) = receive(&socket).unwrap();
+
+        // The frame with a truncated IP header is skipped.
+        assert_eq!(0, socket.remaining());
+        assert_eq!(IpAddr::V4(HOST), ip_addr);
+        assert_eq!(50, ttl);
+        assert_eq!(7, EchoReplyPacket::new(&complete).unwrap().get_sequence_number());
+        assert_eq!(reply[..4], truncated);
+    }
+
+    #[test]
+    fn original_timing_delays_messages() {
+        let reply = [0u8; 8];
+        let mut content = pcap_header(LINKTYPE_RAW);
+        push_record(&mut content, &ipv4_icmp_packet(HOST, &reply));
+        content.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0x4e, 0x20]); // 20 ms
+        content.extend_from_slice(&28u32.to_be_bytes());
+        content.extend_from_slice(&28u32.to_be_bytes());
+        content.extend_from_slice(&ipv4_icmp_packet(HOST, &reply));
+
+        let socket = PcapReplaySocket::from_reader(content.as_slice(), ReplayTiming::Original).unwrap();
+        let start = Instant::now();
+        receive(&socket).unwrap();
+        receive(&socket).unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn unsupported_link_type_is_rejected() {
+        let mut content = pcap_header(0x1234);
+        push_record(&mut content, &[0u8; 40]);
+
+        let result = PcapReplaySocket::from_reader(content.as_slice(), ReplayTiming::AsFastAsPossible);
+
+        assert_eq!(io::ErrorKind::InvalidData, result.err().unwrap().kind());
+    }
+}
@@ -0,0 +1,76 @@
+/* Exercises the C ABI of ping-fox against localhost. Run by scripts/test_capi.sh. */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "ping_fox.h"
+
+#define CHECK(condition)                                                                 \
+    do {                                                                                 \
+        if (!(condition)) {                                                              \
+            const char *message = ping_fox_last_error_message();                       \
+            fprintf(stderr, "%s:%d: check failed: %s (%s)\n", __FILE__, __LINE__,        \
+                    #condition, message ? message : "no error message");                \
+            exit(1);                                                                     \
+        }                                                                                \
+    } while (0)
+
+static void test_ping_localhost(void) {
+    PingFoxConfig *config = ping_fox_config_new();
+    CHECK(config != NULL);
+    CHECK(ping_fox_config_set_socket_type(config, PING_FOX_SOCKET_TYPE_DGRAM) == PING_FOX_ERROR_OK);
+    CHECK(ping_fox_config_set_timeout_ms(config, 1000) == PING_FOX_ERROR_OK);
+    CHECK(ping_fox_config_set_max_in_flight(config, 3) == PING_FOX_ERROR_OK);
+
+    PingFoxSender *sender = NULL;
+    PingFoxReceiver *receiver = NULL;
+    CHECK(ping_fox_create(config, &sender, &receiver) == PING_FOX_ERROR_OK);
+    ping_fox_config_free(config);
+
+    for (int i = 0; i < 3; i++) {
+        CHECK(ping_fox_send_to(sender, "127.0.0.1") == PING_FOX_ERROR_OK);
+    }
+    for (int i = 0; i < 3; i++) {
+        PingFoxReply reply;
+        CHECK(ping_fox_receive(receiver, &reply) == PING_FOX_ERROR_OK);
+        CHECK(reply.outcome == PING_FOX_OUTCOME_DATA);
+        CHECK(memcmp(reply.address, (uint8_t[]){127, 0, 0, 1}, 4) == 0);
+        printf("reply from 127.0.0.1: icmp_seq=%u ttl=%u time=%llu us\n", (unsigned)reply.sequence_number,
+               (unsigned)reply.ttl, (unsigned long long)reply.rtt_us);
+    }
+
+    CHECK(ping_fox_shutdown(sender) == PING_FOX_ERROR_OK);
+    PingFoxReply reply;
+    CHECK(ping_fox_receive(receiver, &reply) == PING_FOX_ERROR_OK);
+    CHECK(reply.outcome == PING_FOX_OUTCOME_CLOSED);
+    CHECK(ping_fox_send_to(sender, "127.0.0.1") == PING_FOX_ERROR_SHUT_DOWN);
+
+    ping_fox_sender_free(sender);
+    ping_fox_receiver_free(receiver);
+}
+
+static void test_errors(void) {
+    PingFoxSender *sender = NULL;
+    PingFoxReceiver *receiver = NULL;
+    CHECK(ping_fox_create(NULL, &sender, &receiver) == PING_FOX_ERROR_INVALID_ARGUMENT);
+    CHECK(strcmp(ping_fox_last_error_message(), "config is null") == 0);
+
+    PingFoxConfig *config = ping_fox_config_new();
+    CHECK(ping_fox_config_set_max_in_flight(config, 1) == PING_FOX_ERROR_OK);
+    CHECK(ping_fox_create(config, &sender, &receiver) == PING_FOX_ERROR_OK);
+    ping_fox_config_free(config);
+
+    CHECK(ping_fox_send_to(sender, "not an address") == PING_FOX_ERROR_INVALID_ARGUMENT);
+    CHECK(ping_fox_send_to(sender, "127.0.0.1") == PING_FOX_ERROR_OK);
+    CHECK(ping_fox_send_to(sender, "127.0.0.1") == PING_FOX_ERROR_WOULD_EXCEED_IN_FLIGHT);
+
+    ping_fox_sender_free(sender);
+    ping_fox_receiver_free(receiver);
+}
+
+int main(void) {
+    test_ping_localhost();
+    test_errors();
+    printf("all C ABI tests passed\n");
+    return 0;
+}
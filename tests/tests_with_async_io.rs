@@ -0,0 +1,203 @@
+#![cfg(feature = "async-io")]
+use ping_fox::asynchronous::{self, AsyncPingReceiver, AsyncPingSender};
+use ping_fox::{PingFoxConfig, PingReceive, ProbeKind, SocketType, SystemClock, TSocket};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, TcpListener, UdpSocket};
+use std::os::fd::{AsRawFd, RawFd};
+use std::sync::{Arc, Once};
+use std::time::{Duration, Instant};
+
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+static SETUP: Once = Once::new();
+
+fn setup() {
+    SETUP.call_once(|| {
+        let subscriber = FmtSubscriber::builder().with_max_level(Level::ERROR).finish();
+        tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+    });
+}
+
+async fn ping_localhost(ping_sender: &AsyncPingSender, ping_receiver: &mut AsyncPingReceiver) {
+    let localhost = Ipv4Addr::new(127, 0, 0, 1);
+    let token = ping_sender.send_to(localhost).await.unwrap();
+    let PingReceive::Data(data) = ping_receiver.receive(token).await.unwrap() else {
+        panic!("expected data");
+    };
+    assert_eq!(IpAddr::V4(localhost), data.ip_addr);
+}
+
+#[test]
+fn test_ping_to_localhost_with_smol() {
+    setup();
+
+    let config = PingFoxConfig { socket_type: SocketType::DGRAM, ..PingFoxConfig::default() };
+    smol::block_on(async {
+        let (ping_sender, mut ping_receiver) = asynchronous::create(&config).unwrap();
+        ping_localhost(&ping_sender, &mut ping_receiver).await;
+        ping_localhost(&ping_sender, &mut ping_receiver).await;
+    });
+}
+
+#[test]
+fn test_ping_to_localhost_with_futures_block_on() {
+    setup();
+
+    let config = PingFoxConfig { socket_type: SocketType::DGRAM, ..PingFoxConfig::default() };
+    futures::executor::block_on(async {
+        let (ping_sender, mut ping_receiver) = asynchronous::create(&config).unwrap();
+        ping_localhost(&ping_sender, &mut ping_receiver).await;
+    });
+}
+
+// A socket which never receives anything, with a file descriptor to wait on.
+struct SilentSocket(UdpSocket);
+
+impl TSocket for SilentSocket {
+    fn send_to(&self, buf: &[u8], _addr: IpAddr) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, _buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.0.as_raw_fd())
+    }
+}
+
+fn silent_socket() -> SilentSocket {
+    SilentSocket(UdpSocket::bind("127.0.0.1:0").unwrap())
+}
+
+#[test]
+fn test_receive_times_out_with_custom_socket() {
+    setup();
+
+    let timeout = Duration::from_millis(100);
+    let config = PingFoxConfig { timeout, ..PingFoxConfig::default() };
+    smol::block_on(async {
+        let (ping_sender, mut ping_receiver) =
+            asynchronous::create_with_socket(silent_socket(), Arc::new(SystemClock), &config).unwrap();
+        let start = Instant::now();
+        let token = ping_sender.send_to(Ipv4Addr::new(127, 0, 0, 1)).await.unwrap();
+
+        assert!(matches!(ping_receiver.receive(token).await.unwrap(), PingReceive::Timeout));
+        assert!(start.elapsed() >= timeout);
+    });
+}
+
+#[test]
+fn test_shutdown_wakes_up_receive() {
+    setup();
+
+    let config = PingFoxConfig { timeout: Duration::from_secs(30), ..PingFoxConfig::default() };
+    let start = Instant::now();
+    smol::block_on(async {
+        let (ping_sender, mut ping_receiver) =
+            asynchronous::create_with_socket(silent_socket(), Arc::new(SystemClock), &config).unwrap();
+        let token = ping_sender.send_to(Ipv4Addr::new(127, 0, 0, 1)).await.unwrap();
+        let shutdown_handle = ping_sender.shutdown_handle();
+        let shutdown = async {
+            smol::Timer::after(Duration::from_millis(10)).await;
+            shutdown_handle.shutdown();
+        };
+
+        let (outcome, ()) = futures::join!(ping_receiver.receive(token), shutdown);
+
+        assert!(matches!(outcome.unwrap(), PingReceive::Closed));
+    });
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+// A socket which receives the messages sent to it from itself. Without messages, a receive blocks
+// until the socket times out.
+struct LoopbackSocket(UdpSocket);
+
+impl LoopbackSocket {
+    fn new() -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        LoopbackSocket(socket)
+    }
+
+    fn inject(&self, message: &[u8]) {
+        self.0.send_to(message, self.0.local_addr().unwrap()).unwrap();
+    }
+}
+
+impl TSocket for LoopbackSocket {
+    fn send_to(&self, buf: &[u8], _addr: IpAddr) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+        let (n, _) = self.0.recv_from(buf)?;
+        Ok((n, IpAddr::V4(Ipv4Addr::LOCALHOST), 64))
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.0.as_raw_fd())
+    }
+}
+
+#[test]
+fn test_receive_skips_message_without_blocking() {
+    setup();
+
+    let timeout = Duration::from_millis(100);
+    let config = PingFoxConfig { timeout, ..PingFoxConfig::default() };
+    let socket = LoopbackSocket::new();
+    // ICMP port unreachable, which is not a reply to a ping.
+    socket.inject(&[3, 3, 0xFC, 0xFC, 0, 0, 0, 0]);
+    let start = Instant::now();
+    smol::block_on(async {
+        let (ping_sender, mut ping_receiver) =
+            asynchronous::create_with_socket(socket, Arc::new(SystemClock), &config).unwrap();
+        let token = ping_sender.send_to(Ipv4Addr::new(127, 0, 0, 1)).await.unwrap();
+
+        assert!(matches!(ping_receiver.receive(token).await.unwrap(), PingReceive::Timeout));
+    });
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+// A socket without a file descriptor, which an async receiver cannot wait for.
+struct SocketWithoutFd;
+
+impl TSocket for SocketWithoutFd {
+    fn send_to(&self, buf: &[u8], _addr: IpAddr) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, _buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+}
+
+#[test]
+fn test_create_fails_without_file_descriptor() {
+    setup();
+
+    let config = PingFoxConfig::default();
+    assert!(asynchronous::create_with_socket(SocketWithoutFd, Arc::new(SystemClock), &config).is_err());
+}
+
+#[test]
+fn test_tcp_ping_to_localhost() {
+    setup();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let config = PingFoxConfig { probe_kind: ProbeKind::Tcp { port }, ..PingFoxConfig::default() };
+    smol::block_on(async {
+        let (ping_sender, mut ping_receiver) = asynchronous::create(&config).unwrap();
+        let token = ping_sender.send_to(Ipv4Addr::new(127, 0, 0, 1)).await.unwrap();
+
+        match ping_receiver.receive(token).await.unwrap() {
+            PingReceive::Data(data) => assert_eq!(ProbeKind::Tcp { port }, data.probe_kind),
+            outcome => panic!("expected a reply, got {outcome:?}"),
+        }
+    });
+}
@@ -0,0 +1,83 @@
+use ping_fox::{PingFoxConfig, PingReceive, SystemClock, TSocket};
+use std::collections::VecDeque;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex, Once};
+
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+static SETUP: Once = Once::new();
+
+fn setup() {
+    SETUP.call_once(|| {
+        let subscriber = FmtSubscriber::builder().with_max_level(Level::ERROR).finish();
+        tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+    });
+}
+
+// A transport which answers every echo request itself, like a userspace stack would.
+#[derive(Default)]
+struct LoopbackSocket {
+    replies: Mutex<VecDeque<(Vec<u8>, IpAddr)>>,
+}
+
+fn internet_checksum(bytes: &[u8]) -> u16 {
+    let mut sum: u32 = bytes
+        .chunks(2)
+        .map(|chunk| u32::from(u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+impl TSocket for LoopbackSocket {
+    fn send_to(&self, buf: &[u8], addr: IpAddr) -> io::Result<usize> {
+        let mut reply = buf.to_vec();
+        // Echo reply, with the checksum recomputed.
+        reply[0] = 0;
+        reply[2..4].fill(0);
+        let checksum = internet_checksum(&reply);
+        reply[2..4].copy_from_slice(&checksum.to_be_bytes());
+        self.replies.lock().unwrap().push_back((reply, addr));
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, IpAddr, u8)> {
+        let (reply, addr) = self
+            .replies
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::WouldBlock, "no reply"))?;
+        buf[..reply.len()].copy_from_slice(&reply);
+        Ok((reply.len(), addr, 42))
+    }
+}
+
+#[test]
+fn test_ping_with_custom_socket() {
+    setup();
+
+    let ip = Ipv4Addr::new(192, 0, 2, 1);
+    let config = PingFoxConfig { payload_size: 16, channel_size: 2, ..PingFoxConfig::default() };
+
+    let (ping_sender, mut ping_receiver) =
+        ping_fox::create_with_socket(LoopbackSocket::default(), Arc::new(SystemClock), &config);
+    let token_1 = ping_sender.send_to(ip).unwrap();
+    let token_2 = ping_sender.send_to(ip).unwrap();
+
+    for (token, expected_sequence_number) in [(token_1, 1), (token_2, 2)] {
+        match ping_receiver.receive(token).unwrap() {
+            PingReceive::Data(data) => {
+                assert_eq!(IpAddr::V4(ip), data.ip_addr);
+                assert_eq!(42, data.ttl);
+                assert_eq!(8 + 16, data.package_size);
+                assert_eq!(expected_sequence_number, data.sequence_number);
+            }
+            PingReceive::Timeout | PingReceive::Closed => panic!("expected a reply"),
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use ping_fox::{PingFoxConfig, PingReceive, SocketType};
+use ping_fox::{PingFoxConfig, PingReceive, ProbeKind, SocketType};
 use std::net::Ipv4Addr;
 use std::sync::Once;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use more_asserts as ma;
 use tracing::Level;
@@ -23,9 +23,15 @@ fn test_ping_to_localhost_with_dgram_socket() {
     let localhost = Ipv4Addr::new(127, 0, 0, 1);
     let timeout = Duration::from_secs(1);
 
-    let config = PingFoxConfig { timeout, channel_size: 1, socket_type: SocketType::DGRAM };
+    let config = PingFoxConfig {
+        timeout,
+        channel_size: 1,
+        socket_type: SocketType::DGRAM,
+        probe_kind: ProbeKind::Icmp,
+        ..PingFoxConfig::default()
+    };
 
-    let (mut ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
+    let (ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
     let token = ping_sender.send_to(localhost).unwrap();
 
     if let PingReceive::Data(receive_data) = ping_receiver.receive(token).unwrap() {
@@ -36,6 +42,31 @@ fn test_ping_to_localhost_with_dgram_socket() {
     }
 }
 
+#[test]
+fn test_ping_to_localhost_with_payload_and_ttl_with_dgram_socket() {
+    setup();
+
+    let localhost = Ipv4Addr::new(127, 0, 0, 1);
+    let config = PingFoxConfig {
+        payload_size: 1000,
+        payload_pattern: vec![0xAB, 0xCD],
+        ttl: Some(5),
+        interface: Some("127.0.0.1".to_owned()),
+        ..PingFoxConfig::default()
+    };
+
+    let (ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
+    let token = ping_sender.send_to(localhost).unwrap();
+
+    if let PingReceive::Data(receive_data) = ping_receiver.receive(token).unwrap() {
+        assert_eq!(localhost, receive_data.ip_addr);
+        // ICMP header and payload.
+        assert_eq!(8 + 1000, receive_data.package_size);
+    } else {
+        panic!("ping receiver did not return expected data");
+    }
+}
+
 #[test]
 fn test_ping_to_multiple_addresses_on_network_with_dgram_socket() {
     setup();
@@ -46,9 +77,15 @@ fn test_ping_to_multiple_addresses_on_network_with_dgram_socket() {
     let ip_iana_com = Ipv4Addr::new(192, 0, 43, 8);
     let timeout = Duration::from_secs(1);
 
-    let config = PingFoxConfig { timeout, channel_size: 2, socket_type: SocketType::DGRAM };
+    let config = PingFoxConfig {
+        timeout,
+        channel_size: 2,
+        socket_type: SocketType::DGRAM,
+        probe_kind: ProbeKind::Icmp,
+        ..PingFoxConfig::default()
+    };
 
-    let (mut ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
+    let (ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
     let token1 = ping_sender.send_to(ip_example_com).unwrap();
     let token2 = ping_sender.send_to(ip_iana_com).unwrap();
 
@@ -70,3 +107,89 @@ fn test_ping_to_multiple_addresses_on_network_with_dgram_socket() {
         panic!("ping receiver did not return expected data");
     }
 }
+
+#[test]
+fn test_ping_without_reply_returns_timeout_with_dgram_socket() {
+    setup();
+
+    // TEST-NET-2 (RFC 5737) is never assigned. With a TTL of 1 the request does not leave the
+    // first hop, and the resulting ICMP time-exceeded error is not delivered as a reply.
+    let unassigned = Ipv4Addr::new(198, 51, 100, 1);
+    let config = PingFoxConfig { timeout: Duration::from_millis(200), ttl: Some(1), ..PingFoxConfig::default() };
+
+    let (ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
+    let token = ping_sender.send_to(unassigned).unwrap();
+
+    assert!(matches!(ping_receiver.receive(token).unwrap(), PingReceive::Timeout));
+}
+
+#[test]
+fn test_ping_iterator_to_localhost_with_dgram_socket() {
+    setup();
+
+    let mut pings = ping_fox::ping(Ipv4Addr::LOCALHOST)
+        .count(3)
+        .interval(Duration::from_millis(10))
+        .start()
+        .unwrap();
+
+    let outcomes: Vec<PingReceive> = pings.by_ref().collect();
+
+    assert_eq!(3, outcomes.len());
+    assert!(outcomes.iter().all(|outcome| matches!(outcome, PingReceive::Data(_))));
+    let summary = pings.summary();
+    assert_eq!(3, summary.transmitted);
+    assert_eq!(3, summary.received);
+    assert!(summary.min <= summary.avg && summary.avg <= summary.max);
+    assert!(summary.mdev.is_some());
+    assert!(pings.error().is_none());
+}
+
+#[test]
+fn test_ping_iterator_keeps_interval_with_dgram_socket() {
+    setup();
+
+    let interval = Duration::from_millis(50);
+    let start = Instant::now();
+
+    let n = ping_fox::ping(Ipv4Addr::LOCALHOST)
+        .count(3)
+        .interval(interval)
+        .start()
+        .unwrap()
+        .count();
+
+    assert_eq!(3, n);
+    ma::assert_ge!(start.elapsed(), interval * 2);
+}
+
+#[cfg(feature = "capi")]
+#[test]
+fn test_capi_send_rejects_address_which_is_not_ipv4_with_dgram_socket() {
+    use ping_fox::capi::*;
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+
+    setup();
+
+    let config = ping_fox_config_new();
+    let mut sender = ptr::null_mut();
+    let mut receiver = ptr::null_mut();
+    assert_eq!(PingFoxError::Ok, unsafe {
+        ping_fox_create(config, ptr::addr_of_mut!(sender), ptr::addr_of_mut!(receiver))
+    });
+
+    let address = CString::new("::1").unwrap();
+    let error = unsafe { ping_fox_send_to(sender, address.as_ptr()) };
+
+    assert_eq!(PingFoxError::InvalidArgument, error);
+    assert_eq!(
+        "not an IPv4 address: ::1",
+        unsafe { CStr::from_ptr(ping_fox_last_error_message()) }.to_string_lossy()
+    );
+    unsafe {
+        ping_fox_sender_free(sender);
+        ping_fox_receiver_free(receiver);
+        ping_fox_config_free(config);
+    }
+}
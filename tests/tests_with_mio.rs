@@ -0,0 +1,59 @@
+#![cfg(feature = "mio")]
+use mio::{Events, Interest, Poll, Token};
+use ping_fox::{PingFoxConfig, PingReceive, SocketType};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+static SETUP: Once = Once::new();
+
+fn setup() {
+    SETUP.call_once(|| {
+        let subscriber = FmtSubscriber::builder().with_max_level(Level::ERROR).finish();
+        tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+    });
+}
+
+const PING_RECEIVER: Token = Token(0);
+
+#[test]
+fn test_ping_to_localhost_in_mio_event_loop() {
+    setup();
+
+    let localhost = Ipv4Addr::new(127, 0, 0, 1);
+    let config = PingFoxConfig { socket_type: SocketType::DGRAM, channel_size: 3, ..PingFoxConfig::default() };
+    let (ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
+
+    let mut poll = Poll::new().unwrap();
+    poll.registry()
+        .register(&mut ping_receiver, PING_RECEIVER, Interest::READABLE)
+        .unwrap();
+    let mut events = Events::with_capacity(8);
+
+    for _ in 0..3 {
+        ping_sender.send_to(localhost).unwrap();
+    }
+
+    let mut n_replies = 0;
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while n_replies < 3 && Instant::now() < deadline {
+        poll.poll(&mut events, Some(Duration::from_millis(100))).unwrap();
+        for event in &events {
+            assert_eq!(PING_RECEIVER, event.token());
+            // Readiness is edge-triggered, so everything is received at once.
+            while let Some(outcome) = ping_receiver.try_receive().unwrap() {
+                let PingReceive::Data(data) = outcome else {
+                    panic!("expected data");
+                };
+                assert_eq!(IpAddr::V4(localhost), data.ip_addr);
+                n_replies += 1;
+            }
+        }
+    }
+    assert_eq!(3, n_replies);
+
+    poll.registry().deregister(&mut ping_receiver).unwrap();
+}
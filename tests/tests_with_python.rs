@@ -0,0 +1,91 @@
+#![cfg(feature = "python")]
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::ffi::CString;
+use std::sync::Once;
+
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+static SETUP: Once = Once::new();
+
+fn setup() {
+    SETUP.call_once(|| {
+        let subscriber = FmtSubscriber::builder().with_max_level(Level::ERROR).finish();
+        tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+        pyo3::prepare_freethreaded_python();
+    });
+}
+
+// Runs `code` with the module imported as `ping_fox`.
+fn run_python(code: &str) {
+    setup();
+    Python::with_gil(|py| {
+        let module = pyo3::wrap_pymodule!(ping_fox::python::ping_fox)(py);
+        let globals = PyDict::new(py);
+        globals.set_item("ping_fox", module).unwrap();
+        let code = CString::new(code).unwrap();
+        if let Err(error) = py.run(&code, Some(&globals), None) {
+            error.print(py);
+            panic!("Python code failed");
+        }
+    });
+}
+
+#[test]
+fn test_send_and_receive_from_python_with_dgram_socket() {
+    run_python(
+        r"
+sender, receiver = ping_fox.create(timeout=1.0, max_in_flight=2)
+sender.send_to('127.0.0.1')
+reply = receiver.receive()
+assert isinstance(reply, ping_fox.PingData), reply
+assert reply.ip_addr == '127.0.0.1'
+assert reply.rtt > 0
+sender.shutdown()
+assert receiver.receive() == ping_fox.PingClosed()
+",
+    );
+}
+
+#[test]
+fn test_errors_are_python_exceptions_with_dgram_socket() {
+    run_python(
+        r"
+sender, receiver = ping_fox.create()
+try:
+    sender.send_to('::1')
+    raise AssertionError('expected ValueError')
+except ValueError:
+    pass
+sender.send_to('127.0.0.1')
+try:
+    sender.send_to('127.0.0.1')
+    raise AssertionError('expected WouldExceedInFlightError')
+except ping_fox.WouldExceedInFlightError:
+    pass
+",
+    );
+}
+
+#[test]
+fn test_statistics_from_python_with_dgram_socket() {
+    run_python(
+        r"
+summary = ping_fox.ping('127.0.0.1', count=2, interval=0.01)
+assert summary.transmitted == 2 and summary.received == 2, summary
+assert summary.loss == 0.0
+assert summary.min <= summary.avg <= summary.max
+
+metrics = ping_fox.RttMetrics()
+metrics.record(ping_fox.PingTimeout())
+sender, receiver = ping_fox.create()
+sender.send_to('127.0.0.1')
+metrics.record(receiver.receive())
+snapshot = metrics.window(60.0)
+assert snapshot.sent == 2 and snapshot.received == 1, snapshot
+assert snapshot.loss == 0.5
+assert snapshot.quantile(0.5) > 0
+",
+    );
+}
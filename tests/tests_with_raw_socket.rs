@@ -1,4 +1,4 @@
-use ping_fox::{PingFoxConfig, PingReceive, SocketType};
+use ping_fox::{PingFoxConfig, PingReceive, ProbeKind, SocketType};
 use std::time::Duration;
 use std::{net::Ipv4Addr, sync::Once};
 use tracing::Level;
@@ -21,9 +21,15 @@ fn test_ping_to_localhost_with_raw_socket() {
     setup();
 
     let timeout = Duration::from_secs(1);
-    let config = PingFoxConfig { timeout, channel_size: 2, socket_type: SocketType::RAW };
-
-    let (mut ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
+    let config = PingFoxConfig {
+        timeout,
+        channel_size: 2,
+        socket_type: SocketType::RAW,
+        probe_kind: ProbeKind::Icmp,
+        ..PingFoxConfig::default()
+    };
+
+    let (ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
 
     let token = ping_sender.send_to(Ipv4Addr::new(127, 0, 0, 1)).unwrap();
 
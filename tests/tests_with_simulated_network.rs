@@ -0,0 +1,58 @@
+#![cfg(feature = "testing")]
+use ping_fox::testing::{Latency, SimulatedHost, SimulatedNetwork};
+use ping_fox::{PingFoxConfig, PingReceive};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Once;
+use std::time::Duration;
+
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+static SETUP: Once = Once::new();
+
+fn setup() {
+    SETUP.call_once(|| {
+        let subscriber = FmtSubscriber::builder().with_max_level(Level::ERROR).finish();
+        tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+    });
+}
+
+#[test]
+fn test_ping_to_multiple_hosts_with_simulated_network() {
+    setup();
+
+    let alive = Ipv4Addr::new(192, 0, 2, 1);
+    let down = Ipv4Addr::new(192, 0, 2, 2);
+    let network = SimulatedNetwork::new()
+        .with_host(
+            alive,
+            SimulatedHost {
+                latency: Latency::Uniform { min: Duration::from_millis(1), max: Duration::from_millis(3) },
+                ..SimulatedHost::default()
+            },
+        )
+        .with_host(down, SimulatedHost { loss_rate: 1.0, ..SimulatedHost::default() });
+    let config = PingFoxConfig { timeout: Duration::from_millis(50), channel_size: 8, ..PingFoxConfig::default() };
+
+    let (ping_sender, mut ping_receiver) = ping_fox::testing::create(&network, &config);
+    let mut tokens = Vec::new();
+    for _ in 0..3 {
+        tokens.push(ping_sender.send_to(alive).unwrap());
+        tokens.push(ping_sender.send_to(down).unwrap());
+    }
+    let mut n_replies = 0;
+    for token in tokens {
+        match ping_receiver.receive(token).unwrap() {
+            PingReceive::Data(data) => {
+                assert_eq!(IpAddr::V4(alive), data.ip_addr);
+                assert!(data.ping_duration >= Duration::from_millis(1));
+                n_replies += 1;
+            }
+            PingReceive::Timeout => {}
+            PingReceive::Closed => panic!("not shut down"),
+        }
+    }
+
+    assert_eq!(3, n_replies);
+    assert_eq!(3, network.sent_count(down));
+}
@@ -0,0 +1,50 @@
+use ping_fox::{PingFoxConfig, PingReceive, ProbeKind, SocketType};
+use std::net::{Ipv4Addr, TcpListener};
+use std::sync::Once;
+use std::time::Duration;
+
+use more_asserts as ma;
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+static SETUP: Once = Once::new();
+
+fn setup() {
+    SETUP.call_once(|| {
+        let subscriber = FmtSubscriber::builder().with_max_level(Level::ERROR).finish();
+        tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+    });
+}
+
+#[test]
+fn test_tcp_ping_to_localhost() {
+    setup();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let localhost = Ipv4Addr::new(127, 0, 0, 1);
+    let timeout = Duration::from_secs(1);
+
+    // The socket type is not used by a TCP probe.
+    let config = PingFoxConfig {
+        timeout,
+        channel_size: 2,
+        socket_type: SocketType::DGRAM,
+        probe_kind: ProbeKind::Tcp { port },
+        ..PingFoxConfig::default()
+    };
+
+    let (ping_sender, mut ping_receiver) = ping_fox::create(&config).unwrap();
+    let token1 = ping_sender.send_to(localhost).unwrap();
+    let token2 = ping_sender.send_to(localhost).unwrap();
+
+    for token in [token1, token2] {
+        if let PingReceive::Data(receive_data) = ping_receiver.receive(token).unwrap() {
+            assert_eq!(localhost, receive_data.ip_addr);
+            assert_eq!(ProbeKind::Tcp { port }, receive_data.probe_kind);
+            ma::assert_gt!(receive_data.ping_duration, Duration::from_secs(0));
+        } else {
+            panic!("ping receiver did not return expected data");
+        }
+    }
+}